@@ -0,0 +1,481 @@
+//! Indexed, immutable representation of a parsed [`Scxml`] document.
+//!
+//! The parsed tree is flattened into an arena of states and transitions so that
+//! ancestors, the least common compound ancestor (LCCA) and document order can be
+//! computed without recursing through `StateLike` values. The compiled model is
+//! the foundation of the [`runtime`](crate::runtime) and is equally suited to
+//! static analysis and visualisation.
+//!
+//! ```rust
+//! use harel::parse_scxml;
+//! use harel::chart::StateChart;
+//!
+//! let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+//!     <state id="outer">
+//!         <state id="a"><transition event="go" target="b"/></state>
+//!         <state id="b"/>
+//!     </state>
+//! </scxml>"#;
+//!
+//! let scxml = parse_scxml(xml).unwrap();
+//! let chart = StateChart::compile(&scxml).unwrap();
+//! let a = chart.find("a").unwrap();
+//! let b = chart.find("b").unwrap();
+//! assert_eq!(chart.state(a).depth, 2);
+//! assert_eq!(chart.lcca(&[a, b]), chart.find("outer"));
+//!
+//! let go = chart.state(a).transitions[0];
+//! assert_eq!(chart.transition(go).targets, vec![b]);
+//! ```
+
+use std::collections::HashMap;
+
+use crate::{Data, DoneData, EventDescriptor, Executable, Invoke, Scxml, Span, StateLike, Transition, ValidationError};
+
+/// Index of a state in a [`StateChart`].
+///
+/// States are numbered in document order, so comparing two `StateId`s compares
+/// their position in the source document. Entry order is ascending and exit
+/// order is descending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StateId(pub(crate) usize);
+
+impl StateId {
+    /// Returns the position of the state in document order.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Index of a transition in a [`StateChart`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TransitionId(pub(crate) usize);
+
+impl TransitionId {
+    /// Returns the position of the transition in the chart's transition arena.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// The structural kind of a compiled state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateKind {
+    /// A `<state>` without child states.
+    Atomic,
+    /// A `<state>` with child states, or the `<scxml>` root.
+    Compound,
+    /// A `<parallel>` element.
+    Parallel,
+    /// A `<final>` element.
+    Final,
+    /// A `<history>` pseudo-state.
+    History {
+        /// True for `type="deep"`.
+        deep: bool,
+    },
+}
+
+/// A compiled state node.
+#[derive(Debug, Clone)]
+pub struct StateNode {
+    /// The state's `id` attribute, or a generated placeholder for anonymous states.
+    pub id: String,
+    /// The structural kind of the state.
+    pub kind: StateKind,
+    /// The enclosing state, or `None` for the `<scxml>` root.
+    pub parent: Option<StateId>,
+    /// Child states (including history pseudo-states) in document order.
+    pub children: Vec<StateId>,
+    /// Number of ancestors; the root has depth 0.
+    pub depth: usize,
+    /// The initial transition of a compound state, or the default transition of a history state.
+    pub initial: Option<TransitionId>,
+    /// Outgoing transitions in document order.
+    pub transitions: Vec<TransitionId>,
+    /// Executable content on entry.
+    pub onentry: Vec<Executable>,
+    /// Executable content on exit.
+    pub onexit: Vec<Executable>,
+    /// Invoke elements for external processes.
+    pub invokes: Vec<Invoke>,
+    /// Data declared in the state's `<datamodel>`; for the root, the document-level datamodel.
+    pub datamodel: Vec<Data>,
+    /// The `<donedata>` of a final state, sent with the completion event it triggers.
+    pub donedata: Option<DoneData>,
+    /// Position of the element in the source document.
+    pub span: Span,
+}
+
+/// A compiled transition with resolved targets.
+#[derive(Debug, Clone)]
+pub struct TransitionNode {
+    /// The state the transition belongs to.
+    pub source: StateId,
+    /// Event descriptors that trigger the transition.
+    pub event: Option<EventDescriptor>,
+    /// Condition expression that must evaluate to true.
+    pub cond: Option<String>,
+    /// Resolved target states; empty for targetless transitions.
+    pub targets: Vec<StateId>,
+    /// True for `type="internal"` and for synthesized initial transitions.
+    pub internal: bool,
+    /// Executable content within the transition.
+    pub executables: Vec<Executable>,
+    /// Position of the element in the source document; synthesized transitions use their state's span.
+    pub span: Span,
+}
+
+/// Arena of states and transitions compiled from an [`Scxml`] document.
+///
+/// The `<scxml>` element itself is state `0` and is treated as a compound state.
+/// The chart is immutable once compiled.
+#[derive(Debug, Clone)]
+pub struct StateChart {
+    states: Vec<StateNode>,
+    transitions: Vec<TransitionNode>,
+    ids: HashMap<String, StateId>,
+}
+
+impl StateChart {
+    /// Compiles a parsed document, resolving every transition target to a state index.
+    ///
+    /// # Arguments
+    ///
+    /// * `scxml` - The parsed SCXML document.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(StateChart)` - The compiled chart.
+    /// * `Err(ValidationError)` - If a transition or initial target does not exist.
+    pub fn compile(scxml: &Scxml) -> Result<Self, ValidationError> {
+        let mut builder = Builder::default();
+        let root = builder.push_state(None, "scxml".to_string(), StateKind::Compound, scxml.span);
+        for state in &scxml.states {
+            builder.add_state(root, state);
+        }
+        builder.states[root.0].datamodel = scxml.datamodel_elements.clone();
+        builder.add_initial(root, scxml.initial.as_deref(), None);
+        builder.finish()
+    }
+
+    /// Returns the `<scxml>` root state.
+    pub fn root(&self) -> StateId {
+        StateId(0)
+    }
+
+    /// Returns the state with the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was not produced by this chart.
+    pub fn state(&self, id: StateId) -> &StateNode {
+        &self.states[id.0]
+    }
+
+    /// Returns the transition with the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was not produced by this chart.
+    pub fn transition(&self, id: TransitionId) -> &TransitionNode {
+        &self.transitions[id.0]
+    }
+
+    /// Iterates over all states, including the root, in document order.
+    pub fn states(&self) -> impl Iterator<Item = (StateId, &StateNode)> {
+        self.states.iter().enumerate().map(|(i, s)| (StateId(i), s))
+    }
+
+    /// Iterates over all transitions, including synthesized initial transitions.
+    pub fn transitions(&self) -> impl Iterator<Item = (TransitionId, &TransitionNode)> {
+        self.transitions.iter().enumerate().map(|(i, t)| (TransitionId(i), t))
+    }
+
+    /// Looks up a state by its `id` attribute.
+    pub fn find(&self, id: &str) -> Option<StateId> {
+        self.ids.get(id).copied()
+    }
+
+    /// Returns true for atomic and final states.
+    pub fn is_atomic(&self, id: StateId) -> bool {
+        matches!(self.state(id).kind, StateKind::Atomic | StateKind::Final)
+    }
+
+    /// Returns true for compound states and the root.
+    pub fn is_compound(&self, id: StateId) -> bool {
+        self.state(id).kind == StateKind::Compound
+    }
+
+    /// Returns the ancestors of `id`, nearest first, stopping before `upto`.
+    pub fn proper_ancestors(&self, id: StateId, upto: Option<StateId>) -> Vec<StateId> {
+        let mut ancestors = Vec::new();
+        let mut current = self.state(id).parent;
+        while let Some(parent) = current {
+            if Some(parent) == upto {
+                break;
+            }
+            ancestors.push(parent);
+            current = self.state(parent).parent;
+        }
+        ancestors
+    }
+
+    /// Returns true if `id` is a strict descendant of `ancestor`.
+    pub fn is_descendant(&self, id: StateId, ancestor: StateId) -> bool {
+        let mut current = self.state(id).parent;
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            current = self.state(parent).parent;
+        }
+        false
+    }
+
+    /// Returns the child states of `id`, excluding history pseudo-states.
+    pub fn child_states(&self, id: StateId) -> impl Iterator<Item = StateId> + '_ {
+        self.state(id)
+            .children
+            .iter()
+            .copied()
+            .filter(|&child| !matches!(self.state(child).kind, StateKind::History { .. }))
+    }
+
+    /// Finds the least common compound ancestor of a non-empty list of states.
+    ///
+    /// The result is always a proper ancestor of every state in the list; the root
+    /// is returned when no closer compound state encloses them all.
+    pub fn lcca(&self, states: &[StateId]) -> Option<StateId> {
+        let (&head, tail) = states.split_first()?;
+        self.proper_ancestors(head, None)
+            .into_iter()
+            .filter(|&anc| self.is_compound(anc))
+            .find(|&anc| tail.iter().all(|&s| self.is_descendant(s, anc)))
+    }
+}
+
+impl TryFrom<&Scxml> for StateChart {
+    type Error = ValidationError;
+
+    fn try_from(scxml: &Scxml) -> Result<Self, Self::Error> {
+        Self::compile(scxml)
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    states: Vec<StateNode>,
+    transitions: Vec<TransitionNode>,
+    ids: HashMap<String, StateId>,
+    // Raw `target` attributes waiting to be resolved once every state is known.
+    pending: Vec<(TransitionId, String)>,
+}
+
+impl Builder {
+    fn push_state(&mut self, parent: Option<StateId>, id: String, kind: StateKind, span: Span) -> StateId {
+        let index = StateId(self.states.len());
+        let depth = parent.map_or(0, |p| self.states[p.0].depth + 1);
+        self.states.push(StateNode {
+            id,
+            kind,
+            parent,
+            children: Vec::new(),
+            depth,
+            initial: None,
+            transitions: Vec::new(),
+            onentry: Vec::new(),
+            onexit: Vec::new(),
+            invokes: Vec::new(),
+            datamodel: Vec::new(),
+            donedata: None,
+            span,
+        });
+        if let Some(parent) = parent {
+            self.states[parent.0].children.push(index);
+        }
+        index
+    }
+
+    fn push_transition(&mut self, source: StateId, transition: &Transition, internal: bool) -> TransitionId {
+        let index = TransitionId(self.transitions.len());
+        self.transitions.push(TransitionNode {
+            source,
+            event: transition.event.clone(),
+            cond: transition.cond.clone(),
+            targets: Vec::new(),
+            internal: internal || transition.type_.as_deref() == Some("internal"),
+            executables: transition.executables.clone(),
+            span: transition.span,
+        });
+        if let Some(ref target) = transition.target {
+            self.pending.push((index, target.clone()));
+        }
+        index
+    }
+
+    fn add_state(&mut self, parent: StateId, state: &StateLike) -> StateId {
+        let (id, kind, span) = match state {
+            StateLike::State(s) => {
+                let compound = s.children.iter().any(|c| !matches!(c, StateLike::History(_)));
+                (s.id.clone(), if compound { StateKind::Compound } else { StateKind::Atomic }, s.span)
+            }
+            StateLike::Parallel(p) => (p.id.clone(), StateKind::Parallel, p.span),
+            StateLike::Final(f) => (f.id.clone(), StateKind::Final, f.span),
+            StateLike::History(h) => (h.id.clone(), StateKind::History { deep: h.type_ == "deep" }, h.span),
+        };
+        let name = id.clone().unwrap_or_else(|| format!("_state{}", self.states.len()));
+        let index = self.push_state(Some(parent), name, kind, span);
+        if let Some(id) = id {
+            self.ids.entry(id).or_insert(index);
+        }
+
+        match state {
+            StateLike::State(s) => {
+                for child in &s.children {
+                    self.add_state(index, child);
+                }
+                for transition in &s.transitions {
+                    let t = self.push_transition(index, transition, false);
+                    self.states[index.0].transitions.push(t);
+                }
+                let node = &mut self.states[index.0];
+                node.onentry = s.onentry.clone();
+                node.onexit = s.onexit.clone();
+                node.invokes = s.invokes.clone();
+                node.datamodel = s.datamodel.clone();
+                if kind == StateKind::Compound {
+                    self.add_initial(index, s.initial.as_deref(), s.initial_element.as_ref().map(|i| &i.transition));
+                }
+            }
+            StateLike::Parallel(p) => {
+                for child in &p.children {
+                    self.add_state(index, child);
+                }
+                for transition in &p.transitions {
+                    let t = self.push_transition(index, transition, false);
+                    self.states[index.0].transitions.push(t);
+                }
+                let node = &mut self.states[index.0];
+                node.onentry = p.onentry.clone();
+                node.onexit = p.onexit.clone();
+                node.invokes = p.invokes.clone();
+                node.datamodel = p.datamodel.clone();
+            }
+            StateLike::Final(f) => {
+                let node = &mut self.states[index.0];
+                node.onentry = f.onentry.clone();
+                node.onexit = f.onexit.clone();
+                node.donedata = f.donedata.clone();
+            }
+            StateLike::History(h) => {
+                if let Some(ref transition) = h.transition {
+                    let t = self.push_transition(index, transition, false);
+                    self.states[index.0].initial = Some(t);
+                }
+            }
+        }
+        index
+    }
+
+    // Synthesizes the initial transition of a compound state (or the root) from the
+    // `initial` attribute, the `<initial>` element, or the first child in document order.
+    fn add_initial(&mut self, state: StateId, attribute: Option<&str>, element: Option<&Transition>) {
+        let span = self.states[state.0].span;
+        let transition = match (attribute, element) {
+            (Some(target), _) => Transition {
+                event: None,
+                cond: None,
+                target: Some(target.to_string()),
+                type_: None,
+                executables: Vec::new(),
+                span,
+            },
+            (None, Some(transition)) => transition.clone(),
+            (None, None) => {
+                let first = self.states[state.0]
+                    .children
+                    .iter()
+                    .find(|&&c| !matches!(self.states[c.0].kind, StateKind::History { .. }));
+                let Some(&first) = first else { return };
+                let index = self.push_transition(state, &Transition {
+                    event: None,
+                    cond: None,
+                    target: None,
+                    type_: None,
+                    executables: Vec::new(),
+                    span,
+                }, true);
+                self.transitions[index.0].targets.push(first);
+                self.states[state.0].initial = Some(index);
+                return;
+            }
+        };
+        let index = self.push_transition(state, &transition, true);
+        self.states[state.0].initial = Some(index);
+    }
+
+    fn finish(mut self) -> Result<StateChart, ValidationError> {
+        for (transition, target) in std::mem::take(&mut self.pending) {
+            for target_id in target.split_whitespace() {
+                let state = self
+                    .ids
+                    .get(target_id)
+                    .copied()
+                    .ok_or_else(|| ValidationError::InvalidTarget(target_id.to_string(), self.transitions[transition.0].span))?;
+                self.transitions[transition.0].targets.push(state);
+            }
+        }
+        Ok(StateChart { states: self.states, transitions: self.transitions, ids: self.ids })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_scxml;
+
+    #[test]
+    fn test_compile_structure() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="outer">
+                <initial><transition target="b"/></initial>
+                <history id="h" type="deep"><transition target="a"/></history>
+                <state id="a"><transition event="go" target="b c"/></state>
+                <parallel id="b">
+                    <state id="c"/>
+                    <state id="d"/>
+                </parallel>
+            </state>
+            <final id="end"/>
+        </scxml>"#;
+        let chart = StateChart::compile(&parse_scxml(xml).unwrap()).unwrap();
+
+        let ids: Vec<&str> = chart.states().map(|(_, s)| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["scxml", "outer", "h", "a", "b", "c", "d", "end"]);
+
+        let outer = chart.find("outer").unwrap();
+        let c = chart.find("c").unwrap();
+        assert_eq!(chart.state(outer).kind, StateKind::Compound);
+        assert_eq!(chart.state(chart.find("h").unwrap()).kind, StateKind::History { deep: true });
+        assert_eq!(chart.state(c).depth, 3);
+        assert_eq!(chart.proper_ancestors(c, None), vec![chart.find("b").unwrap(), outer, chart.root()]);
+        assert_eq!(chart.child_states(outer).count(), 2);
+
+        let initial = chart.state(outer).initial.unwrap();
+        assert_eq!(chart.transition(initial).targets, vec![chart.find("b").unwrap()]);
+        assert_eq!(chart.lcca(&[c, chart.find("d").unwrap()]), Some(outer));
+        assert_eq!(chart.lcca(&[c, chart.find("end").unwrap()]), Some(chart.root()));
+    }
+
+    #[test]
+    fn test_compile_unknown_target() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="a"><transition event="go" target="missing"/></state>
+        </scxml>"#;
+        let result = StateChart::compile(&parse_scxml(xml).unwrap());
+        let Err(ValidationError::InvalidTarget(id, span)) = result else { panic!("expected InvalidTarget") };
+        assert_eq!(id, "missing");
+        assert_eq!((span.line, span.column), (2, 27));
+    }
+}
@@ -0,0 +1,1574 @@
+//! A pure-Rust interpreter for the subset of ECMAScript that charts commonly use.
+//!
+//! Registered as the `ecmascript` datamodel. It supports:
+//!
+//! - literals: numbers, strings, `true`, `false`, `null`, `undefined`, array and object literals;
+//! - member access (`a.b`, `a[0]`), calls of the built-in functions and methods listed below;
+//! - arithmetic (`+ - * / %`), comparison (`== != === !== < > <= >=`), logical (`&& || !`),
+//!   the conditional operator, `typeof`, assignment (`= += -= *= /= %=`) and `++`/`--`;
+//! - in `<script>`: `var`/`let`/`const`, `if`/`else`, `while`, `for`, `for…in`, `break`, `continue` and blocks.
+//!
+//! Built-in functions are `In()`, `eval()`, `parseInt()`, `parseFloat()`, `isNaN()`, `isFinite()`,
+//! `String()`, `Number()`, `Boolean()`, `JSON.stringify()` and the `Math` functions `abs`, `ceil`,
+//! `floor`, `round`, `min`, `max`, `pow` and `sqrt`. Strings, arrays and numbers have the usual
+//! methods (`substr`, `indexOf`, `lastIndexOf`, `split`, `join`, `push`, `toFixed`, ...).
+//!
+//! Functions cannot be defined, and objects and arrays are values rather than references:
+//! `b = a; b.x = 1` leaves `a` unchanged. Loops stop with an error after
+//! [`MAX_LOOP_ITERATIONS`] iterations so a runaway script cannot hang the interpreter.
+
+use std::collections::BTreeMap;
+
+use crate::Data;
+use crate::datamodel::{Context, Datamodel, DatamodelError, SystemVariables, Value};
+use crate::runtime::{Event, EventType};
+
+/// Number of iterations after which a `<script>` loop is aborted with an error.
+pub const MAX_LOOP_ITERATIONS: usize = 100_000;
+
+// System variables the chart may read but not assign.
+const READ_ONLY: &[&str] = &["_event", "_sessionid", "_name", "_ioprocessors", "_x"];
+
+// Arrays are stored densely, so an assignment may not grow one past this length.
+const MAX_ARRAY_LENGTH: usize = 1 << 20;
+
+/// The ECMAScript datamodel.
+///
+/// # Examples
+///
+/// ```rust
+/// use harel::parse_scxml;
+/// use harel::datamodel::Value;
+/// use harel::runtime::{Event, Interpreter};
+///
+/// let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+///     <datamodel><data id="digits" expr="''"/></datamodel>
+///     <state id="entry">
+///         <transition event="DIGIT" cond="digits.length &lt; 3">
+///             <assign location="digits" expr="digits + _event.name.substr(_event.name.lastIndexOf('.') + 1)"/>
+///         </transition>
+///     </state>
+/// </scxml>"#;
+///
+/// let scxml = parse_scxml(xml).unwrap();
+/// let mut interpreter = Interpreter::new(&scxml).unwrap();
+/// interpreter.start().unwrap();
+/// for digit in ["DIGIT.4", "DIGIT.2", "DIGIT.7", "DIGIT.1"] {
+///     interpreter.send(Event::new(digit)).unwrap();
+/// }
+/// assert_eq!(interpreter.evaluate("digits").unwrap(), Value::from("427"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EcmaScriptDatamodel {
+    vars: BTreeMap<String, Js>,
+}
+
+impl EcmaScriptDatamodel {
+    /// Creates an empty datamodel.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of a global variable, if it is declared.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.vars.get(name).map(|value| value.to_value())
+    }
+
+    // Helper to parse and evaluate a single expression.
+    fn eval_str(&mut self, expr: &str, ctx: &Context) -> Result<Js, DatamodelError> {
+        let expr = Parser::new(expr)?.parse_expression_only()?;
+        Evaluator { vars: &mut self.vars, ctx, completion: Js::Undefined }.eval(&expr)
+    }
+
+    // Helper to check that a location names a declared, writable variable.
+    fn check_location(&self, location: &Expr) -> Result<(), DatamodelError> {
+        let root = location.root().ok_or_else(|| DatamodelError::InvalidLocation("not an assignable expression".to_string()))?;
+        if READ_ONLY.contains(&root) {
+            return Err(DatamodelError::InvalidLocation(format!("{} is read-only", root)));
+        }
+        if !self.vars.contains_key(root) {
+            return Err(DatamodelError::InvalidLocation(format!("{} is not declared", root)));
+        }
+        Ok(())
+    }
+}
+
+impl Datamodel for EcmaScriptDatamodel {
+    fn init(&mut self, system: &SystemVariables) -> Result<(), DatamodelError> {
+        self.vars.insert("_sessionid".to_string(), Js::String(system.sessionid.clone()));
+        self.vars.insert("_name".to_string(), system.name.clone().map_or(Js::Undefined, Js::String));
+        let ioprocessors = system
+            .ioprocessors
+            .iter()
+            .map(|(kind, location)| {
+                let entry = BTreeMap::from([("location".to_string(), Js::String(location.clone()))]);
+                (kind.clone(), Js::Object(entry))
+            })
+            .collect();
+        self.vars.insert("_ioprocessors".to_string(), Js::Object(ioprocessors));
+        self.vars.insert("_event".to_string(), Js::Undefined);
+        Ok(())
+    }
+
+    fn init_data(&mut self, data: &Data, ctx: &Context) -> Result<(), DatamodelError> {
+        // The variable exists even if its initial value cannot be computed.
+        self.vars.insert(data.id.clone(), Js::Undefined);
+        let value = match (&data.expr, &data.content, &data.src) {
+            (Some(expr), _, _) => self.eval_str(expr, ctx)?,
+            (None, Some(content), _) if !content.trim().is_empty() => {
+                // Inline content is used as a literal when it parses as one, and as text otherwise.
+                self.eval_str(content, ctx).unwrap_or_else(|_| Js::String(content.trim().to_string()))
+            }
+            (None, _, Some(src)) => return Err(DatamodelError::Unsupported(format!("<data src=\"{}\">", src))),
+            _ => Js::Undefined,
+        };
+        self.vars.insert(data.id.clone(), value);
+        Ok(())
+    }
+
+    fn set_event(&mut self, event: &Event) {
+        let optional = |field: &Option<String>| field.clone().map_or(Js::Undefined, Js::String);
+        let type_ = match event.type_ {
+            EventType::Platform => "platform",
+            EventType::Internal => "internal",
+            EventType::External => "external",
+        };
+        let fields = BTreeMap::from([
+            ("name".to_string(), Js::String(event.name.clone())),
+            ("type".to_string(), Js::String(type_.to_string())),
+            ("sendid".to_string(), optional(&event.sendid)),
+            ("origin".to_string(), optional(&event.origin)),
+            ("origintype".to_string(), optional(&event.origintype)),
+            ("invokeid".to_string(), optional(&event.invokeid)),
+            ("data".to_string(), Js::from_value(&event.data)),
+        ]);
+        self.vars.insert("_event".to_string(), Js::Object(fields));
+    }
+
+    fn evaluate(&mut self, expr: &str, ctx: &Context) -> Result<Value, DatamodelError> {
+        Ok(self.eval_str(expr, ctx)?.to_value())
+    }
+
+    fn evaluate_bool(&mut self, expr: &str, ctx: &Context) -> Result<bool, DatamodelError> {
+        Ok(self.eval_str(expr, ctx)?.truthy())
+    }
+
+    fn assign(&mut self, location: &str, value: Value, ctx: &Context) -> Result<(), DatamodelError> {
+        let target = Parser::new(location)?.parse_expression_only()?;
+        self.check_location(&target)?;
+        Evaluator { vars: &mut self.vars, ctx, completion: Js::Undefined }.store(&target, Js::from_value(&value))
+    }
+
+    fn declare(&mut self, location: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        // Only a plain variable name can be declared; member locations must already exist.
+        if let Expr::Ident(name) = Parser::new(location)?.parse_expression_only()?
+            && !READ_ONLY.contains(&name.as_str())
+        {
+            self.vars.entry(name).or_insert(Js::Undefined);
+        }
+        Ok(())
+    }
+
+    fn execute_assign(&mut self, location: &str, expr: &str, ctx: &Context) -> Result<(), DatamodelError> {
+        let target = Parser::new(location)?.parse_expression_only()?;
+        self.check_location(&target)?;
+        let value = self.eval_str(expr, ctx)?;
+        Evaluator { vars: &mut self.vars, ctx, completion: Js::Undefined }.store(&target, value)
+    }
+
+    fn run_script(&mut self, script: &str, ctx: &Context) -> Result<(), DatamodelError> {
+        let program = Parser::new(script)?.parse_program()?;
+        let mut evaluator = Evaluator { vars: &mut self.vars, ctx, completion: Js::Undefined };
+        for statement in &program {
+            evaluator.exec(statement)?;
+        }
+        Ok(())
+    }
+}
+
+// A script value. Unlike `Value` it distinguishes `undefined` from `null`.
+#[derive(Debug, Clone, PartialEq)]
+enum Js {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Js>),
+    Object(BTreeMap<String, Js>),
+}
+
+impl Js {
+    fn from_value(value: &Value) -> Js {
+        match value {
+            Value::Null => Js::Null,
+            Value::Bool(b) => Js::Bool(*b),
+            Value::Number(n) => Js::Number(*n),
+            Value::String(s) => Js::String(s.clone()),
+            Value::Array(items) => Js::Array(items.iter().map(Js::from_value).collect()),
+            Value::Object(fields) => Js::Object(fields.iter().map(|(k, v)| (k.clone(), Js::from_value(v))).collect()),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Js::Undefined | Js::Null => Value::Null,
+            Js::Bool(b) => Value::Bool(*b),
+            Js::Number(n) => Value::Number(*n),
+            Js::String(s) => Value::String(s.clone()),
+            Js::Array(items) => Value::Array(items.iter().map(Js::to_value).collect()),
+            Js::Object(fields) => Value::Object(fields.iter().map(|(k, v)| (k.clone(), v.to_value())).collect()),
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Js::Undefined | Js::Null => false,
+            Js::Bool(b) => *b,
+            Js::Number(n) => *n != 0.0 && !n.is_nan(),
+            Js::String(s) => !s.is_empty(),
+            Js::Array(_) | Js::Object(_) => true,
+        }
+    }
+
+    fn to_number(&self) -> f64 {
+        match self {
+            Js::Undefined => f64::NAN,
+            Js::Null => 0.0,
+            Js::Bool(b) => f64::from(u8::from(*b)),
+            Js::Number(n) => *n,
+            Js::String(s) => string_to_number(s),
+            Js::Array(_) | Js::Object(_) => string_to_number(&self.to_js_string()),
+        }
+    }
+
+    fn to_js_string(&self) -> String {
+        match self {
+            Js::Undefined => "undefined".to_string(),
+            Js::Null => "null".to_string(),
+            Js::Bool(b) => b.to_string(),
+            Js::Number(n) => number_to_string(*n),
+            Js::String(s) => s.clone(),
+            Js::Array(items) => items
+                .iter()
+                .map(|item| if matches!(item, Js::Undefined | Js::Null) { String::new() } else { item.to_js_string() })
+                .collect::<Vec<_>>()
+                .join(","),
+            Js::Object(_) => "[object Object]".to_string(),
+        }
+    }
+
+    // Converts arrays and objects to strings, as the `+` and comparison operators do.
+    fn to_primitive(&self) -> Js {
+        match self {
+            Js::Array(_) | Js::Object(_) => Js::String(self.to_js_string()),
+            other => other.clone(),
+        }
+    }
+
+    fn type_of(&self) -> &'static str {
+        match self {
+            Js::Undefined => "undefined",
+            Js::Bool(_) => "boolean",
+            Js::Number(_) => "number",
+            Js::String(_) => "string",
+            Js::Null | Js::Array(_) | Js::Object(_) => "object",
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Js::Undefined | Js::Null => "null".to_string(),
+            Js::Number(n) if !n.is_finite() => "null".to_string(),
+            Js::String(s) => quote_json(s),
+            Js::Array(items) => format!("[{}]", items.iter().map(Js::to_json).collect::<Vec<_>>().join(",")),
+            Js::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .filter(|(_, v)| !matches!(v, Js::Undefined))
+                    .map(|(k, v)| format!("{}:{}", quote_json(k), v.to_json()))
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+            other => other.to_js_string(),
+        }
+    }
+}
+
+// Helper to format a number the way ECMAScript's ToString does.
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        "0".to_string()
+    } else if n.abs() >= 1e21 || n.abs() < 1e-6 {
+        let formatted = format!("{:e}", n);
+        match formatted.split_once('e') {
+            Some((mantissa, exponent)) if !exponent.starts_with('-') => format!("{}e+{}", mantissa, exponent),
+            _ => formatted,
+        }
+    } else {
+        format!("{}", n)
+    }
+}
+
+// Helper to convert a string to a number the way ECMAScript's ToNumber does.
+fn string_to_number(s: &str) -> f64 {
+    let s = s.trim();
+    if s.is_empty() {
+        return 0.0;
+    }
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).map_or(f64::NAN, |n| n as f64);
+    }
+    match s.trim_start_matches(['+', '-']) {
+        "Infinity" => return if s.starts_with('-') { f64::NEG_INFINITY } else { f64::INFINITY },
+        rest if rest.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')) => {}
+        _ => return f64::NAN,
+    }
+    s.parse().unwrap_or(f64::NAN)
+}
+
+// Helper to quote a string as a JSON string literal.
+fn quote_json(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn loose_equals(a: &Js, b: &Js) -> bool {
+    match (a, b) {
+        (Js::Undefined | Js::Null, Js::Undefined | Js::Null) => true,
+        (Js::Undefined | Js::Null, _) | (_, Js::Undefined | Js::Null) => false,
+        (Js::Number(_), Js::String(_)) | (Js::String(_), Js::Number(_)) | (Js::Bool(_), _) | (_, Js::Bool(_)) => {
+            if matches!((a, b), (Js::Bool(_), Js::Bool(_))) {
+                return a == b;
+            }
+            a.to_number() == b.to_number()
+        }
+        (Js::Array(_) | Js::Object(_), Js::Number(_) | Js::String(_)) => loose_equals(&a.to_primitive(), b),
+        (Js::Number(_) | Js::String(_), Js::Array(_) | Js::Object(_)) => loose_equals(a, &b.to_primitive()),
+        _ => strict_equals(a, b),
+    }
+}
+
+fn strict_equals(a: &Js, b: &Js) -> bool {
+    match (a, b) {
+        (Js::Number(x), Js::Number(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+// Returns None when either operand is NaN, mirroring ECMAScript's undefined comparison result.
+fn compare(a: &Js, b: &Js) -> Option<std::cmp::Ordering> {
+    match (a.to_primitive(), b.to_primitive()) {
+        (Js::String(x), Js::String(y)) => Some(x.cmp(&y)),
+        (x, y) => x.to_number().partial_cmp(&y.to_number()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Ident(String),
+    Punct(&'static str),
+    End,
+}
+
+// Longest punctuators first so that `===` is not read as `==` followed by `=`.
+const PUNCTUATORS: &[&str] = &[
+    "===", "!==", "==", "!=", "<=", ">=", "&&", "||", "++", "--", "+=", "-=", "*=", "/=", "%=", "<", ">", "+", "-", "*",
+    "/", "%", "!", "=", "?", ":", "(", ")", "[", "]", "{", "}", ".", ",", ";",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, DatamodelError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let end = (i + 2..chars.len().saturating_sub(1)).find(|&j| chars[j] == '*' && chars[j + 1] == '/');
+            i = end.ok_or_else(|| DatamodelError::Syntax("unterminated comment".to_string()))? + 2;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            if c == '0' && matches!(chars.get(i + 1), Some('x' | 'X')) {
+                i += 2;
+                while i < chars.len() && chars[i].is_ascii_hexdigit() {
+                    i += 1;
+                }
+                let digits: String = chars[start + 2..i].iter().collect();
+                let n = u64::from_str_radix(&digits, 16).map_err(|_| DatamodelError::Syntax(format!("invalid number 0x{}", digits)))?;
+                tokens.push(Token::Number(n as f64));
+                continue;
+            }
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                i += 1;
+                if i < chars.len() && matches!(chars[i], '+' | '-') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text.parse().map_err(|_| DatamodelError::Syntax(format!("invalid number {}", text)))?;
+            tokens.push(Token::Number(n));
+        } else if c == '\'' || c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                let Some(&ch) = chars.get(i) else {
+                    return Err(DatamodelError::Syntax("unterminated string".to_string()));
+                };
+                i += 1;
+                if ch == c {
+                    break;
+                }
+                if ch != '\\' {
+                    text.push(ch);
+                    continue;
+                }
+                let Some(&escaped) = chars.get(i) else {
+                    return Err(DatamodelError::Syntax("unterminated string".to_string()));
+                };
+                i += 1;
+                match escaped {
+                    'n' => text.push('\n'),
+                    't' => text.push('\t'),
+                    'r' => text.push('\r'),
+                    'b' => text.push('\u{8}'),
+                    'f' => text.push('\u{c}'),
+                    'v' => text.push('\u{b}'),
+                    '0' => text.push('\0'),
+                    'u' => {
+                        let hex: String = chars.get(i..i + 4).map(|h| h.iter().collect()).unwrap_or_default();
+                        let code = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        text.push(code.ok_or_else(|| DatamodelError::Syntax(format!("invalid escape \\u{}", hex)))?);
+                        i += 4;
+                    }
+                    other => text.push(other),
+                }
+            }
+            tokens.push(Token::String(text));
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+            let punct = PUNCTUATORS
+                .iter()
+                .find(|p| rest.starts_with(**p))
+                .ok_or_else(|| DatamodelError::Syntax(format!("unexpected character '{}'", c)))?;
+            tokens.push(Token::Punct(punct));
+            i += punct.len();
+        }
+    }
+    tokens.push(Token::End);
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Js),
+    Ident(String),
+    Array(Vec<Expr>),
+    Object(Vec<(String, Expr)>),
+    Member(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Update { op: &'static str, prefix: bool, target: Box<Expr> },
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Assign(&'static str, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // The variable an assignable expression ultimately writes to.
+    fn root(&self) -> Option<&str> {
+        match self {
+            Expr::Ident(name) => Some(name),
+            Expr::Member(object, _) => object.root(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Var(Vec<(String, Option<Expr>)>),
+    Expr(Expr),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    For(Option<Box<Stmt>>, Option<Expr>, Option<Expr>, Box<Stmt>),
+    ForIn(String, Expr, Box<Stmt>),
+    Block(Vec<Stmt>),
+    Break,
+    Continue,
+    Empty,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, DatamodelError> {
+        Ok(Self { tokens: tokenize(source)?, pos: 0 })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(name) if name == keyword)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), DatamodelError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", punct)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> DatamodelError {
+        let found = match self.peek() {
+            Token::Number(n) => number_to_string(*n),
+            Token::String(s) => format!("'{}'", s),
+            Token::Ident(name) => name.clone(),
+            Token::Punct(p) => p.to_string(),
+            Token::End => "end of input".to_string(),
+        };
+        DatamodelError::Syntax(format!("expected {}, found {}", expected, found))
+    }
+
+    fn ident(&mut self) -> Result<String, DatamodelError> {
+        match self.next() {
+            Token::Ident(name) => Ok(name),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("an identifier"))
+            }
+        }
+    }
+
+    fn parse_expression_only(mut self) -> Result<Expr, DatamodelError> {
+        let expr = self.expression()?;
+        if *self.peek() != Token::End {
+            return Err(self.unexpected("end of expression"));
+        }
+        Ok(expr)
+    }
+
+    fn parse_program(mut self) -> Result<Vec<Stmt>, DatamodelError> {
+        let mut statements = Vec::new();
+        while *self.peek() != Token::End {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, DatamodelError> {
+        if self.eat(";") {
+            return Ok(Stmt::Empty);
+        }
+        if self.eat("{") {
+            let mut body = Vec::new();
+            while !self.eat("}") {
+                if *self.peek() == Token::End {
+                    return Err(self.unexpected("'}'"));
+                }
+                body.push(self.statement()?);
+            }
+            return Ok(Stmt::Block(body));
+        }
+        let Token::Ident(keyword) = self.peek().clone() else {
+            return self.expression_statement();
+        };
+        match keyword.as_str() {
+            "var" | "let" | "const" => {
+                let declaration = self.declaration()?;
+                self.eat(";");
+                Ok(declaration)
+            }
+            "if" => {
+                self.next();
+                self.expect("(")?;
+                let cond = self.expression()?;
+                self.expect(")")?;
+                let then = Box::new(self.statement()?);
+                let otherwise = if self.is_keyword("else") {
+                    self.next();
+                    Some(Box::new(self.statement()?))
+                } else {
+                    None
+                };
+                Ok(Stmt::If(cond, then, otherwise))
+            }
+            "while" => {
+                self.next();
+                self.expect("(")?;
+                let cond = self.expression()?;
+                self.expect(")")?;
+                Ok(Stmt::While(cond, Box::new(self.statement()?)))
+            }
+            "for" => self.for_statement(),
+            "break" | "continue" => {
+                self.next();
+                self.eat(";");
+                Ok(if keyword == "break" { Stmt::Break } else { Stmt::Continue })
+            }
+            "function" | "return" | "new" | "class" | "switch" | "do" | "try" | "throw" => {
+                Err(DatamodelError::Unsupported(format!("'{}' statements", keyword)))
+            }
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, DatamodelError> {
+        let expr = self.expression()?;
+        if !self.eat(";") && !self.is_punct("}") && *self.peek() != Token::End {
+            // Statements on separate lines without semicolons are accepted as is.
+            if matches!(self.peek(), Token::Punct(_)) {
+                return Err(self.unexpected("';'"));
+            }
+        }
+        Ok(Stmt::Expr(expr))
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, DatamodelError> {
+        self.next();
+        let mut declarations = Vec::new();
+        loop {
+            let name = self.ident()?;
+            let init = if self.eat("=") { Some(self.assignment()?) } else { None };
+            declarations.push((name, init));
+            if !self.eat(",") {
+                break;
+            }
+        }
+        Ok(Stmt::Var(declarations))
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, DatamodelError> {
+        self.next();
+        self.expect("(")?;
+        // `for (x in obj)` and `for (var x in obj)`.
+        let start = self.pos;
+        if matches!(self.peek(), Token::Ident(k) if k == "var" || k == "let" || k == "const") {
+            self.next();
+        }
+        if let Token::Ident(name) = self.peek().clone() {
+            self.next();
+            if self.is_keyword("in") {
+                self.next();
+                let object = self.expression()?;
+                self.expect(")")?;
+                return Ok(Stmt::ForIn(name, object, Box::new(self.statement()?)));
+            }
+        }
+        self.pos = start;
+        let init = if self.eat(";") {
+            None
+        } else {
+            let init = if matches!(self.peek(), Token::Ident(k) if k == "var" || k == "let" || k == "const") {
+                self.declaration()?
+            } else {
+                Stmt::Expr(self.expression()?)
+            };
+            self.expect(";")?;
+            Some(Box::new(init))
+        };
+        let cond = if self.is_punct(";") { None } else { Some(self.expression()?) };
+        self.expect(";")?;
+        let update = if self.is_punct(")") { None } else { Some(self.expression()?) };
+        self.expect(")")?;
+        Ok(Stmt::For(init, cond, update, Box::new(self.statement()?)))
+    }
+
+    fn expression(&mut self) -> Result<Expr, DatamodelError> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, DatamodelError> {
+        let target = self.conditional()?;
+        for op in ["=", "+=", "-=", "*=", "/=", "%="] {
+            if self.is_punct(op) {
+                if target.root().is_none() {
+                    return Err(DatamodelError::Syntax("invalid assignment target".to_string()));
+                }
+                self.next();
+                let value = self.assignment()?;
+                let op = PUNCTUATORS.iter().find(|p| **p == op).copied().unwrap_or("=");
+                return Ok(Expr::Assign(op, Box::new(target), Box::new(value)));
+            }
+        }
+        Ok(target)
+    }
+
+    fn conditional(&mut self) -> Result<Expr, DatamodelError> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let then = self.assignment()?;
+        self.expect(":")?;
+        let otherwise = self.assignment()?;
+        Ok(Expr::Conditional(Box::new(cond), Box::new(then), Box::new(otherwise)))
+    }
+
+    // Binary operators by increasing precedence.
+    const LEVELS: &'static [&'static [&'static str]] = &[
+        &["||"],
+        &["&&"],
+        &["==", "!=", "===", "!=="],
+        &["<", ">", "<=", ">="],
+        &["+", "-"],
+        &["*", "/", "%"],
+    ];
+
+    fn binary(&mut self, level: usize) -> Result<Expr, DatamodelError> {
+        if level == Self::LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Token::Punct(op) = *self.peek() {
+            if !Self::LEVELS[level].contains(&op) {
+                break;
+            }
+            self.next();
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, DatamodelError> {
+        if self.is_keyword("typeof") {
+            self.next();
+            return Ok(Expr::Unary("typeof", Box::new(self.unary()?)));
+        }
+        if let Token::Punct(op @ ("!" | "-" | "+")) = *self.peek() {
+            self.next();
+            return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+        }
+        if let Token::Punct(op @ ("++" | "--")) = *self.peek() {
+            self.next();
+            let target = self.unary()?;
+            if target.root().is_none() {
+                return Err(DatamodelError::Syntax("invalid update target".to_string()));
+            }
+            return Ok(Expr::Update { op, prefix: true, target: Box::new(target) });
+        }
+        let expr = self.postfix()?;
+        if let Token::Punct(op @ ("++" | "--")) = *self.peek()
+            && expr.root().is_some()
+        {
+            self.next();
+            return Ok(Expr::Update { op, prefix: false, target: Box::new(expr) });
+        }
+        Ok(expr)
+    }
+
+    fn postfix(&mut self) -> Result<Expr, DatamodelError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat(".") {
+                let name = self.ident()?;
+                expr = Expr::Member(Box::new(expr), Box::new(Expr::Literal(Js::String(name))));
+            } else if self.eat("[") {
+                let key = self.expression()?;
+                self.expect("]")?;
+                expr = Expr::Member(Box::new(expr), Box::new(key));
+            } else if self.eat("(") {
+                let mut args = Vec::new();
+                while !self.eat(")") {
+                    args.push(self.assignment()?);
+                    if !self.eat(",") {
+                        self.expect(")")?;
+                        break;
+                    }
+                }
+                expr = Expr::Call(Box::new(expr), args);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, DatamodelError> {
+        match self.next() {
+            Token::Number(n) => Ok(Expr::Literal(Js::Number(n))),
+            Token::String(s) => Ok(Expr::Literal(Js::String(s))),
+            Token::Ident(name) => Ok(match name.as_str() {
+                "true" => Expr::Literal(Js::Bool(true)),
+                "false" => Expr::Literal(Js::Bool(false)),
+                "null" => Expr::Literal(Js::Null),
+                "undefined" => Expr::Literal(Js::Undefined),
+                "NaN" => Expr::Literal(Js::Number(f64::NAN)),
+                "Infinity" => Expr::Literal(Js::Number(f64::INFINITY)),
+                _ => Expr::Ident(name),
+            }),
+            Token::Punct("(") => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[") => {
+                let mut items = Vec::new();
+                while !self.eat("]") {
+                    items.push(self.assignment()?);
+                    if !self.eat(",") {
+                        self.expect("]")?;
+                        break;
+                    }
+                }
+                Ok(Expr::Array(items))
+            }
+            Token::Punct("{") => {
+                let mut fields = Vec::new();
+                while !self.eat("}") {
+                    let key = match self.next() {
+                        Token::Ident(name) | Token::String(name) => name,
+                        Token::Number(n) => number_to_string(n),
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.unexpected("a property name"));
+                        }
+                    };
+                    self.expect(":")?;
+                    fields.push((key, self.assignment()?));
+                    if !self.eat(",") {
+                        self.expect("}")?;
+                        break;
+                    }
+                }
+                Ok(Expr::Object(fields))
+            }
+            _ => {
+                self.pos = self.pos.saturating_sub(1);
+                Err(self.unexpected("an expression"))
+            }
+        }
+    }
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+}
+
+struct Evaluator<'a, 'c> {
+    vars: &'a mut BTreeMap<String, Js>,
+    ctx: &'a Context<'c>,
+    // Value of the last expression statement, returned by `eval()`.
+    completion: Js,
+}
+
+fn type_error(message: impl Into<String>) -> DatamodelError {
+    DatamodelError::Evaluation(message.into())
+}
+
+impl Evaluator<'_, '_> {
+    fn exec(&mut self, statement: &Stmt) -> Result<Flow, DatamodelError> {
+        match statement {
+            Stmt::Empty => {}
+            Stmt::Var(declarations) => {
+                for (name, init) in declarations {
+                    if READ_ONLY.contains(&name.as_str()) {
+                        return Err(DatamodelError::InvalidLocation(format!("{} is read-only", name)));
+                    }
+                    match init {
+                        Some(init) => {
+                            let value = self.eval(init)?;
+                            self.vars.insert(name.clone(), value);
+                        }
+                        None => {
+                            self.vars.entry(name.clone()).or_insert(Js::Undefined);
+                        }
+                    }
+                }
+            }
+            Stmt::Expr(expr) => self.completion = self.eval(expr)?,
+            Stmt::If(cond, then, otherwise) => {
+                if self.eval(cond)?.truthy() {
+                    return self.exec(then);
+                } else if let Some(otherwise) = otherwise {
+                    return self.exec(otherwise);
+                }
+            }
+            Stmt::Block(body) => {
+                for statement in body {
+                    match self.exec(statement)? {
+                        Flow::Normal => {}
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            Stmt::While(cond, body) => {
+                let mut iterations = 0;
+                while self.eval(cond)?.truthy() {
+                    count_iteration(&mut iterations)?;
+                    if let Flow::Break = self.exec(body)? {
+                        break;
+                    }
+                }
+            }
+            Stmt::For(init, cond, update, body) => {
+                if let Some(init) = init {
+                    self.exec(init)?;
+                }
+                let mut iterations = 0;
+                loop {
+                    if let Some(cond) = cond
+                        && !self.eval(cond)?.truthy()
+                    {
+                        break;
+                    }
+                    count_iteration(&mut iterations)?;
+                    if let Flow::Break = self.exec(body)? {
+                        break;
+                    }
+                    if let Some(update) = update {
+                        self.eval(update)?;
+                    }
+                }
+            }
+            Stmt::ForIn(name, object, body) => {
+                let keys: Vec<String> = match self.eval(object)? {
+                    Js::Object(fields) => fields.keys().cloned().collect(),
+                    Js::Array(items) => (0..items.len()).map(|i| i.to_string()).collect(),
+                    Js::String(s) => (0..s.chars().count()).map(|i| i.to_string()).collect(),
+                    _ => Vec::new(),
+                };
+                for key in keys {
+                    self.store(&Expr::Ident(name.clone()), Js::String(key))?;
+                    if let Flow::Break = self.exec(body)? {
+                        break;
+                    }
+                }
+            }
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Js, DatamodelError> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Ident(name) => self.vars.get(name).cloned().ok_or_else(|| type_error(format!("{} is not defined", name))),
+            Expr::Array(items) => Ok(Js::Array(items.iter().map(|item| self.eval(item)).collect::<Result<_, _>>()?)),
+            Expr::Object(fields) => {
+                let mut object = BTreeMap::new();
+                for (key, value) in fields {
+                    object.insert(key.clone(), self.eval(value)?);
+                }
+                Ok(Js::Object(object))
+            }
+            Expr::Member(object, key) => {
+                let object = self.eval(object)?;
+                let key = self.eval(key)?;
+                get_member(&object, &key)
+            }
+            Expr::Call(callee, args) => self.call(callee, args),
+            Expr::Unary("typeof", operand) => {
+                if let Expr::Ident(name) = operand.as_ref()
+                    && !self.vars.contains_key(name)
+                {
+                    return Ok(Js::String("undefined".to_string()));
+                }
+                Ok(Js::String(self.eval(operand)?.type_of().to_string()))
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                Ok(match *op {
+                    "!" => Js::Bool(!value.truthy()),
+                    "-" => Js::Number(-value.to_number()),
+                    _ => Js::Number(value.to_number()),
+                })
+            }
+            Expr::Update { op, prefix, target } => {
+                let old = self.eval(target)?.to_number();
+                let new = if *op == "++" { old + 1.0 } else { old - 1.0 };
+                self.store(target, Js::Number(new))?;
+                Ok(Js::Number(if *prefix { new } else { old }))
+            }
+            Expr::Binary("&&", left, right) => {
+                let left = self.eval(left)?;
+                if left.truthy() { self.eval(right) } else { Ok(left) }
+            }
+            Expr::Binary("||", left, right) => {
+                let left = self.eval(left)?;
+                if left.truthy() { Ok(left) } else { self.eval(right) }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                Ok(binary(op, &left, &right))
+            }
+            Expr::Conditional(cond, then, otherwise) => {
+                if self.eval(cond)?.truthy() {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Assign(op, target, value) => {
+                let value = self.eval(value)?;
+                let value = match *op {
+                    "=" => value,
+                    compound => binary(&compound[..1], &self.eval(target)?, &value),
+                };
+                self.store(target, value.clone())?;
+                Ok(value)
+            }
+        }
+    }
+
+    // Writes a value to an assignable expression, creating undeclared globals.
+    fn store(&mut self, target: &Expr, value: Js) -> Result<(), DatamodelError> {
+        let mut keys = Vec::new();
+        let mut current = target;
+        while let Expr::Member(object, key) = current {
+            keys.push(self.eval(key)?);
+            current = object;
+        }
+        let Expr::Ident(name) = current else {
+            return Err(DatamodelError::InvalidLocation("not an assignable expression".to_string()));
+        };
+        if READ_ONLY.contains(&name.as_str()) {
+            return Err(DatamodelError::InvalidLocation(format!("{} is read-only", name)));
+        }
+        keys.reverse();
+        let Some((last, path)) = keys.split_last() else {
+            self.vars.insert(name.clone(), value);
+            return Ok(());
+        };
+        let mut slot = self.vars.get_mut(name).ok_or_else(|| type_error(format!("{} is not defined", name)))?;
+        for key in path {
+            slot = member_mut(slot, key)?;
+        }
+        match slot {
+            Js::Object(fields) => {
+                fields.insert(last.to_js_string(), value);
+            }
+            Js::Array(items) => {
+                let index = array_index(last).ok_or_else(|| type_error(format!("invalid array index {}", last.to_js_string())))?;
+                if index >= items.len() {
+                    let length = index.checked_add(1).filter(|&length| length <= MAX_ARRAY_LENGTH);
+                    let length = length.ok_or_else(|| type_error(format!("array index {} is too large", last.to_js_string())))?;
+                    items.resize(length, Js::Undefined);
+                }
+                items[index] = value;
+            }
+            other => return Err(type_error(format!("cannot set property '{}' of {}", last.to_js_string(), other.to_js_string()))),
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr]) -> Result<Js, DatamodelError> {
+        match callee {
+            Expr::Ident(name) if !self.vars.contains_key(name) => {
+                let args = self.eval_args(args)?;
+                self.call_global(name, &args)
+            }
+            Expr::Member(object, key) => {
+                let key = self.eval(key)?.to_js_string();
+                if let Expr::Ident(namespace) = object.as_ref()
+                    && (namespace == "Math" || namespace == "JSON")
+                    && !self.vars.contains_key(namespace)
+                {
+                    let args = self.eval_args(args)?;
+                    return call_namespace(namespace, &key, &args);
+                }
+                let args = self.eval_args(args)?;
+                if matches!(key.as_str(), "push" | "pop" | "shift" | "unshift") && object.root().is_some() {
+                    let mut array = self.eval(object)?;
+                    let result = mutate_array(&mut array, &key, args)?;
+                    self.store(object, array)?;
+                    return Ok(result);
+                }
+                call_method(&self.eval(object)?, &key, &args)
+            }
+            _ => Err(type_error("expression is not a function")),
+        }
+    }
+
+    fn eval_args(&mut self, args: &[Expr]) -> Result<Vec<Js>, DatamodelError> {
+        args.iter().map(|arg| self.eval(arg)).collect()
+    }
+
+    fn call_global(&mut self, name: &str, args: &[Js]) -> Result<Js, DatamodelError> {
+        let first = args.first().cloned().unwrap_or(Js::Undefined);
+        Ok(match name {
+            "In" => Js::Bool(self.ctx.in_state(&first.to_js_string())),
+            "eval" => {
+                let Js::String(source) = first else { return Ok(first) };
+                let program = Parser::new(&source)?.parse_program()?;
+                self.completion = Js::Undefined;
+                for statement in &program {
+                    self.exec(statement)?;
+                }
+                std::mem::replace(&mut self.completion, Js::Undefined)
+            }
+            "parseInt" => {
+                let radix = args.get(1).map(|r| r.to_number() as u32).filter(|r| (2..=36).contains(r));
+                Js::Number(parse_int(&first.to_js_string(), radix))
+            }
+            "parseFloat" => Js::Number(parse_float(&first.to_js_string())),
+            "isNaN" => Js::Bool(first.to_number().is_nan()),
+            "isFinite" => Js::Bool(first.to_number().is_finite()),
+            "String" => Js::String(if args.is_empty() { String::new() } else { first.to_js_string() }),
+            "Number" => Js::Number(if args.is_empty() { 0.0 } else { first.to_number() }),
+            "Boolean" => Js::Bool(first.truthy()),
+            _ => return Err(type_error(format!("{} is not defined", name))),
+        })
+    }
+}
+
+fn count_iteration(iterations: &mut usize) -> Result<(), DatamodelError> {
+    *iterations += 1;
+    if *iterations > MAX_LOOP_ITERATIONS {
+        return Err(type_error(format!("loop exceeded {} iterations", MAX_LOOP_ITERATIONS)));
+    }
+    Ok(())
+}
+
+fn binary(op: &str, left: &Js, right: &Js) -> Js {
+    match op {
+        "+" => match (left.to_primitive(), right.to_primitive()) {
+            (l @ Js::String(_), r) | (l, r @ Js::String(_)) => Js::String(l.to_js_string() + &r.to_js_string()),
+            (l, r) => Js::Number(l.to_number() + r.to_number()),
+        },
+        "-" => Js::Number(left.to_number() - right.to_number()),
+        "*" => Js::Number(left.to_number() * right.to_number()),
+        "/" => Js::Number(left.to_number() / right.to_number()),
+        "%" => Js::Number(left.to_number() % right.to_number()),
+        "==" => Js::Bool(loose_equals(left, right)),
+        "!=" => Js::Bool(!loose_equals(left, right)),
+        "===" => Js::Bool(strict_equals(left, right)),
+        "!==" => Js::Bool(!strict_equals(left, right)),
+        "<" => Js::Bool(compare(left, right).is_some_and(|o| o.is_lt())),
+        ">" => Js::Bool(compare(left, right).is_some_and(|o| o.is_gt())),
+        "<=" => Js::Bool(compare(left, right).is_some_and(|o| o.is_le())),
+        ">=" => Js::Bool(compare(left, right).is_some_and(|o| o.is_ge())),
+        _ => Js::Undefined,
+    }
+}
+
+fn array_index(key: &Js) -> Option<usize> {
+    let n = key.to_number();
+    // ECMAScript array indices stop one short of 2^32 - 1.
+    (n >= 0.0 && n.fract() == 0.0 && n < u32::MAX as f64).then_some(n as usize)
+}
+
+fn get_member(object: &Js, key: &Js) -> Result<Js, DatamodelError> {
+    let name = key.to_js_string();
+    Ok(match object {
+        Js::Undefined | Js::Null => {
+            return Err(type_error(format!("cannot read property '{}' of {}", name, object.to_js_string())));
+        }
+        Js::Object(fields) => fields.get(&name).cloned().unwrap_or(Js::Undefined),
+        Js::Array(items) if name == "length" => Js::Number(items.len() as f64),
+        Js::Array(items) => array_index(key).and_then(|i| items.get(i).cloned()).unwrap_or(Js::Undefined),
+        Js::String(s) if name == "length" => Js::Number(s.chars().count() as f64),
+        Js::String(s) => array_index(key)
+            .and_then(|i| s.chars().nth(i))
+            .map_or(Js::Undefined, |c| Js::String(c.to_string())),
+        _ => Js::Undefined,
+    })
+}
+
+fn member_mut<'v>(object: &'v mut Js, key: &Js) -> Result<&'v mut Js, DatamodelError> {
+    let missing = || type_error(format!("cannot set a property of undefined '{}'", key.to_js_string()));
+    match object {
+        Js::Object(fields) => fields.get_mut(&key.to_js_string()).ok_or_else(missing),
+        Js::Array(items) => array_index(key).and_then(|i| items.get_mut(i)).ok_or_else(missing),
+        _ => Err(missing()),
+    }
+}
+
+fn mutate_array(array: &mut Js, method: &str, args: Vec<Js>) -> Result<Js, DatamodelError> {
+    let Js::Array(items) = array else {
+        return Err(type_error(format!("{} is not a function", method)));
+    };
+    Ok(match method {
+        "push" => {
+            items.extend(args);
+            Js::Number(items.len() as f64)
+        }
+        "pop" => items.pop().unwrap_or(Js::Undefined),
+        "shift" if items.is_empty() => Js::Undefined,
+        "shift" => items.remove(0),
+        _ => {
+            items.splice(0..0, args);
+            Js::Number(items.len() as f64)
+        }
+    })
+}
+
+// Helper to resolve the start and end arguments of `slice`, which may be negative.
+fn relative_range(args: &[Js], len: usize) -> (usize, usize) {
+    let resolve = |arg: Option<&Js>, default: usize| match arg {
+        None | Some(Js::Undefined) => default,
+        Some(value) => {
+            let n = value.to_number();
+            let n = if n.is_nan() { 0.0 } else { n.trunc() };
+            if n < 0.0 { (len as f64 + n).max(0.0) as usize } else { (n as usize).min(len) }
+        }
+    };
+    let start = resolve(args.first(), 0);
+    (start, resolve(args.get(1), len).max(start))
+}
+
+fn call_method(object: &Js, method: &str, args: &[Js]) -> Result<Js, DatamodelError> {
+    let arg = |i: usize| args.get(i).cloned().unwrap_or(Js::Undefined);
+    let int_arg = |i: usize, default: f64| match args.get(i) {
+        None | Some(Js::Undefined) => default,
+        Some(value) => {
+            let n = value.to_number();
+            if n.is_nan() { 0.0 } else { n.trunc() }
+        }
+    };
+    match (object, method) {
+        (_, "toString") => return Ok(Js::String(object.to_js_string())),
+        (Js::Number(n), "toFixed") => return Ok(Js::String(format!("{:.*}", int_arg(0, 0.0).clamp(0.0, 100.0) as usize, n))),
+        (Js::String(s), _) => {
+            let chars: Vec<char> = s.chars().collect();
+            let len = chars.len();
+            let substring = |start: usize, end: usize| Js::String(chars[start.min(len)..end.min(len).max(start.min(len))].iter().collect());
+            // The character index in the first argument, if it is within the string.
+            let position = {
+                let n = int_arg(0, 0.0);
+                (n >= 0.0 && n < len as f64).then_some(n as usize)
+            };
+            let find = |needle: &str, from: usize| -> Option<usize> {
+                let byte_from = s.char_indices().nth(from).map_or(s.len(), |(b, _)| b);
+                s[byte_from..].find(needle).map(|b| s[..byte_from + b].chars().count())
+            };
+            let result = match method {
+                "charAt" => Js::String(position.map(|i| chars[i].to_string()).unwrap_or_default()),
+                "charCodeAt" => position.map_or(Js::Number(f64::NAN), |i| Js::Number(chars[i] as u32 as f64)),
+                "indexOf" => Js::Number(find(&arg(0).to_js_string(), int_arg(1, 0.0).clamp(0.0, len as f64) as usize).map_or(-1.0, |i| i as f64)),
+                "lastIndexOf" => {
+                    let needle = arg(0).to_js_string();
+                    Js::Number(s.rfind(&needle).map_or(-1.0, |b| s[..b].chars().count() as f64))
+                }
+                "includes" => Js::Bool(s.contains(&arg(0).to_js_string())),
+                "startsWith" => Js::Bool(s.starts_with(&arg(0).to_js_string())),
+                "endsWith" => Js::Bool(s.ends_with(&arg(0).to_js_string())),
+                "substr" => {
+                    let start = int_arg(0, 0.0);
+                    let start = if start < 0.0 { (len as f64 + start).max(0.0) } else { start } as usize;
+                    let count = int_arg(1, len as f64).max(0.0) as usize;
+                    substring(start, start.saturating_add(count))
+                }
+                "substring" => {
+                    let a = (int_arg(0, 0.0).max(0.0) as usize).min(len);
+                    let b = (int_arg(1, len as f64).max(0.0) as usize).min(len);
+                    substring(a.min(b), a.max(b))
+                }
+                "slice" => {
+                    let (start, end) = relative_range(args, len);
+                    substring(start, end)
+                }
+                "toUpperCase" => Js::String(s.to_uppercase()),
+                "toLowerCase" => Js::String(s.to_lowercase()),
+                "trim" => Js::String(s.trim().to_string()),
+                "concat" => Js::String(args.iter().fold(s.clone(), |acc, a| acc + &a.to_js_string())),
+                "replace" => Js::String(s.replacen(&arg(0).to_js_string(), &arg(1).to_js_string(), 1)),
+                "split" => match arg(0) {
+                    Js::Undefined => Js::Array(vec![object.clone()]),
+                    separator => {
+                        let separator = separator.to_js_string();
+                        if separator.is_empty() {
+                            Js::Array(chars.iter().map(|c| Js::String(c.to_string())).collect())
+                        } else {
+                            Js::Array(s.split(&separator).map(|part| Js::String(part.to_string())).collect())
+                        }
+                    }
+                },
+                _ => return Err(type_error(format!("string has no method '{}'", method))),
+            };
+            return Ok(result);
+        }
+        (Js::Array(items), _) => {
+            let result = match method {
+                "join" => {
+                    let separator = match arg(0) {
+                        Js::Undefined => ",".to_string(),
+                        separator => separator.to_js_string(),
+                    };
+                    let parts: Vec<String> = items
+                        .iter()
+                        .map(|item| if matches!(item, Js::Undefined | Js::Null) { String::new() } else { item.to_js_string() })
+                        .collect();
+                    Js::String(parts.join(&separator))
+                }
+                "indexOf" => Js::Number(items.iter().position(|item| strict_equals(item, &arg(0))).map_or(-1.0, |i| i as f64)),
+                "includes" => Js::Bool(items.iter().any(|item| strict_equals(item, &arg(0)))),
+                "slice" => {
+                    let (start, end) = relative_range(args, items.len());
+                    Js::Array(items[start..end].to_vec())
+                }
+                "concat" => {
+                    let mut result = items.clone();
+                    for a in args {
+                        match a {
+                            Js::Array(more) => result.extend(more.iter().cloned()),
+                            other => result.push(other.clone()),
+                        }
+                    }
+                    Js::Array(result)
+                }
+                "reverse" => Js::Array(items.iter().rev().cloned().collect()),
+                _ => return Err(type_error(format!("array has no method '{}'", method))),
+            };
+            return Ok(result);
+        }
+        _ => {}
+    }
+    if let Js::Object(fields) = object
+        && method == "hasOwnProperty"
+    {
+        return Ok(Js::Bool(fields.contains_key(&arg(0).to_js_string())));
+    }
+    Err(type_error(format!("{} is not a function", method)))
+}
+
+fn call_namespace(namespace: &str, name: &str, args: &[Js]) -> Result<Js, DatamodelError> {
+    let numbers: Vec<f64> = args.iter().map(Js::to_number).collect();
+    let first = numbers.first().copied().unwrap_or(f64::NAN);
+    Ok(match (namespace, name) {
+        ("Math", "abs") => Js::Number(first.abs()),
+        ("Math", "ceil") => Js::Number(first.ceil()),
+        ("Math", "floor") => Js::Number(first.floor()),
+        // ECMAScript rounds halves towards positive infinity.
+        ("Math", "round") => Js::Number((first + 0.5).floor()),
+        ("Math", "sqrt") => Js::Number(first.sqrt()),
+        ("Math", "pow") => Js::Number(first.powf(numbers.get(1).copied().unwrap_or(f64::NAN))),
+        ("Math", "min") => Js::Number(numbers.iter().copied().fold(f64::INFINITY, |a, b| if a.is_nan() || b.is_nan() { f64::NAN } else { a.min(b) })),
+        ("Math", "max") => Js::Number(numbers.iter().copied().fold(f64::NEG_INFINITY, |a, b| if a.is_nan() || b.is_nan() { f64::NAN } else { a.max(b) })),
+        ("JSON", "stringify") => match args.first() {
+            None | Some(Js::Undefined) => Js::Undefined,
+            Some(value) => Js::String(value.to_json()),
+        },
+        _ => return Err(type_error(format!("{}.{} is not a function", namespace, name))),
+    })
+}
+
+fn parse_int(s: &str, radix: Option<u32>) -> f64 {
+    let s = s.trim();
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (radix, s) = match (radix, s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))) {
+        (None | Some(16), Some(hex)) => (16, hex),
+        (radix, _) => (radix.unwrap_or(10), s),
+    };
+    let digits: String = s.chars().take_while(|c| c.is_digit(radix)).collect();
+    if digits.is_empty() {
+        return f64::NAN;
+    }
+    let value = digits.chars().fold(0.0, |acc, c| acc * radix as f64 + c.to_digit(radix).unwrap_or(0) as f64);
+    if negative { -value } else { value }
+}
+
+fn parse_float(s: &str) -> f64 {
+    let s = s.trim_start();
+    // Try the longest prefix that parses, as ECMAScript's parseFloat does.
+    (1..=s.len())
+        .rev()
+        .filter(|&end| s.is_char_boundary(end))
+        .map(|end| &s[..end])
+        .find_map(|prefix| {
+            if prefix.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')) {
+                prefix.parse::<f64>().ok()
+            } else if prefix.trim_start_matches(['+', '-']) == "Infinity" {
+                Some(if prefix.starts_with('-') { f64::NEG_INFINITY } else { f64::INFINITY })
+            } else {
+                None
+            }
+        })
+        .unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::StateChart;
+    use crate::parse_scxml;
+    use std::collections::BTreeSet;
+
+    fn with_context<T>(f: impl FnOnce(&Context) -> T) -> T {
+        let scxml = parse_scxml(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0"><state id="s"/></scxml>"#).unwrap();
+        let chart = StateChart::compile(&scxml).unwrap();
+        let configuration = BTreeSet::from([chart.find("s").unwrap()]);
+        f(&Context::new(&chart, &configuration))
+    }
+
+    fn eval(dm: &mut EcmaScriptDatamodel, expr: &str) -> Result<Value, DatamodelError> {
+        with_context(|ctx| dm.evaluate(expr, ctx))
+    }
+
+    #[test]
+    fn test_expressions() {
+        let mut dm = EcmaScriptDatamodel::new();
+        let cases: &[(&str, Value)] = &[
+            ("1 + 2 * 3", 7.0.into()),
+            ("(1 + 2) * 3 % 4", 1.0.into()),
+            ("'a' + 1 + 2", "a12".into()),
+            ("1 + 2 + 'a'", "3a".into()),
+            ("0.1 + 0.2", (0.1 + 0.2).into()),
+            ("'5' * '2'", 10.0.into()),
+            ("'DIGIT.7'.substr('DIGIT.7'.lastIndexOf('.') + 1)", "7".into()),
+            ("'hello'.substring(1, 3).toUpperCase()", "EL".into()),
+            ("'abc'.charAt(Infinity) + 'abc'.charAt(-1) + 'abc'.charAt(1) + 'abc'.charAt(3)", "b".into()),
+            ("'abc'.charCodeAt(0) + isNaN('abc'.charCodeAt(-Infinity)) + isNaN('abc'.charCodeAt(1e300))", 99.0.into()),
+            ("'abcabc'.indexOf('b', Infinity) + 'abcabc'.indexOf('b', -Infinity) + 'abcabc'.indexOf('b', 2)", 4.0.into()),
+            ("'hello'.substr(Infinity) + 'hello'.substr(-Infinity, Infinity) + 'hello'.substring(-Infinity, Infinity)", "hellohello".into()),
+            ("'a,b,c'.split(',').length", 3.0.into()),
+            ("[1, 2, 3].join('-')", "1-2-3".into()),
+            ("[1, [2, 3]] + ''", "1,2,3".into()),
+            ("({a: {b: [10, 20]}}).a.b[1]", 20.0.into()),
+            ("1 == '1' && null == undefined && !(1 === '1')", true.into()),
+            ("'b' > 'a' && 2 < 10 && '2' > '10'", true.into()),
+            ("NaN == NaN", false.into()),
+            ("typeof missing + typeof 1 + typeof null", "undefinednumberobject".into()),
+            ("0 || '' || 'x'", "x".into()),
+            ("1 && 0", 0.0.into()),
+            ("true ? 'y' : 'n'", "y".into()),
+            ("In('s') && !In('t')", true.into()),
+            ("eval('(1)+(2)*(3)')", 7.0.into()),
+            ("parseInt('42px') + parseFloat('1.5e1x')", 57.0.into()),
+            ("Math.max(1, 5, 3) + Math.round(2.5) + Math.floor(-1.5)", 6.0.into()),
+            ("(1/3).toFixed(2)", "0.33".into()),
+            ("String(1e21) + ' ' + String(-0) + ' ' + (1/0)", "1e+21 0 Infinity".into()),
+            ("JSON.stringify({b: [1, 'x'], a: null})", r#"{"a":null,"b":[1,"x"]}"#.into()),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(eval(&mut dm, expr).as_ref(), Ok(expected), "{}", expr);
+        }
+        assert!(matches!(eval(&mut dm, "missing + 1"), Err(DatamodelError::Evaluation(_))));
+        assert!(matches!(eval(&mut dm, "null.x"), Err(DatamodelError::Evaluation(_))));
+        assert!(matches!(eval(&mut dm, ".x==''"), Err(DatamodelError::Syntax(_))));
+        assert!(matches!(eval(&mut dm, "1 +"), Err(DatamodelError::Syntax(_))));
+        assert!(matches!(eval(&mut dm, "'open"), Err(DatamodelError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_scripts_and_locations() {
+        let mut dm = EcmaScriptDatamodel::new();
+        with_context(|ctx| {
+            dm.init(&SystemVariables { sessionid: "s1".into(), name: Some("calc".into()), ..Default::default() }).unwrap();
+            let data = |id: &str, expr: Option<&str>, content: Option<&str>| Data {
+                id: id.into(),
+                expr: expr.map(Into::into),
+                src: None,
+                content: content.map(Into::into),
+                span: Default::default(),
+            };
+            dm.init_data(&data("list", Some("[]"), None), ctx).unwrap();
+            dm.init_data(&data("config", None, Some(r#" { "depth": 2 } "#)), ctx).unwrap();
+            dm.init_data(&data("text", None, Some(" plain words ")), ctx).unwrap();
+            assert!(dm.init_data(&data("broken", Some("1 +"), None), ctx).is_err());
+            assert_eq!(dm.get("broken"), Some(Value::Null));
+            assert_eq!(dm.get("text"), Some(Value::from("plain words")));
+
+            dm.run_script(
+                "var total = 0, keys = '';
+                 for (var i = 0; i < 5; i++) { if (i == 3) continue; total += i; }
+                 while (true) { total = total * 2; if (total > 20) break; }
+                 for (k in config) keys = keys + k;
+                 list.push(total, keys);
+                 config.depth = config.depth + 1
+                 config['extra'] = {nested: [0]};
+                 config.extra.nested[2] = 'x';",
+                ctx,
+            )
+            .unwrap();
+            assert_eq!(dm.get("total"), Some(Value::Number(28.0)));
+            assert_eq!(dm.get("list"), Some(Value::Array(vec![28.0.into(), "depth".into()])));
+            assert_eq!(dm.evaluate("config.depth", ctx), Ok(Value::Number(3.0)));
+            assert_eq!(dm.evaluate("config.extra.nested.length", ctx), Ok(Value::Number(3.0)));
+
+            dm.execute_assign("config.depth", "_name + _sessionid", ctx).unwrap();
+            assert_eq!(dm.evaluate("config.depth", ctx), Ok(Value::from("calcs1")));
+            assert!(matches!(dm.execute_assign("undeclared", "1", ctx), Err(DatamodelError::InvalidLocation(_))));
+            assert!(matches!(dm.execute_assign("_sessionid", "1", ctx), Err(DatamodelError::InvalidLocation(_))));
+            assert!(matches!(dm.execute_assign("1 + 1", "1", ctx), Err(DatamodelError::InvalidLocation(_))));
+            assert!(matches!(dm.run_script("while (true) {}", ctx), Err(DatamodelError::Evaluation(_))));
+            assert!(matches!(dm.run_script("function f() {}", ctx), Err(DatamodelError::Unsupported(_))));
+
+            // Huge indices are refused instead of allocating the array up to them.
+            for index in ["1e300", "4294967296000", "4294967294", "Infinity"] {
+                let script = format!("list[{}] = 1", index);
+                assert!(matches!(dm.run_script(&script, ctx), Err(DatamodelError::Evaluation(_))), "{}", script);
+            }
+            assert_eq!(dm.evaluate("list.length", ctx), Ok(Value::Number(2.0)));
+        });
+    }
+
+    #[test]
+    fn test_event_binding() {
+        let mut dm = EcmaScriptDatamodel::new();
+        let mut data = BTreeMap::new();
+        data.insert("operator".to_string(), Value::from("OPER.PLUS"));
+        dm.set_event(&Event::new("OP.INSERT").with_data(Value::Object(data)));
+        assert_eq!(eval(&mut dm, "_event.name + ':' + _event.type"), Ok(Value::from("OP.INSERT:external")));
+        assert_eq!(eval(&mut dm, "_event.data.operator"), Ok(Value::from("OPER.PLUS")));
+        assert_eq!(eval(&mut dm, "_event.sendid === undefined"), Ok(Value::Bool(true)));
+    }
+}
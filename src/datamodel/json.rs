@@ -0,0 +1,711 @@
+//! A datamodel that stores its data as a [`serde_json::Value`] tree.
+//!
+//! Registered as the `json` datamodel when the `json-datamodel` feature is enabled.
+//! Every `<data>` element declares a top-level variable; inline content is parsed
+//! as JSON, falling back to a string when it is not valid JSON. Expressions are a
+//! small language over that tree:
+//!
+//! - literals: JSON numbers, single- or double-quoted strings, `true`, `false`, `null`,
+//!   and array and object literals whose elements are expressions;
+//! - paths: `order.items[0].sku`, `order["item-count"]`, or rooted at `$` in the JSONPath
+//!   style, as in `$.order.items[0]`. Reading a missing member or index yields `null`;
+//!   reading an undeclared variable is an error;
+//! - comparisons (`== != < <= > >=`), boolean operators (`&& || !`), unary `-` and parentheses;
+//! - the `In('stateId')` predicate.
+//!
+//! `<assign>` locations are paths. Assigning creates missing object members, and
+//! assigning one past the end of an array appends to it. The system variables
+//! `_event`, `_sessionid`, `_name` and `_ioprocessors` are read-only. `<script>` is not supported.
+
+use serde_json::{Map, Number, Value as Json};
+
+use crate::Data;
+use crate::datamodel::{Context, Datamodel, DatamodelError, SystemVariables, Value};
+use crate::runtime::{Event, EventType};
+
+// System variables the chart may read but not assign.
+const READ_ONLY: &[&str] = &["_event", "_sessionid", "_name", "_ioprocessors"];
+
+/// The JSON datamodel.
+///
+/// # Examples
+///
+/// ```rust
+/// use harel::parse_scxml;
+/// use harel::datamodel::JsonDatamodel;
+/// use harel::runtime::{Event, Interpreter};
+/// use serde_json::json;
+///
+/// let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="json">
+///     <datamodel><data id="order">{"items": [{"sku": "A1", "qty": 0}], "paid": false}</data></datamodel>
+///     <state id="open">
+///         <transition event="update"><assign location="order.items[0].qty" expr="_event.data.qty"/></transition>
+///         <transition event="pay" cond="order.items[0].qty &gt; 0 &amp;&amp; !order.paid" target="paid">
+///             <assign location="order.paid" expr="true"/>
+///         </transition>
+///     </state>
+///     <state id="paid"/>
+/// </scxml>"#;
+///
+/// let scxml = parse_scxml(xml).unwrap();
+/// let mut interpreter = Interpreter::new(&scxml).unwrap();
+/// interpreter.start().unwrap();
+/// interpreter.send(Event::new("update").with_data(json!({"qty": 2}))).unwrap();
+/// interpreter.send(Event::new("pay")).unwrap();
+/// assert!(interpreter.is_in_state("paid"));
+///
+/// let datamodel = interpreter.datamodel().downcast_ref::<JsonDatamodel>().unwrap();
+/// assert_eq!(datamodel.get("order"), Some(&json!({"items": [{"sku": "A1", "qty": 2}], "paid": true})));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JsonDatamodel {
+    vars: Map<String, Json>,
+}
+
+impl JsonDatamodel {
+    /// Creates an empty datamodel.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of a top-level variable, if it is declared.
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        self.vars.get(name)
+    }
+
+    /// Returns every top-level variable, including the system variables.
+    pub fn variables(&self) -> &Map<String, Json> {
+        &self.vars
+    }
+
+    // Helper to parse and evaluate a single expression.
+    fn eval_str(&self, expr: &str, ctx: &Context) -> Result<Json, DatamodelError> {
+        let parsed = Parser::new(expr)?.parse_expression()?;
+        self.eval(&parsed, ctx)
+    }
+
+    fn eval(&self, expr: &Expr, ctx: &Context) -> Result<Json, DatamodelError> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Path(steps) => self.read(steps),
+            Expr::Array(items) => items.iter().map(|item| self.eval(item, ctx)).collect::<Result<_, _>>().map(Json::Array),
+            Expr::Object(fields) => fields
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), self.eval(value, ctx)?)))
+                .collect::<Result<_, _>>()
+                .map(Json::Object),
+            Expr::In(id) => Ok(Json::Bool(ctx.in_state(id))),
+            Expr::Not(operand) => Ok(Json::Bool(!self.eval_bool(operand, ctx)?)),
+            Expr::Neg(operand) => match self.eval(operand, ctx)? {
+                Json::Number(n) => negate(&n),
+                other => Err(DatamodelError::Evaluation(format!("cannot negate {}", other))),
+            },
+            Expr::And(left, right) => Ok(Json::Bool(self.eval_bool(left, ctx)? && self.eval_bool(right, ctx)?)),
+            Expr::Or(left, right) => Ok(Json::Bool(self.eval_bool(left, ctx)? || self.eval_bool(right, ctx)?)),
+            Expr::Compare(op, left, right) => {
+                let (left, right) = (self.eval(left, ctx)?, self.eval(right, ctx)?);
+                compare(op, &left, &right).map(Json::Bool)
+            }
+        }
+    }
+
+    // Helper to evaluate an operand of a boolean operator, which must be a boolean.
+    fn eval_bool(&self, expr: &Expr, ctx: &Context) -> Result<bool, DatamodelError> {
+        match self.eval(expr, ctx)? {
+            Json::Bool(b) => Ok(b),
+            other => Err(DatamodelError::Evaluation(format!("{} is not a boolean", other))),
+        }
+    }
+
+    // Reads the value at a path; missing members and indices read as `null`.
+    fn read(&self, steps: &[Step]) -> Result<Json, DatamodelError> {
+        let (root, rest) = match steps.split_first() {
+            None => return Ok(Json::Object(self.vars.clone())),
+            Some((Step::Key(root), rest)) => (root, rest),
+            Some((Step::Index(_), _)) => return Ok(Json::Null),
+        };
+        let mut value = self
+            .vars
+            .get(root)
+            .ok_or_else(|| DatamodelError::Evaluation(format!("{} is not declared", root)))?;
+        for step in rest {
+            let next = match (value, step) {
+                (Json::Object(fields), Step::Key(key)) => fields.get(key),
+                (Json::Array(items), Step::Index(index)) => items.get(*index),
+                _ => None,
+            };
+            match next {
+                Some(next) => value = next,
+                None => return Ok(Json::Null),
+            }
+        }
+        Ok(value.clone())
+    }
+
+    // Stores a value at a location, creating missing members along the way.
+    fn store(&mut self, location: &str, value: Json) -> Result<(), DatamodelError> {
+        let steps = Parser::new(location)?.parse_location()?;
+        let Some((Step::Key(root), rest)) = steps.split_first() else {
+            return Err(DatamodelError::InvalidLocation(format!("'{}' does not name a variable", location)));
+        };
+        if READ_ONLY.contains(&root.as_str()) {
+            return Err(DatamodelError::InvalidLocation(format!("{} is read-only", root)));
+        }
+        let mut slot = self
+            .vars
+            .get_mut(root)
+            .ok_or_else(|| DatamodelError::InvalidLocation(format!("{} is not declared", root)))?;
+        for step in rest {
+            slot = member_mut(slot, step)?;
+        }
+        *slot = value;
+        Ok(())
+    }
+}
+
+impl Datamodel for JsonDatamodel {
+    fn init(&mut self, system: &SystemVariables) -> Result<(), DatamodelError> {
+        self.vars.insert("_sessionid".to_string(), Json::String(system.sessionid.clone()));
+        self.vars.insert("_name".to_string(), system.name.clone().map_or(Json::Null, Json::String));
+        let ioprocessors = system
+            .ioprocessors
+            .iter()
+            .map(|(kind, location)| {
+                let entry = Map::from_iter([("location".to_string(), Json::String(location.clone()))]);
+                (kind.clone(), Json::Object(entry))
+            })
+            .collect();
+        self.vars.insert("_ioprocessors".to_string(), Json::Object(ioprocessors));
+        self.vars.insert("_event".to_string(), Json::Null);
+        Ok(())
+    }
+
+    fn init_data(&mut self, data: &Data, ctx: &Context) -> Result<(), DatamodelError> {
+        // The variable exists even if its initial value cannot be computed.
+        self.vars.insert(data.id.clone(), Json::Null);
+        let value = match (&data.expr, &data.content, &data.src) {
+            (Some(expr), _, _) => self.eval_str(expr, ctx)?,
+            (None, Some(content), _) if !content.trim().is_empty() => {
+                serde_json::from_str(content).unwrap_or_else(|_| Json::String(content.trim().to_string()))
+            }
+            (None, _, Some(src)) => return Err(DatamodelError::Unsupported(format!("<data src=\"{}\">", src))),
+            _ => Json::Null,
+        };
+        self.vars.insert(data.id.clone(), value);
+        Ok(())
+    }
+
+    fn set_event(&mut self, event: &Event) {
+        let optional = |field: &Option<String>| field.clone().map_or(Json::Null, Json::String);
+        let type_ = match event.type_ {
+            EventType::Platform => "platform",
+            EventType::Internal => "internal",
+            EventType::External => "external",
+        };
+        let fields = Map::from_iter([
+            ("name".to_string(), Json::String(event.name.clone())),
+            ("type".to_string(), Json::String(type_.to_string())),
+            ("sendid".to_string(), optional(&event.sendid)),
+            ("origin".to_string(), optional(&event.origin)),
+            ("origintype".to_string(), optional(&event.origintype)),
+            ("invokeid".to_string(), optional(&event.invokeid)),
+            ("data".to_string(), event.data.clone().into()),
+        ]);
+        self.vars.insert("_event".to_string(), Json::Object(fields));
+    }
+
+    fn evaluate(&mut self, expr: &str, ctx: &Context) -> Result<Value, DatamodelError> {
+        Ok(self.eval_str(expr, ctx)?.into())
+    }
+
+    fn assign(&mut self, location: &str, value: Value, _ctx: &Context) -> Result<(), DatamodelError> {
+        self.store(location, value.into())
+    }
+
+    fn declare(&mut self, location: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        // Only a plain variable name can be declared; nested paths must already exist.
+        if let [Step::Key(name)] = Parser::new(location)?.parse_location()?.as_slice()
+            && !READ_ONLY.contains(&name.as_str())
+        {
+            self.vars.entry(name.clone()).or_insert(Json::Null);
+        }
+        Ok(())
+    }
+
+    fn execute_assign(&mut self, location: &str, expr: &str, ctx: &Context) -> Result<(), DatamodelError> {
+        // Evaluated directly so integers stay integers instead of round-tripping through `Value`.
+        let value = self.eval_str(expr, ctx)?;
+        self.store(location, value)
+    }
+}
+
+impl From<Json> for Value {
+    fn from(json: Json) -> Self {
+        match json {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Bool(b),
+            Json::Number(n) => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+            Json::String(s) => Value::String(s),
+            Json::Array(items) => Value::Array(items.into_iter().map(Value::from).collect()),
+            Json::Object(fields) => Value::Object(fields.into_iter().map(|(k, v)| (k, Value::from(v))).collect()),
+        }
+    }
+}
+
+impl From<Value> for Json {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Json::Null,
+            Value::Bool(b) => Json::Bool(b),
+            Value::Number(n) => number(n),
+            Value::String(s) => Json::String(s),
+            Value::Array(items) => Json::Array(items.into_iter().map(Json::from).collect()),
+            Value::Object(fields) => Json::Object(fields.into_iter().map(|(k, v)| (k, Json::from(v))).collect()),
+        }
+    }
+}
+
+// Converts a float to a JSON number, keeping whole numbers as integers. NaN and infinities become `null`.
+fn number(n: f64) -> Json {
+    if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 {
+        Json::Number(Number::from(n as i64))
+    } else {
+        Number::from_f64(n).map_or(Json::Null, Json::Number)
+    }
+}
+
+fn negate(n: &Number) -> Result<Json, DatamodelError> {
+    if let Some(i) = n.as_i64().and_then(i64::checked_neg) {
+        return Ok(Json::Number(i.into()));
+    }
+    Ok(number(-n.as_f64().unwrap_or(f64::NAN)))
+}
+
+fn compare(op: &str, left: &Json, right: &Json) -> Result<bool, DatamodelError> {
+    match op {
+        "==" => return Ok(json_equals(left, right)),
+        "!=" => return Ok(!json_equals(left, right)),
+        _ => {}
+    }
+    let ordering = match (left, right) {
+        (Json::Number(a), Json::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+        (Json::String(a), Json::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    let ordering = ordering.ok_or_else(|| DatamodelError::Evaluation(format!("cannot compare {} {} {}", left, op, right)))?;
+    Ok(match op {
+        "<" => ordering.is_lt(),
+        "<=" => ordering.is_le(),
+        ">" => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    })
+}
+
+// Structural equality in which `1` and `1.0` are the same number.
+fn json_equals(left: &Json, right: &Json) -> bool {
+    match (left, right) {
+        (Json::Number(a), Json::Number(b)) => a.as_f64() == b.as_f64(),
+        (Json::Array(a), Json::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_equals(a, b)),
+        (Json::Object(a), Json::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(key, value)| b.get(key).is_some_and(|other| json_equals(value, other)))
+        }
+        _ => left == right,
+    }
+}
+
+// Returns the member or element a path step selects, creating it if needed.
+fn member_mut<'v>(value: &'v mut Json, step: &Step) -> Result<&'v mut Json, DatamodelError> {
+    if value.is_null() {
+        *value = match step {
+            Step::Key(_) => Json::Object(Map::new()),
+            Step::Index(_) => Json::Array(Vec::new()),
+        };
+    }
+    match (value, step) {
+        (Json::Object(fields), Step::Key(key)) => Ok(fields.entry(key.clone()).or_insert(Json::Null)),
+        (Json::Array(items), Step::Index(index)) => {
+            if *index == items.len() {
+                items.push(Json::Null);
+            }
+            let len = items.len();
+            items
+                .get_mut(*index)
+                .ok_or_else(|| DatamodelError::InvalidLocation(format!("index {} is out of bounds for an array of length {}", index, len)))
+        }
+        (other, Step::Key(key)) => Err(DatamodelError::InvalidLocation(format!("cannot select member '{}' of {}", key, other))),
+        (other, Step::Index(index)) => Err(DatamodelError::InvalidLocation(format!("cannot select index {} of {}", index, other))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Number),
+    String(String),
+    Ident(String),
+    Punct(&'static str),
+    End,
+}
+
+// Longest punctuators first, so `<=` is not read as `<` followed by `=`.
+const PUNCTUATORS: &[&str] =
+    &["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "-", "(", ")", "[", "]", "{", "}", ".", ",", ":", "$"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, DatamodelError> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else { break };
+        if c.is_ascii_digit() {
+            let end = rest
+                .char_indices()
+                .find(|&(i, c)| {
+                    !(c.is_ascii_digit()
+                        || c == '.'
+                        || c == 'e'
+                        || c == 'E'
+                        || ((c == '+' || c == '-') && matches!(rest[..i].chars().last(), Some('e' | 'E'))))
+                })
+                .map_or(rest.len(), |(i, _)| i);
+            let number = serde_json::from_str::<Number>(&rest[..end])
+                .map_err(|_| DatamodelError::Syntax(format!("invalid number '{}'", &rest[..end])))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c == '"' || c == '\'' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, ch)) if ch == c => break i + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(DatamodelError::Syntax(format!("unterminated string in '{}'", source))),
+                    },
+                    Some((_, ch)) => value.push(ch),
+                    None => return Err(DatamodelError::Syntax(format!("unterminated string in '{}'", source))),
+                }
+            };
+            tokens.push(Token::String(value));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(punct) = PUNCTUATORS.iter().find(|p| rest.starts_with(**p)) {
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        } else {
+            return Err(DatamodelError::Syntax(format!("unexpected character '{}' in '{}'", c, source)));
+        }
+    }
+    tokens.push(Token::End);
+    Ok(tokens)
+}
+
+// One step of a path: an object member or an array index.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(Json),
+    // An empty path is the `$` root: the object holding every variable.
+    Path(Vec<Step>),
+    Array(Vec<Expr>),
+    Object(Vec<(String, Expr)>),
+    In(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    source: String,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, DatamodelError> {
+        Ok(Self { tokens: tokenize(source)?, pos: 0, source: source.to_string() })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Token::Punct(p) if *p == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), DatamodelError> {
+        if self.eat(punct) { Ok(()) } else { Err(self.unexpected(self.peek(), &format!("'{}'", punct))) }
+    }
+
+    fn unexpected(&self, found: &Token, expected: &str) -> DatamodelError {
+        let found = match found {
+            Token::Number(n) => n.to_string(),
+            Token::String(s) => format!("'{}'", s),
+            Token::Ident(name) => name.clone(),
+            Token::Punct(p) => p.to_string(),
+            Token::End => "end of input".to_string(),
+        };
+        DatamodelError::Syntax(format!("expected {} but found {} in '{}'", expected, found, self.source))
+    }
+
+    fn parse_expression(mut self) -> Result<Expr, DatamodelError> {
+        let expr = self.or()?;
+        if *self.peek() != Token::End {
+            return Err(self.unexpected(self.peek(), "end of expression"));
+        }
+        Ok(expr)
+    }
+
+    fn parse_location(mut self) -> Result<Vec<Step>, DatamodelError> {
+        let steps = match self.next() {
+            Token::Punct("$") => self.steps(Vec::new())?,
+            Token::Ident(name) => self.steps(vec![Step::Key(name)])?,
+            _ => return Err(DatamodelError::InvalidLocation(format!("'{}' is not a path", self.source))),
+        };
+        if *self.peek() != Token::End {
+            return Err(DatamodelError::InvalidLocation(format!("'{}' is not a path", self.source)));
+        }
+        Ok(steps)
+    }
+
+    fn or(&mut self) -> Result<Expr, DatamodelError> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, DatamodelError> {
+        let mut left = self.comparison()?;
+        while self.eat("&&") {
+            left = Expr::And(Box::new(left), Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, DatamodelError> {
+        let left = self.unary()?;
+        for op in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.eat(op) {
+                return Ok(Expr::Compare(op, Box::new(left), Box::new(self.unary()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, DatamodelError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, DatamodelError> {
+        match self.next() {
+            Token::Number(n) => Ok(Expr::Literal(Json::Number(n))),
+            Token::String(s) => Ok(Expr::Literal(Json::String(s))),
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Json::Bool(true))),
+                "false" => Ok(Expr::Literal(Json::Bool(false))),
+                "null" => Ok(Expr::Literal(Json::Null)),
+                "In" if self.eat("(") => {
+                    let Token::String(id) = self.next() else {
+                        return Err(DatamodelError::Syntax(format!("In() expects a quoted state ID in '{}'", self.source)));
+                    };
+                    self.expect(")")?;
+                    Ok(Expr::In(id))
+                }
+                _ => Ok(Expr::Path(self.steps(vec![Step::Key(name)])?)),
+            },
+            Token::Punct("$") => Ok(Expr::Path(self.steps(Vec::new())?)),
+            Token::Punct("(") => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[") => {
+                let mut items = Vec::new();
+                while !self.eat("]") {
+                    items.push(self.or()?);
+                    if !self.eat(",") {
+                        self.expect("]")?;
+                        break;
+                    }
+                }
+                Ok(Expr::Array(items))
+            }
+            Token::Punct("{") => {
+                let mut fields = Vec::new();
+                while !self.eat("}") {
+                    let key = match self.next() {
+                        Token::String(key) | Token::Ident(key) => key,
+                        other => return Err(self.unexpected(&other, "an object key")),
+                    };
+                    self.expect(":")?;
+                    fields.push((key, self.or()?));
+                    if !self.eat(",") {
+                        self.expect("}")?;
+                        break;
+                    }
+                }
+                Ok(Expr::Object(fields))
+            }
+            other => Err(self.unexpected(&other, "an expression")),
+        }
+    }
+
+    // Parses the `.member`, `["member"]` and `[index]` steps following a path root.
+    fn steps(&mut self, mut steps: Vec<Step>) -> Result<Vec<Step>, DatamodelError> {
+        loop {
+            if self.eat(".") {
+                match self.next() {
+                    Token::Ident(key) => steps.push(Step::Key(key)),
+                    other => return Err(self.unexpected(&other, "a member name")),
+                }
+            } else if self.eat("[") {
+                let step = match self.next() {
+                    Token::String(key) => Step::Key(key),
+                    Token::Number(n) => match n.as_u64() {
+                        Some(index) => Step::Index(index as usize),
+                        None => return Err(self.unexpected(&Token::Number(n), "an array index")),
+                    },
+                    other => return Err(self.unexpected(&other, "an array index or quoted member name")),
+                };
+                steps.push(step);
+                self.expect("]")?;
+            } else {
+                return Ok(steps);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::StateChart;
+    use crate::parse_scxml;
+    use crate::runtime::Interpreter;
+    use serde_json::json;
+    use std::collections::BTreeSet;
+
+    fn with_context<T>(f: impl FnOnce(&Context) -> T) -> T {
+        let scxml = parse_scxml(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0"><state id="s"/></scxml>"#).unwrap();
+        let chart = StateChart::compile(&scxml).unwrap();
+        let configuration = BTreeSet::from([chart.find("s").unwrap()]);
+        f(&Context::new(&chart, &configuration))
+    }
+
+    #[test]
+    fn test_expressions() {
+        let mut dm = JsonDatamodel::new();
+        dm.vars.insert("a".to_string(), json!({"b": [10, 20, {"c": "x"}], "key-with-dash": true, "n": 1.5}));
+        let cases: &[(&str, Json)] = &[
+            ("a.b[1]", json!(20)),
+            ("a.b[2].c", json!("x")),
+            ("$.a['key-with-dash']", json!(true)),
+            ("a.missing.deeper", json!(null)),
+            ("a.b[9]", json!(null)),
+            ("-a.n", json!(-1.5)),
+            ("[1, 'two', {\"three\": a.b[0]}]", json!([1, "two", {"three": 10}])),
+            ("a.b[0] == 10.0", json!(true)),
+            ("a.b[2] == {c: 'x'}", json!(true)),
+            ("a.b[0] < a.b[1] && 'abc' >= 'abb'", json!(true)),
+            ("!(a.n > 2) || a.missing", json!(true)),
+            ("a.missing == null", json!(true)),
+            ("In('s') && !In('t')", json!(true)),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(with_context(|ctx| dm.eval_str(expr, ctx)), Ok(expected.clone()), "{}", expr);
+        }
+        let errors = ["undeclared", "a.b <", "a.b < 1", "a.n && true", "In(s)", "a.b[-1]", "'open"];
+        for expr in errors {
+            assert!(with_context(|ctx| dm.eval_str(expr, ctx)).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_locations() {
+        let mut dm = JsonDatamodel::new();
+        dm.init(&SystemVariables::default()).unwrap();
+        with_context(|ctx| {
+            let data = Data { id: "a".to_string(), expr: None, src: None, content: Some(" [1] ".to_string()), span: Default::default() };
+            dm.init_data(&data, ctx)?;
+            dm.execute_assign("a[1]", "2", ctx)?;
+            dm.execute_assign("$.a[2].b.c", "'deep'", ctx)?;
+            dm.assign("a[0]", Value::Number(7.0), ctx)?;
+            dm.declare("a", ctx)?;
+            dm.declare("item", ctx)?;
+            dm.declare("_event", ctx)?;
+            dm.declare("nested.path", ctx)
+        })
+        .unwrap();
+        assert_eq!(dm.get("a"), Some(&json!([7, 2, {"b": {"c": "deep"}}])));
+        assert_eq!(dm.get("item"), Some(&json!(null)));
+        assert_eq!(dm.get("nested"), None);
+
+        let failures = [("a[5]", "out of bounds"), ("a.x", "member 'x'"), ("b", "not declared"), ("_event.name", "read-only"), ("a == 1", "not a path")];
+        for (location, message) in failures {
+            let error = with_context(|ctx| dm.execute_assign(location, "1", ctx)).unwrap_err();
+            assert!(matches!(error, DatamodelError::InvalidLocation(ref m) if m.contains(message)), "{}: {}", location, error);
+        }
+    }
+
+    #[test]
+    fn test_event_data_and_params() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="json">
+            <datamodel>
+                <data id="config">{"limit": 3}</data>
+                <data id="label">not json</data>
+                <data id="received" expr="[]"/>
+            </datamodel>
+            <state id="a">
+                <transition event="reading" cond="_event.data.value &lt;= config.limit">
+                    <assign location="received[0]" expr="_event.data"/>
+                    <send event="echo"><param name="copy" expr="_event.data.value"/><param name="label" expr="label"/></send>
+                </transition>
+                <transition event="echo" cond="_event.data.copy == received[0].value" target="b"/>
+            </state>
+            <state id="b"/>
+        </scxml>"#;
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::new(&scxml).unwrap();
+        interpreter.start().unwrap();
+        interpreter.send(Event::new("reading").with_data(json!({"value": 5}))).unwrap();
+        assert!(interpreter.is_in_state("a"));
+        interpreter.send(Event::new("reading").with_data(json!({"value": 2}))).unwrap();
+        assert!(interpreter.is_in_state("b"));
+        assert_eq!(interpreter.evaluate("label").unwrap(), Value::from("not json"));
+        let dm = interpreter.datamodel().downcast_ref::<JsonDatamodel>().unwrap();
+        assert_eq!(dm.get("received"), Some(&json!([{"value": 2}])));
+    }
+}
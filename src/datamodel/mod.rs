@@ -0,0 +1,294 @@
+//! Pluggable evaluation of the expressions embedded in a chart.
+//!
+//! SCXML leaves the expression language to the datamodel named by the
+//! `datamodel` attribute of `<scxml>`. Guards (`cond`), values (`expr`),
+//! locations (`location`) and `<script>` bodies are opaque strings in the parsed
+//! model; the [`Interpreter`](crate::runtime::Interpreter) hands them to a
+//! [`Datamodel`] implementation, so any expression language can be plugged in.
+//!
+//! ```rust
+//! use harel::datamodel::{Context, Datamodel, DatamodelError, SystemVariables, Value};
+//! use harel::runtime::{Event, Interpreter};
+//! use harel::{parse_scxml, Data};
+//!
+//! // A datamodel whose only guard is the literal `ready`.
+//! #[derive(Debug, Default)]
+//! struct Ready;
+//!
+//! impl Datamodel for Ready {
+//!     fn init(&mut self, _system: &SystemVariables) -> Result<(), DatamodelError> {
+//!         Ok(())
+//!     }
+//!     fn init_data(&mut self, data: &Data, _ctx: &Context) -> Result<(), DatamodelError> {
+//!         Err(DatamodelError::Unsupported(format!("<data id=\"{}\">", data.id)))
+//!     }
+//!     fn set_event(&mut self, _event: &Event) {}
+//!     fn evaluate(&mut self, expr: &str, _ctx: &Context) -> Result<Value, DatamodelError> {
+//!         match expr {
+//!             "ready" => Ok(Value::Bool(true)),
+//!             _ => Err(DatamodelError::Evaluation(expr.to_string())),
+//!         }
+//!     }
+//!     fn assign(&mut self, location: &str, _value: Value, _ctx: &Context) -> Result<(), DatamodelError> {
+//!         Err(DatamodelError::InvalidLocation(location.to_string()))
+//!     }
+//! }
+//!
+//! let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+//!     <state id="idle"><transition cond="ready" target="busy"/></state>
+//!     <state id="busy"/>
+//! </scxml>"#;
+//!
+//! let scxml = parse_scxml(xml).unwrap();
+//! let mut interpreter = Interpreter::with_datamodel(&scxml, Box::new(Ready)).unwrap();
+//! interpreter.start().unwrap();
+//! assert_eq!(interpreter.configuration(), vec!["busy"]);
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use thiserror::Error;
+
+use crate::Data;
+use crate::chart::{StateChart, StateId};
+use crate::runtime::Event;
+
+pub mod ecmascript;
+#[cfg(feature = "json-datamodel")]
+pub mod json;
+pub mod native;
+pub mod null;
+
+pub use ecmascript::EcmaScriptDatamodel;
+#[cfg(feature = "json-datamodel")]
+pub use json::JsonDatamodel;
+pub use native::NativeDatamodel;
+pub use null::NullDatamodel;
+
+/// Returns the built-in datamodel registered under a `datamodel` attribute value.
+///
+/// # Arguments
+///
+/// * `name` - The value of the `datamodel` attribute of `<scxml>`, if present.
+///
+/// # Returns
+///
+/// * `Some(datamodel)` - A fresh [`NullDatamodel`] for `null` or a missing attribute,
+///   a fresh [`EcmaScriptDatamodel`] for `ecmascript`, or, with the `json-datamodel`
+///   feature, a fresh `JsonDatamodel` for `json`.
+/// * `None` - If no built-in datamodel has that name.
+pub fn builtin(name: Option<&str>) -> Option<Box<dyn Datamodel>> {
+    match name {
+        None | Some("null") => Some(Box::new(NullDatamodel)),
+        Some("ecmascript") => Some(Box::new(EcmaScriptDatamodel::new())),
+        #[cfg(feature = "json-datamodel")]
+        Some("json") => Some(Box::new(JsonDatamodel::new())),
+        Some(_) => None,
+    }
+}
+
+/// Errors reported by a [`Datamodel`].
+///
+/// The interpreter turns any of these into an `error.execution` event.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum DatamodelError {
+    #[error("Syntax error: {0}")]
+    Syntax(String),
+    #[error("Evaluation failed: {0}")]
+    Evaluation(String),
+    #[error("Invalid location: {0}")]
+    InvalidLocation(String),
+    #[error("Not supported by this datamodel: {0}")]
+    Unsupported(String),
+}
+
+/// A datamodel-independent value, used for event data and `<param>` values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Value {
+    /// No value.
+    #[default]
+    Null,
+    /// A boolean.
+    Bool(bool),
+    /// A number.
+    Number(f64),
+    /// A string.
+    String(String),
+    /// An ordered list of values.
+    Array(Vec<Value>),
+    /// A map from names to values.
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Returns the boolean if the value is a [`Value::Bool`].
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the string if the value is a [`Value::String`].
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the field `name` if the value is a [`Value::Object`].
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.get(name),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => f.write_str(s),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", items.join(","))
+            }
+            Value::Object(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+                write!(f, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+}
+
+/// The read-only system variables every datamodel exposes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemVariables {
+    /// `_sessionid`: unique identifier of the running session.
+    pub sessionid: String,
+    /// `_name`: the `name` attribute of `<scxml>`, if any.
+    pub name: Option<String>,
+    /// `_ioprocessors`: the location of each Event I/O Processor, keyed by its type URI.
+    pub ioprocessors: BTreeMap<String, String>,
+}
+
+/// Interpreter state a datamodel may consult while evaluating, such as for `In()`.
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    chart: &'a StateChart,
+    configuration: &'a BTreeSet<StateId>,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn new(chart: &'a StateChart, configuration: &'a BTreeSet<StateId>) -> Self {
+        Self { chart, configuration }
+    }
+
+    /// Returns true if the state with the given ID is active.
+    pub fn in_state(&self, id: &str) -> bool {
+        self.chart.find(id).is_some_and(|s| self.configuration.contains(&s))
+    }
+
+    /// Returns the IDs of the active states in document order.
+    pub fn active_states(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.configuration.iter().map(|&s| self.chart.state(s).id.as_str())
+    }
+}
+
+/// An expression language the interpreter evaluates a chart's expressions with.
+///
+/// The interpreter calls [`init`](Datamodel::init) once before the chart starts,
+/// [`init_data`](Datamodel::init_data) for every `<data>` element when it is bound,
+/// [`set_event`](Datamodel::set_event) before processing each event, and the
+/// evaluation methods while taking transitions and running executable content.
+/// Errors are reported back to the chart as `error.execution`.
+pub trait Datamodel: std::any::Any + std::fmt::Debug + Send {
+    /// Binds the system variables `_sessionid`, `_name` and `_ioprocessors`.
+    fn init(&mut self, system: &SystemVariables) -> Result<(), DatamodelError>;
+
+    /// Creates the variable declared by a `<data>` element, evaluating its `expr` or `content`.
+    fn init_data(&mut self, data: &Data, ctx: &Context) -> Result<(), DatamodelError>;
+
+    /// Binds `_event` to the event about to be processed.
+    fn set_event(&mut self, event: &Event);
+
+    /// Evaluates a value expression, such as an `expr` attribute.
+    fn evaluate(&mut self, expr: &str, ctx: &Context) -> Result<Value, DatamodelError>;
+
+    /// Stores a value at a location expression.
+    fn assign(&mut self, location: &str, value: Value, ctx: &Context) -> Result<(), DatamodelError>;
+
+    /// Creates the variable named by a location if it does not exist yet, as `<foreach>` does
+    /// for its `item` and `index` before assigning them. The default does nothing, leaving
+    /// [`assign`](Datamodel::assign) to reject locations that were never declared.
+    fn declare(&mut self, _location: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        Ok(())
+    }
+
+    /// Evaluates a guard. The default converts the result of [`evaluate`](Datamodel::evaluate),
+    /// rejecting anything that is not a boolean.
+    fn evaluate_bool(&mut self, expr: &str, ctx: &Context) -> Result<bool, DatamodelError> {
+        match self.evaluate(expr, ctx)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(DatamodelError::Evaluation(format!("'{}' is not a boolean: {}", expr, other))),
+        }
+    }
+
+    /// Executes `<assign location expr>`. The default evaluates `expr` and stores the result.
+    fn execute_assign(&mut self, location: &str, expr: &str, ctx: &Context) -> Result<(), DatamodelError> {
+        let value = self.evaluate(expr, ctx)?;
+        self.assign(location, value, ctx)
+    }
+
+    /// Runs the body of a `<script>` element. Not every datamodel supports scripting.
+    fn run_script(&mut self, _script: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        Err(DatamodelError::Unsupported("<script>".to_string()))
+    }
+
+    /// Executes a custom action element, captured as [`Executable::Other`](crate::Executable::Other).
+    /// The default ignores it.
+    fn execute_custom(&mut self, _name: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        Ok(())
+    }
+}
+
+impl dyn Datamodel {
+    /// Returns the datamodel as a `T` if that is its concrete type, for reading its
+    /// data back once the interpreter has run.
+    pub fn downcast_ref<T: Datamodel>(&self) -> Option<&T> {
+        (self as &dyn std::any::Any).downcast_ref()
+    }
+
+    /// Returns the datamodel as a mutable `T` if that is its concrete type.
+    pub fn downcast_mut<T: Datamodel>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn std::any::Any).downcast_mut()
+    }
+}
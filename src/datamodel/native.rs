@@ -0,0 +1,265 @@
+//! A datamodel without an expression language, for embedding charts in Rust programs.
+//!
+//! The data lives in a user-provided context struct. Guards (`cond`) name Rust
+//! closures registered with [`NativeDatamodel::with_guard`], and `<assign>`,
+//! `<script>` and custom action elements name handlers registered with
+//! [`NativeDatamodel::with_action`]:
+//!
+//! - `cond="name"` calls the guard `name`; `In('id')`, `true` and `false` work as in the null datamodel;
+//! - `<assign location="name" .../>` calls the action `name`, ignoring `expr`;
+//! - `<script>a; b</script>` calls the actions `a` and `b` in order;
+//! - a custom element `<name/>` calls the action `name`.
+//!
+//! A reference to a name that was never registered is reported as `error.execution`
+//! with a message naming it. Value expressions and `<data>` are not supported: data
+//! belongs in the context struct.
+
+use std::collections::BTreeMap;
+
+use crate::Data;
+use crate::datamodel::{Context, Datamodel, DatamodelError, NullDatamodel, SystemVariables, Value};
+use crate::runtime::Event;
+
+type Guard<C> = Box<dyn Fn(&C, &Event) -> bool + Send>;
+type Action<C> = Box<dyn FnMut(&mut C, &Event) + Send>;
+
+/// A datamodel that runs registered Rust closures against a context struct.
+///
+/// # Examples
+///
+/// ```rust
+/// use harel::parse_scxml;
+/// use harel::datamodel::NativeDatamodel;
+/// use harel::runtime::{Event, Interpreter};
+///
+/// #[derive(Debug, Default)]
+/// struct Turnstile {
+///     coins: u32,
+/// }
+///
+/// let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+///     <state id="locked">
+///         <transition event="coin" target="unlocked"><script>count_coin</script></transition>
+///     </state>
+///     <state id="unlocked">
+///         <transition event="push" cond="paid" target="locked"/>
+///     </state>
+/// </scxml>"#;
+///
+/// let datamodel = NativeDatamodel::new(Turnstile::default())
+///     .with_guard("paid", |ctx: &Turnstile, _: &Event| ctx.coins > 0)
+///     .with_action("count_coin", |ctx: &mut Turnstile, _: &Event| ctx.coins += 1);
+///
+/// let scxml = parse_scxml(xml).unwrap();
+/// let mut interpreter = Interpreter::with_datamodel(&scxml, Box::new(datamodel)).unwrap();
+/// interpreter.start().unwrap();
+/// interpreter.send(Event::new("coin")).unwrap();
+/// interpreter.send(Event::new("push")).unwrap();
+/// assert!(interpreter.is_in_state("locked"));
+///
+/// let turnstile = interpreter.datamodel().downcast_ref::<NativeDatamodel<Turnstile>>().unwrap();
+/// assert_eq!(turnstile.context().coins, 1);
+/// ```
+pub struct NativeDatamodel<C> {
+    context: C,
+    guards: BTreeMap<String, Guard<C>>,
+    actions: BTreeMap<String, Action<C>>,
+    // The event being processed, passed to guards and actions.
+    event: Event,
+}
+
+impl<C> NativeDatamodel<C> {
+    /// Creates a datamodel over the given context, with no guards or actions registered.
+    pub fn new(context: C) -> Self {
+        Self { context, guards: BTreeMap::new(), actions: BTreeMap::new(), event: Event::new("") }
+    }
+
+    /// Registers a guard, replacing any previous guard with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The `cond` value that selects the guard.
+    /// * `guard` - Decides whether the transition is enabled.
+    pub fn with_guard(mut self, name: impl Into<String>, guard: impl Fn(&C, &Event) -> bool + Send + 'static) -> Self {
+        self.guards.insert(name.into(), Box::new(guard));
+        self
+    }
+
+    /// Registers an action, replacing any previous action with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The `<assign>` location, `<script>` entry or custom element name that runs the action.
+    /// * `action` - Updates the context.
+    pub fn with_action(mut self, name: impl Into<String>, action: impl FnMut(&mut C, &Event) + Send + 'static) -> Self {
+        self.actions.insert(name.into(), Box::new(action));
+        self
+    }
+
+    /// Returns the context struct.
+    pub fn context(&self) -> &C {
+        &self.context
+    }
+
+    /// Returns the context struct mutably.
+    pub fn context_mut(&mut self) -> &mut C {
+        &mut self.context
+    }
+
+    /// Consumes the datamodel and returns the context struct.
+    pub fn into_context(self) -> C {
+        self.context
+    }
+
+    // Helper to run the action registered under `name`.
+    fn run_action(&mut self, name: &str, element: &str) -> Result<(), DatamodelError> {
+        let action = self
+            .actions
+            .get_mut(name)
+            .ok_or_else(|| DatamodelError::Evaluation(format!("no action registered as '{}' (referenced by {})", name, element)))?;
+        action(&mut self.context, &self.event);
+        Ok(())
+    }
+}
+
+impl<C: std::fmt::Debug> std::fmt::Debug for NativeDatamodel<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeDatamodel")
+            .field("context", &self.context)
+            .field("guards", &self.guards.keys().collect::<Vec<_>>())
+            .field("actions", &self.actions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<C: std::fmt::Debug + Send + 'static> Datamodel for NativeDatamodel<C> {
+    fn init(&mut self, _system: &SystemVariables) -> Result<(), DatamodelError> {
+        Ok(())
+    }
+
+    fn init_data(&mut self, data: &Data, _ctx: &Context) -> Result<(), DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("<data id=\"{}\"> in the native datamodel; use a context field instead", data.id)))
+    }
+
+    fn set_event(&mut self, event: &Event) {
+        self.event = event.clone();
+    }
+
+    fn evaluate(&mut self, expr: &str, _ctx: &Context) -> Result<Value, DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("value expression '{}' in the native datamodel", expr)))
+    }
+
+    fn assign(&mut self, location: &str, _value: Value, _ctx: &Context) -> Result<(), DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("assigning a value to '{}' in the native datamodel", location)))
+    }
+
+    fn declare(&mut self, location: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("declaring '{}' in the native datamodel", location)))
+    }
+
+    fn evaluate_bool(&mut self, expr: &str, ctx: &Context) -> Result<bool, DatamodelError> {
+        let name = expr.trim();
+        if let Some(guard) = self.guards.get(name) {
+            return Ok(guard(&self.context, &self.event));
+        }
+        let is_in_call = name.strip_prefix("In").is_some_and(|rest| rest.trim_start().starts_with('('));
+        if matches!(name, "true" | "false") || is_in_call {
+            return NullDatamodel.evaluate_bool(name, ctx);
+        }
+        Err(DatamodelError::Evaluation(format!("no guard registered as '{}'", name)))
+    }
+
+    fn execute_assign(&mut self, location: &str, _expr: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        self.run_action(location.trim(), "<assign>")
+    }
+
+    fn run_script(&mut self, script: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        for name in script.split([';', '\n']).map(str::trim).filter(|name| !name.is_empty()) {
+            self.run_action(name, "<script>")?;
+        }
+        Ok(())
+    }
+
+    fn execute_custom(&mut self, name: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        self.run_action(name, &format!("<{}>", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_scxml;
+    use crate::runtime::Interpreter;
+
+    #[derive(Debug, Default)]
+    struct Counter {
+        count: u32,
+        last: String,
+    }
+
+    #[test]
+    fn test_guards_and_actions() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="counting">
+                <onentry><reset/></onentry>
+                <transition event="tick" cond="below_limit"><assign location="increment" expr="ignored"/></transition>
+                <transition event="tick" cond="In('counting')" target="done"><script>remember; increment</script></transition>
+            </state>
+            <final id="done"/>
+        </scxml>"#;
+        let datamodel = NativeDatamodel::new(Counter { count: 7, last: String::new() })
+            .with_guard("below_limit", |ctx: &Counter, _: &Event| ctx.count < 2)
+            .with_action("increment", |ctx: &mut Counter, _: &Event| ctx.count += 1)
+            .with_action("remember", |ctx: &mut Counter, event: &Event| ctx.last = event.name.clone())
+            .with_action("reset", |ctx: &mut Counter, _: &Event| ctx.count = 0);
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::with_datamodel(&scxml, Box::new(datamodel)).unwrap();
+        interpreter.start().unwrap();
+        for _ in 0..3 {
+            interpreter.send(Event::new("tick")).unwrap();
+        }
+        assert!(!interpreter.is_running());
+        let counter = interpreter.datamodel().downcast_ref::<NativeDatamodel<Counter>>().unwrap().context();
+        assert_eq!(counter.count, 3);
+        assert_eq!(counter.last, "tick");
+    }
+
+    #[test]
+    fn test_unregistered_names_raise_errors() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="a">
+                <transition event="go" cond="missing_guard" target="wrong"/>
+                <transition event="error.execution" target="b"/>
+            </state>
+            <state id="b">
+                <onentry><custom_action/></onentry>
+                <transition event="error.execution" target="c"/>
+            </state>
+            <state id="c"/>
+            <state id="wrong"/>
+        </scxml>"#;
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::with_datamodel(&scxml, Box::new(NativeDatamodel::new(Counter::default()))).unwrap();
+        interpreter.start().unwrap();
+        interpreter.send(Event::new("go")).unwrap();
+        assert_eq!(interpreter.configuration(), vec!["c"]);
+
+        let mut datamodel = NativeDatamodel::new(Counter::default());
+        let chart = crate::chart::StateChart::compile(&scxml).unwrap();
+        let configuration = Default::default();
+        let ctx = Context::new(&chart, &configuration);
+        assert_eq!(
+            datamodel.evaluate_bool("missing_guard", &ctx),
+            Err(DatamodelError::Evaluation("no guard registered as 'missing_guard'".to_string()))
+        );
+        assert_eq!(
+            datamodel.evaluate_bool("Inventory_low", &ctx),
+            Err(DatamodelError::Evaluation("no guard registered as 'Inventory_low'".to_string()))
+        );
+        assert_eq!(datamodel.evaluate_bool("In ('a')", &ctx), Ok(false));
+        assert_eq!(
+            datamodel.run_script("missing_action", &ctx),
+            Err(DatamodelError::Evaluation("no action registered as 'missing_action' (referenced by <script>)".to_string()))
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! The `null` datamodel, used when `<scxml>` declares `datamodel="null"` or none at all.
+//!
+//! It has no data: the only expressions it understands are the `In('stateId')`
+//! predicate, which tests the current configuration, and the literals `true`
+//! and `false`. Anything else, and every `<data>`, `<assign>` or `<script>`,
+//! is reported as an error so the chart receives `error.execution`.
+
+use crate::Data;
+use crate::datamodel::{Context, Datamodel, DatamodelError, SystemVariables, Value};
+use crate::runtime::Event;
+
+/// The SCXML null datamodel.
+///
+/// # Examples
+///
+/// ```rust
+/// use harel::parse_scxml;
+/// use harel::datamodel::NullDatamodel;
+/// use harel::runtime::{Event, Interpreter};
+///
+/// let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+///     <parallel id="p">
+///         <state id="light"><state id="on"/></state>
+///         <state id="door">
+///             <state id="closed"><transition event="open" cond="In('on')" target="opened"/></state>
+///             <state id="opened"/>
+///         </state>
+///     </parallel>
+/// </scxml>"#;
+///
+/// let scxml = parse_scxml(xml).unwrap();
+/// let mut interpreter = Interpreter::with_datamodel(&scxml, Box::new(NullDatamodel)).unwrap();
+/// interpreter.start().unwrap();
+/// interpreter.send(Event::new("open")).unwrap();
+/// assert!(interpreter.is_in_state("opened"));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullDatamodel;
+
+impl Datamodel for NullDatamodel {
+    fn init(&mut self, _system: &SystemVariables) -> Result<(), DatamodelError> {
+        Ok(())
+    }
+
+    fn init_data(&mut self, data: &Data, _ctx: &Context) -> Result<(), DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("<data id=\"{}\"> in the null datamodel", data.id)))
+    }
+
+    fn set_event(&mut self, _event: &Event) {}
+
+    fn evaluate(&mut self, expr: &str, _ctx: &Context) -> Result<Value, DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("value expression '{}' in the null datamodel", expr)))
+    }
+
+    fn assign(&mut self, location: &str, _value: Value, _ctx: &Context) -> Result<(), DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("location '{}' in the null datamodel", location)))
+    }
+
+    fn declare(&mut self, location: &str, _ctx: &Context) -> Result<(), DatamodelError> {
+        Err(DatamodelError::Unsupported(format!("location '{}' in the null datamodel", location)))
+    }
+
+    fn evaluate_bool(&mut self, expr: &str, ctx: &Context) -> Result<bool, DatamodelError> {
+        let expr = expr.trim();
+        match expr {
+            "true" => return Ok(true),
+            "false" => return Ok(false),
+            _ => {}
+        }
+        let argument = expr
+            .strip_prefix("In")
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix('('))
+            .and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(|| DatamodelError::Syntax(format!("'{}': the null datamodel only supports In('stateId')", expr)))?;
+        let id = parse_string_literal(argument.trim())
+            .ok_or_else(|| DatamodelError::Syntax(format!("'{}': In() expects a quoted state ID", expr)))?;
+        Ok(ctx.in_state(id))
+    }
+}
+
+// Helper to unquote a single- or double-quoted string literal.
+fn parse_string_literal(literal: &str) -> Option<&str> {
+    ['\'', '"'].iter().find_map(|&quote| {
+        let inner = literal.strip_prefix(quote)?.strip_suffix(quote)?;
+        (!inner.contains(quote)).then_some(inner)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_scxml;
+    use crate::runtime::Interpreter;
+
+    fn start(xml: &str) -> Interpreter {
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::with_datamodel(&scxml, Box::new(NullDatamodel)).unwrap();
+        interpreter.start().unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn test_in_predicate() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <parallel id="p">
+                <state id="x"><state id="x1"/></state>
+                <state id="y">
+                    <state id="y1">
+                        <transition event="go" cond="In('missing')" target="wrong"/>
+                        <transition event="go" cond='In ( "x1" )' target="y2"/>
+                    </state>
+                    <state id="y2"/>
+                    <state id="wrong"/>
+                </state>
+            </parallel>
+        </scxml>"#);
+        interpreter.send(Event::new("go")).unwrap();
+        assert_eq!(interpreter.configuration(), vec!["p", "x", "x1", "y", "y2"]);
+    }
+
+    #[test]
+    fn test_other_expressions_raise_errors() {
+        let interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="null">
+            <state id="a">
+                <transition cond="count &gt; 1" target="wrong"/>
+                <transition event="error.execution" target="b"/>
+            </state>
+            <state id="b">
+                <onentry><assign location="x" expr="1"/></onentry>
+                <transition event="error.execution" target="c"/>
+            </state>
+            <state id="c"/>
+            <state id="wrong"/>
+        </scxml>"#);
+        assert_eq!(interpreter.configuration(), vec!["c"]);
+
+        let mut null = NullDatamodel;
+        let scxml = parse_scxml(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0"><state id="s"/></scxml>"#).unwrap();
+        let chart = crate::chart::StateChart::compile(&scxml).unwrap();
+        let configuration = Default::default();
+        let ctx = Context::new(&chart, &configuration);
+        assert!(matches!(null.evaluate_bool("In(s)", &ctx), Err(DatamodelError::Syntax(_))));
+        assert!(matches!(null.evaluate_bool("In('s') && true", &ctx), Err(DatamodelError::Syntax(_))));
+        assert_eq!(null.evaluate_bool(" In('s') ", &ctx), Ok(false));
+    }
+}
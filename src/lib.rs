@@ -9,10 +9,14 @@
 //! ## Key Features
 //!
 //! - **Parsing**: Parse SCXML from strings or files into structured Rust types, with support for both strict and relaxed namespace handling.
-//! - **Validation**: Perform structural and semantic validation to ensure compliance with the SCXML specification, including checks for unique IDs, valid transition targets, and datamodel constraints.
+//! - **Validation**: Perform structural and semantic validation to ensure compliance with the SCXML specification, including checks for unique IDs, valid transition targets, and datamodel constraints. `validate_all` reports every problem at once as diagnostics with a severity, rule code and element path.
 //! - **Serialization**: Convert parsed SCXML structures back to well-formatted XML strings, preserving the original structure and attributes.
 //! - **Relaxed Parsing Mode**: Optionally parse SCXML documents without requiring namespace declarations, useful for legacy or non-standard files.
 //! - **Comprehensive Element Support**: Handles core SCXML elements, transitions, data models, executable content, and external invocations.
+//! - **Compiled Model**: Flatten a parsed document into an indexed [`chart::StateChart`] with parent/child links, depth and document order.
+//! - **Execution**: Run parsed state machines with the [`runtime::Interpreter`], which follows the W3C interpretation algorithm.
+//! - **Pluggable Datamodels**: Evaluate `cond`, `expr`, `location` and `<script>` through the [`datamodel::Datamodel`] trait. The `null` and `ecmascript` datamodels are built in and selected from the `datamodel` attribute; [`datamodel::NativeDatamodel`] runs charts against a Rust struct with registered guards and actions. With the `json-datamodel` cargo feature, `datamodel="json"` evaluates a small path and comparison language over a `serde_json::Value` tree.
+//! - **Sessions and Communication**: `<invoke type="scxml">` runs child sessions, other invoke types are started by a registered [`runtime::InvokeHandler`], and `<send>` reaches the parent, invoked children and other sessions by ID through the SCXML Event I/O Processor. With the `http-ioprocessor` cargo feature, `<send>` also POSTs events over HTTP and `runtime::http::HttpListener` turns incoming POSTs into external events. With the `tokio` feature, `runtime::session::Session` runs a chart as a tokio task with an async event inbox.
 //!
 //! ## Usage
 //!
@@ -63,58 +67,146 @@
 //!
 //! - Currently supports SCXML 1.0 only; future versions may add support for later drafts or extensions.
 //! - Custom or unsupported executable elements are captured as `Executable::Other` for forward compatibility.
-//! - No runtime interpretation of SCXML state machines; this crate focuses on parsing, validation, and serialization.
+//! - The built-in `ecmascript` datamodel is a subset of the language: functions cannot be defined, and objects and arrays are copied by value. Other datamodels (such as `xpath`) must be supplied through `Interpreter::with_datamodel`.
 
 use roxmltree::{Document, Node};
 use thiserror::Error;
 
+pub mod chart;
+pub mod datamodel;
+pub mod runtime;
+
 /// Errors that can occur during SCXML parsing.
+///
+/// Every variant carries the position of the offending element; see [`ParseError::span`].
 #[derive(Debug, Error)]
 pub enum ParseError {
     #[error("Invalid XML: {0}")]
     InvalidXml(#[from] roxmltree::Error),
-    #[error("Missing required attribute: {0}")]
-    MissingAttribute(String),
-    #[error("Invalid structure: {0}")]
-    InvalidStructure(String),
-    #[error("Invalid namespace: expected {0}")]
-    InvalidNamespace(String),
+    #[error("Missing required attribute: {0} at {1}")]
+    MissingAttribute(String, Span),
+    #[error("Invalid structure: {0} at {1}")]
+    InvalidStructure(String, Span),
+    #[error("Invalid namespace: expected {0} at {1}")]
+    InvalidNamespace(String, Span),
+}
+
+impl ParseError {
+    /// Returns the source position of the error.
+    ///
+    /// For [`ParseError::InvalidXml`] only the line and column reported by the XML parser are known,
+    /// so the byte range is empty.
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::InvalidXml(e) => {
+                let pos = e.pos();
+                Span { start: 0, end: 0, line: pos.row, column: pos.col }
+            }
+            ParseError::MissingAttribute(_, span)
+            | ParseError::InvalidStructure(_, span)
+            | ParseError::InvalidNamespace(_, span) => *span,
+        }
+    }
 }
 
 /// Errors that can occur during SCXML validation.
+///
+/// Every variant carries the position of the offending element; see [`ValidationError::span`].
 #[derive(Debug, Error)]
 pub enum ValidationError {
-    #[error("Duplicate state ID: {0}")]
-    DuplicateId(String),
-    #[error("Invalid transition target: {0}")]
-    InvalidTarget(String),
-    #[error("Circular initial state reference")]
-    CircularInitial,
-    #[error("Invalid datamodel constraint: {0}")]
-    InvalidDatamodel(String),
-    #[error("Missing required element: {0}")]
-    MissingElement(String),
+    #[error("Duplicate state ID: {0} at {1}")]
+    DuplicateId(String, Span),
+    #[error("Invalid transition target: {0} at {1}")]
+    InvalidTarget(String, Span),
+    #[error("Circular initial state reference at {0}")]
+    CircularInitial(Span),
+    #[error("Invalid datamodel constraint: {0} at {1}")]
+    InvalidDatamodel(String, Span),
+    #[error("Missing required element: {0} at {1}")]
+    MissingElement(String, Span),
+    #[error("Invalid <donedata> in final state {0} at {1}: <content> and <param> are mutually exclusive")]
+    InvalidDoneData(String, Span),
+    #[error("Invalid <cancel> at {0}: exactly one of sendid and sendidexpr is required")]
+    InvalidCancel(Span),
+}
+
+impl ValidationError {
+    /// Returns the source position of the element that failed validation.
+    pub fn span(&self) -> Span {
+        match self {
+            ValidationError::CircularInitial(span) | ValidationError::InvalidCancel(span) => *span,
+            ValidationError::DuplicateId(_, span)
+            | ValidationError::InvalidTarget(_, span)
+            | ValidationError::InvalidDatamodel(_, span)
+            | ValidationError::MissingElement(_, span)
+            | ValidationError::InvalidDoneData(_, span) => *span,
+        }
+    }
 }
 
 const SCXML_NS: &str = "http://www.w3.org/2005/07/scxml";
 
+/// The position of an element in the source document.
+///
+/// Holds the byte range of the element and the 1-based line and column of its start.
+/// Values built in code rather than parsed use `Span::default()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    /// Byte offset of the start of the element.
+    pub start: usize,
+    /// Byte offset just past the end of the element.
+    pub end: usize,
+    /// 1-based line of the start of the element.
+    pub line: u32,
+    /// 1-based column of the start of the element.
+    pub column: u32,
+}
+
+impl Span {
+    /// Returns the byte range of the element.
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+
+    // Helper to compute the span of a parsed node.
+    fn of(node: &Node) -> Span {
+        let range = node.range();
+        let pos = node.document().text_pos_at(range.start);
+        Span { start: range.start, end: range.end, line: pos.row, column: pos.col }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 /// Represents the root `<scxml>` element, containing the overall state machine definition.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Scxml {
     /// The SCXML version (must be "1.0").
     pub version: String,
     /// The ID of the initial state or substate.
     pub initial: Option<String>,
+    /// The name of the state machine.
+    pub name: Option<String>,
     /// The datamodel type (e.g., "ecmascript").
     pub datamodel: Option<String>,
+    /// The data binding strategy ("early" or "late").
+    pub binding: Option<String>,
     /// Child states, parallels, finals, or histories.
     pub states: Vec<StateLike>,
     /// Data elements within the `<datamodel>`.
     pub datamodel_elements: Vec<Data>,
+    /// IDs synthesized for anonymous states when [`ParseOptions::generate_ids`] is set, in document order.
+    pub generated_ids: Vec<String>,
+    /// Position of the `<scxml>` element.
+    pub span: Span,
 }
 
 /// Enum representing state-like elements: `<state>`, `<parallel>`, `<final>`, or `<history>`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StateLike {
     State(State),
     Parallel(Parallel),
@@ -123,7 +215,7 @@ pub enum StateLike {
 }
 
 /// Represents a `<state>` element, which can contain substates and transitions.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct State {
     /// Unique identifier for the state.
     pub id: Option<String>,
@@ -141,10 +233,14 @@ pub struct State {
     pub children: Vec<StateLike>,
     /// Invoke elements for external processes.
     pub invokes: Vec<Invoke>,
+    /// Data elements within a state-level `<datamodel>`.
+    pub datamodel: Vec<Data>,
+    /// Position of the element in the source document.
+    pub span: Span,
 }
 
 /// Represents a `<parallel>` element for concurrent substates.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Parallel {
     /// Unique identifier for the parallel region.
     pub id: Option<String>,
@@ -158,10 +254,14 @@ pub struct Parallel {
     pub children: Vec<StateLike>,
     /// Invoke elements for external processes.
     pub invokes: Vec<Invoke>,
+    /// Data elements within a state-level `<datamodel>`.
+    pub datamodel: Vec<Data>,
+    /// Position of the element in the source document.
+    pub span: Span,
 }
 
 /// Represents a `<final>` element, indicating an end state.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Final {
     /// Unique identifier for the final state.
     pub id: Option<String>,
@@ -169,13 +269,29 @@ pub struct Final {
     pub onentry: Vec<Executable>,
     /// Executable content on exit.
     pub onexit: Vec<Executable>,
+    /// Data included in the `done.state.*` event raised when the final state is entered.
+    pub donedata: Option<DoneData>,
+    /// Position of the element in the source document.
+    pub span: Span,
+}
+
+/// Represents a `<donedata>` element within `<final>`.
+///
+/// The specification allows either a single `<content>` or any number of `<param>`s, not both;
+/// [`validate`] enforces this.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DoneData {
+    /// `<content>` child providing the whole payload.
+    pub content: Option<Content>,
+    /// `<param>` children providing named payload fields.
+    pub params: Vec<Param>,
 }
 
 /// Represents a `<transition>` element.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Transition {
-    /// Event descriptor that triggers the transition.
-    pub event: Option<String>,
+    /// Event descriptors that trigger the transition.
+    pub event: Option<EventDescriptor>,
     /// Condition expression that must evaluate to true.
     pub cond: Option<String>,
     /// Target state ID(s) (space-separated for multiple).
@@ -184,10 +300,77 @@ pub struct Transition {
     pub type_: Option<String>,
     /// Executable content within the transition.
     pub executables: Vec<Executable>,
+    /// Position of the element in the source document.
+    pub span: Span,
+}
+
+/// The parsed `event` attribute of a `<transition>`: a list of event descriptors.
+///
+/// A descriptor matches an event name if it is `*`, or if its dot-separated tokens are a prefix of
+/// the event name's tokens. `DIGIT` therefore matches `DIGIT` and `DIGIT.0` but not `DIGITS`; a
+/// trailing `.*` or `.` is ignored, so `DIGIT.*` behaves like `DIGIT`. The transition matches if any
+/// descriptor in the list does.
+///
+/// # Examples
+///
+/// ```rust
+/// use harel::EventDescriptor;
+///
+/// let descriptor: EventDescriptor = "DIGIT OPER.*".parse().unwrap();
+/// assert!(descriptor.matches("DIGIT.7"));
+/// assert!(descriptor.matches("OPER.MINUS"));
+/// assert!(!descriptor.matches("DIGITS"));
+/// assert_eq!(descriptor.to_string(), "DIGIT OPER.*");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EventDescriptor {
+    descriptors: Vec<String>,
+}
+
+impl EventDescriptor {
+    /// Parses a space-separated list of event descriptors.
+    pub fn new(descriptors: &str) -> Self {
+        EventDescriptor { descriptors: descriptors.split_whitespace().map(str::to_string).collect() }
+    }
+
+    /// Returns true if the event named `event_name` matches any descriptor in the list.
+    pub fn matches(&self, event_name: &str) -> bool {
+        self.descriptors.iter().any(|descriptor| {
+            let prefix = descriptor.trim_end_matches(".*").trim_end_matches('.');
+            prefix == "*"
+                || event_name == prefix
+                || (event_name.starts_with(prefix) && event_name.as_bytes().get(prefix.len()) == Some(&b'.'))
+        })
+    }
+
+    /// Iterates over the descriptors as written in the document.
+    pub fn descriptors(&self) -> impl Iterator<Item = &str> {
+        self.descriptors.iter().map(String::as_str)
+    }
+}
+
+impl std::str::FromStr for EventDescriptor {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(EventDescriptor::new(s))
+    }
+}
+
+impl From<&str> for EventDescriptor {
+    fn from(s: &str) -> Self {
+        EventDescriptor::new(s)
+    }
+}
+
+impl std::fmt::Display for EventDescriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.descriptors.join(" "))
+    }
 }
 
 /// Represents a `<data>` element in the datamodel.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Data {
     /// Unique identifier for the data item.
     pub id: String,
@@ -197,10 +380,12 @@ pub struct Data {
     pub src: Option<String>,
     /// Inline content for data.
     pub content: Option<String>,
+    /// Position of the element in the source document.
+    pub span: Span,
 }
 
 /// Represents an `<initial>` element within a compound state.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Initial {
     /// Optional ID for the initial pseudo-state.
     pub id: Option<String>,
@@ -209,7 +394,7 @@ pub struct Initial {
 }
 
 /// Represents a `<history>` pseudo-state.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct History {
     /// Unique identifier for the history state.
     pub id: Option<String>,
@@ -217,27 +402,41 @@ pub struct History {
     pub type_: String,
     /// Default transition for history.
     pub transition: Option<Transition>,
+    /// Position of the element in the source document.
+    pub span: Span,
 }
 
 /// Represents an `<invoke>` element for external processes.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Invoke {
     /// Type of the invoked process (e.g., "scxml", "vxml3").
     pub type_: String,
+    /// Expression evaluating to the type of the invoked process.
+    pub typeexpr: Option<String>,
     /// Source URL of the invoked document.
     pub src: Option<String>,
+    /// Expression evaluating to the source URL.
+    pub srcexpr: Option<String>,
     /// Unique identifier for the invocation.
     pub id: Option<String>,
+    /// Location in the datamodel receiving a generated invoke ID.
+    pub idlocation: Option<String>,
+    /// Space-separated datamodel locations whose values are passed to the invoked process.
+    pub namelist: Option<String>,
+    /// If true, every external event is forwarded to the invoked process.
+    pub autoforward: bool,
     /// Parameters passed to the invocation.
     pub params: Vec<Param>,
     /// Finalize executable content.
     pub finalize: Option<Finalize>,
     /// Inline content for the invocation.
     pub content: Option<Content>,
+    /// Position of the element in the source document.
+    pub span: Span,
 }
 
-/// Represents a `<param>` element within `<invoke>`.
-#[derive(Debug, Clone)]
+/// Represents a `<param>` element within `<invoke>`, `<send>` or `<donedata>`.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Param {
     /// Parameter name.
     pub name: String,
@@ -248,42 +447,122 @@ pub struct Param {
 }
 
 /// Represents a `<finalize>` element within `<invoke>`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Finalize {
     /// Executable content to finalize the invocation.
     pub executables: Vec<Executable>,
 }
 
 /// Represents a `<content>` element.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Content {
     /// Expression for content.
     pub expr: Option<String>,
-    /// Inline content.
-    pub content: Option<String>,
+    /// Inline content, as text or as markup.
+    pub content: Option<ContentBody>,
+}
+
+/// The inline body of a `<content>` element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentBody {
+    /// Character data, with entities resolved.
+    Text(String),
+    /// The source markup of the children when they include elements, such as an inline
+    /// `<scxml>` document. Text around the elements is kept, and namespace prefixes the
+    /// elements inherit from their ancestors are declared on them.
+    Markup(String),
+}
+
+impl ContentBody {
+    /// Returns the text, or the markup as written.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContentBody::Text(text) | ContentBody::Markup(text) => text,
+        }
+    }
+}
+
+/// Represents a `<send>` element, which sends an event to a target.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SendElement {
+    /// Name of the event to send.
+    pub event: Option<String>,
+    /// Expression evaluating to the event name.
+    pub eventexpr: Option<String>,
+    /// URI of the destination.
+    pub target: Option<String>,
+    /// Expression evaluating to the destination URI.
+    pub targetexpr: Option<String>,
+    /// URI of the Event I/O Processor to use.
+    pub type_: Option<String>,
+    /// Expression evaluating to the Event I/O Processor URI.
+    pub typeexpr: Option<String>,
+    /// Identifier of the send, usable by `<cancel>`.
+    pub id: Option<String>,
+    /// Location in the datamodel receiving a generated send ID.
+    pub idlocation: Option<String>,
+    /// Delay before dispatching the event, as a CSS2 time (e.g. "5s").
+    pub delay: Option<String>,
+    /// Expression evaluating to the delay.
+    pub delayexpr: Option<String>,
+    /// Space-separated datamodel locations whose values are included in the event data.
+    pub namelist: Option<String>,
+    /// `<param>` children included in the event data.
+    pub params: Vec<Param>,
+    /// `<content>` child used as the event data.
+    pub content: Option<Content>,
+    /// Position of the element in the source document.
+    pub span: Span,
+}
+
+/// A conditional branch of an `<if>`, introduced by `<if>` itself or by `<elseif>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfBranch {
+    /// Condition guarding the branch.
+    pub cond: String,
+    /// Executable content run when the condition is the first to hold.
+    pub body: Vec<Executable>,
 }
 
 /// Enum representing executable content elements.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Executable {
     /// `<raise>` to raise an event.
-    Raise { event: String },
-    /// `<if>` conditional.
-    If { cond: String, then: Vec<Executable>, else_: Vec<Executable> },
+    Raise { event: String, span: Span },
+    /// `<if>` conditional: the `<if>` and `<elseif>` branches in order, then the optional `<else>` block.
+    If { branches: Vec<IfBranch>, else_: Option<Vec<Executable>>, span: Span },
     /// `<foreach>` loop.
-    Foreach { array: String, item: String, index: Option<String>, body: Vec<Executable> },
+    Foreach { array: String, item: String, index: Option<String>, body: Vec<Executable>, span: Span },
     /// `<send>` to send an event.
-    Send { event: String, target: Option<String> /* Additional attributes can be added */ },
+    Send(Box<SendElement>),
     /// `<script>` for embedded scripts.
-    Script { src: Option<String>, content: Option<String> },
+    Script { src: Option<String>, content: Option<String>, span: Span },
     /// `<assign>` to update data.
-    Assign { location: String, expr: String },
+    Assign { location: String, expr: String, span: Span },
     /// `<log>` for logging.
-    Log { label: Option<String>, expr: String },
-    /// `<cancel>` to cancel a send.
-    Cancel { sendid: String },
-    /// Placeholder for unsupported or custom executables.
-    Other(String),
+    Log { label: Option<String>, expr: String, span: Span },
+    /// `<cancel>` to cancel a delayed send, by its ID or by an expression evaluating to it.
+    Cancel { sendid: Option<String>, sendidexpr: Option<String>, span: Span },
+    /// Unsupported or custom executables, holding the element name and its source markup,
+    /// which [`to_xml`] writes back unchanged.
+    Other { name: String, markup: String, span: Span },
+}
+
+impl Executable {
+    /// Returns the position of the element in the source document.
+    pub fn span(&self) -> Span {
+        match self {
+            Executable::Send(send) => send.span,
+            Executable::Raise { span, .. }
+            | Executable::If { span, .. }
+            | Executable::Foreach { span, .. }
+            | Executable::Script { span, .. }
+            | Executable::Assign { span, .. }
+            | Executable::Log { span, .. }
+            | Executable::Cancel { span, .. }
+            | Executable::Other { span, .. } => *span,
+        }
+    }
 }
 
 /// Parses an SCXML document from a string using default options.
@@ -317,18 +596,13 @@ pub fn parse_scxml(xml: &str) -> Result<Scxml, ParseError> {
 }
 
 /// Options for customizing SCXML parsing behavior.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ParseOptions {
     /// If true, allows parsing without strict namespace checking.
     pub relaxed_namespace: bool,
-}
-
-impl Default for ParseOptions {
-    fn default() -> Self {
-        Self {
-            relaxed_namespace: false,
-        }
-    }
+    /// If true, assigns a unique ID to every `<state>`, `<parallel>`, `<final>` and `<history>`
+    /// without one, derived from its position in the document (e.g. `_state_0_2`).
+    pub generate_ids: bool,
 }
 
 /// Parses an SCXML document from a string with custom options.
@@ -354,7 +628,7 @@ impl Default for ParseOptions {
 ///     <state id="start"/>
 /// </scxml>"#;
 ///
-/// let options = ParseOptions { relaxed_namespace: true };
+/// let options = ParseOptions { relaxed_namespace: true, ..Default::default() };
 /// let scxml = parse_scxml_with_options(xml, options).expect("Failed to parse SCXML");
 /// assert_eq!(scxml.version, "1.0");
 /// ```
@@ -364,26 +638,29 @@ pub fn parse_scxml_with_options(xml: &str, options: ParseOptions) -> Result<Scxm
     let root = doc.root_element();
 
     // Validate namespace if not in relaxed mode.
-    if !options.relaxed_namespace {
-        if root.tag_name().namespace() != Some(SCXML_NS) {
-            return Err(ParseError::InvalidNamespace(SCXML_NS.to_string()));
-        }
+    if !options.relaxed_namespace && root.tag_name().namespace() != Some(SCXML_NS) {
+        return Err(ParseError::InvalidNamespace(SCXML_NS.to_string(), Span::of(&root)));
     }
 
     // Ensure the root element is <scxml>.
     if root.tag_name().name() != "scxml" {
-        return Err(ParseError::InvalidStructure("Root must be <scxml>".into()));
+        return Err(ParseError::InvalidStructure("Root must be <scxml>".into(), Span::of(&root)));
     }
 
     // Extract required version attribute.
-    let version = root.attribute("version").ok_or(ParseError::MissingAttribute("version".into()))?.to_string();
+    let version = root
+        .attribute("version")
+        .ok_or_else(|| ParseError::MissingAttribute("version".into(), Span::of(&root)))?
+        .to_string();
     if version != "1.0" {
-        return Err(ParseError::InvalidStructure("SCXML version must be 1.0".into()));
+        return Err(ParseError::InvalidStructure("SCXML version must be 1.0".into(), Span::of(&root)));
     }
 
     // Extract optional attributes.
     let initial = root.attribute("initial").map(|s| s.to_string());
+    let name = root.attribute("name").map(|s| s.to_string());
     let datamodel = root.attribute("datamodel").map(|s| s.to_string());
+    let binding = root.attribute("binding").map(|s| s.to_string());
 
     let mut states = Vec::new();
     let mut datamodel_elements = Vec::new();
@@ -403,7 +680,81 @@ pub fn parse_scxml_with_options(xml: &str, options: ParseOptions) -> Result<Scxm
         }
     }
 
-    Ok(Scxml { version, initial, datamodel, states, datamodel_elements })
+    let mut scxml = Scxml {
+        version,
+        initial,
+        name,
+        datamodel,
+        binding,
+        states,
+        datamodel_elements,
+        generated_ids: Vec::new(),
+        span: Span::of(&root),
+    };
+    if options.generate_ids {
+        generate_state_ids(&mut scxml);
+    }
+    Ok(scxml)
+}
+
+// Helper to assign path-based IDs to anonymous states, avoiding every ID already in the document.
+fn generate_state_ids(scxml: &mut Scxml) {
+    let mut taken = std::collections::HashSet::new();
+    collect_explicit_ids(&scxml.states, &mut taken);
+    taken.extend(scxml.datamodel_elements.iter().map(|d| d.id.clone()));
+
+    let mut generated = Vec::new();
+    assign_generated_ids(&mut scxml.states, "", &mut taken, &mut generated);
+    scxml.generated_ids = generated;
+}
+
+// Helper to recursively collect the IDs written in the document.
+fn collect_explicit_ids(states: &[StateLike], taken: &mut std::collections::HashSet<String>) {
+    for state in states {
+        let (id, children) = match state {
+            StateLike::State(s) => (&s.id, s.children.as_slice()),
+            StateLike::Parallel(p) => (&p.id, p.children.as_slice()),
+            StateLike::Final(f) => (&f.id, &[][..]),
+            StateLike::History(h) => (&h.id, &[][..]),
+        };
+        if let Some(id) = id {
+            taken.insert(id.clone());
+        }
+        collect_explicit_ids(children, taken);
+    }
+}
+
+// Helper to recursively name anonymous states after their child-index path.
+fn assign_generated_ids(
+    states: &mut [StateLike],
+    path: &str,
+    taken: &mut std::collections::HashSet<String>,
+    generated: &mut Vec<String>,
+) {
+    for (index, state) in states.iter_mut().enumerate() {
+        let child_path = format!("{}_{}", path, index);
+        let (tag, id, children) = match state {
+            StateLike::State(s) => ("state", &mut s.id, Some(&mut s.children)),
+            StateLike::Parallel(p) => ("parallel", &mut p.id, Some(&mut p.children)),
+            StateLike::Final(f) => ("final", &mut f.id, None),
+            StateLike::History(h) => ("history", &mut h.id, None),
+        };
+        if id.is_none() {
+            let base = format!("_{}{}", tag, child_path);
+            let mut candidate = base.clone();
+            let mut suffix = 1;
+            while taken.contains(&candidate) {
+                candidate = format!("{}_{}", base, suffix);
+                suffix += 1;
+            }
+            taken.insert(candidate.clone());
+            generated.push(candidate.clone());
+            *id = Some(candidate);
+        }
+        if let Some(children) = children {
+            assign_generated_ids(children, &child_path, taken, generated);
+        }
+    }
 }
 
 /// Validates the parsed SCXML structure for compliance with the specification.
@@ -413,6 +764,10 @@ pub fn parse_scxml_with_options(xml: &str, options: ParseOptions) -> Result<Scxm
 /// - Valid targets for transitions.
 /// - No circular initial references (basic check).
 /// - Datamodel constraints (e.g., unique data IDs).
+/// - `<donedata>` holding either `<content>` or `<param>`s, not both.
+/// - `<cancel>` naming its send with either `sendid` or `sendidexpr`, not both.
+///
+/// This is a convenience wrapper over [`validate_all`] that reports only the first error.
 ///
 /// # Arguments
 ///
@@ -421,7 +776,7 @@ pub fn parse_scxml_with_options(xml: &str, options: ParseOptions) -> Result<Scxm
 /// # Returns
 ///
 /// * `Ok(())` - If valid.
-/// * `Err(ValidationError)` - If invalid.
+/// * `Err(ValidationError)` - The first error found, if invalid.
 ///
 /// # Examples
 ///
@@ -439,125 +794,337 @@ pub fn parse_scxml_with_options(xml: &str, options: ParseOptions) -> Result<Scxm
 /// validate(&scxml).expect("SCXML validation failed");
 /// ```
 pub fn validate(scxml: &Scxml) -> Result<(), ValidationError> {
-    let mut all_ids = std::collections::HashSet::new();
+    match Validator::run(scxml).errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
 
-    // Collect and check for duplicate IDs.
-    collect_state_ids(&scxml.states, &mut all_ids)?;
+/// Checks an SCXML document and reports every problem found.
+///
+/// Unlike [`validate`], this does not stop at the first error: every rule is applied to the whole
+/// document and each violation becomes a [`Diagnostic`]. Besides the errors [`validate`] rejects,
+/// it reports warnings and informational notes about constructs that are legal but likely mistakes.
+/// Diagnostics are grouped by rule, in document order within each rule.
+///
+/// # Arguments
+///
+/// * `scxml` - The parsed SCXML to check.
+///
+/// # Returns
+///
+/// All diagnostics; the document is valid if none has [`Severity::Error`].
+///
+/// # Examples
+///
+/// ```rust
+/// use harel::{parse_scxml, validate_all, Severity};
+///
+/// let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+///     <state id="a"><transition event="go" target="nowhere"/></state>
+///     <state id="a"/>
+/// </scxml>"#;
+///
+/// let scxml = parse_scxml(xml).expect("Failed to parse SCXML");
+/// let diagnostics = validate_all(&scxml);
+/// let errors: Vec<_> = diagnostics.iter().filter(|d| d.severity == Severity::Error).collect();
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!(errors[0].code, "duplicate-id");
+/// assert_eq!(errors[1].code, "invalid-target");
+/// ```
+pub fn validate_all(scxml: &Scxml) -> Vec<Diagnostic> {
+    Validator::run(scxml).diagnostics
+}
 
-    // Validate transition targets exist.
-    validate_transition_targets(&scxml.states, &all_ids)?;
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The document violates the SCXML specification; [`validate`] rejects it.
+    Error,
+    /// The document is valid but probably does not do what was intended.
+    Warning,
+    /// A note about the document that needs no action.
+    Info,
+}
 
-    // Validate initial reference if present.
-    if let Some(ref initial) = scxml.initial {
-        if !all_ids.contains(initial) {
-            return Err(ValidationError::InvalidTarget(initial.clone()));
-        }
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
     }
+}
 
-    // Validate datamodel elements.
-    validate_datamodel_constraints(&scxml.datamodel_elements)?;
+/// A single problem reported by [`validate_all`].
+///
+/// Rule codes are stable and can be used to filter or suppress diagnostics:
+///
+/// | Code | Severity | Meaning |
+/// |------|----------|---------|
+/// | `duplicate-id` | error | Two states share an `id`. |
+/// | `invalid-target` | error | A transition or the `<scxml>` `initial` names a state that does not exist. |
+/// | `duplicate-data-id` | error | Two `<data>` elements share an `id`. |
+/// | `invalid-donedata` | error | A `<donedata>` mixes `<content>` and `<param>`. |
+/// | `invalid-cancel` | error | A `<cancel>` has neither or both of `sendid` and `sendidexpr`. |
+/// | `eventless-self-loop` | warning | An unguarded eventless transition targets its own state and never settles. |
+/// | `unknown-element` | warning | Executable content that is not standard SCXML and is left to [`datamodel::Datamodel::execute_custom`]. |
+/// | `anonymous-state` | info | A state has no `id` and cannot be targeted. |
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// Stable identifier of the rule that produced the diagnostic.
+    pub code: &'static str,
+    /// Human-readable description of the problem.
+    pub message: String,
+    /// Path of the offending element, such as `/scxml/state[@id='a']/transition[2]`.
+    pub path: String,
+    /// Position of the offending element in the source document.
+    pub span: Span,
+}
 
-    // TODO: Add check for circular initial references if needed.
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}] {} at {} ({})", self.severity, self.code, self.message, self.path, self.span)
+    }
+}
 
-    Ok(())
+// Accumulates diagnostics, keeping the errors in the form `validate` reports them.
+#[derive(Default)]
+struct Validator {
+    diagnostics: Vec<Diagnostic>,
+    errors: Vec<ValidationError>,
 }
 
-// Helper function to recursively collect state IDs and detect duplicates.
-fn collect_state_ids(states: &[StateLike], all_ids: &mut std::collections::HashSet<String>) -> Result<(), ValidationError> {
-    for state in states {
-        match state {
-            StateLike::State(s) => {
-                if let Some(ref id) = s.id {
-                    if !all_ids.insert(id.clone()) {
-                        return Err(ValidationError::DuplicateId(id.clone()));
-                    }
-                }
-                collect_state_ids(&s.children, all_ids)?;
+impl Validator {
+    // Helper to apply every rule to the document, one rule at a time.
+    fn run(scxml: &Scxml) -> Validator {
+        let mut validator = Validator::default();
+        let mut states = Vec::new();
+        flatten_states(&scxml.states, "/scxml", &mut states);
+
+        // Collect and check for duplicate IDs.
+        let mut all_ids = std::collections::HashSet::new();
+        for (path, state) in &states {
+            if let Some(id) = state_id(state)
+                && !all_ids.insert(id)
+            {
+                validator.error(ValidationError::DuplicateId(id.to_string(), state_span(state)), "duplicate-id", format!("duplicate state ID '{}'", id), path);
             }
-            StateLike::Parallel(p) => {
-                if let Some(ref id) = p.id {
-                    if !all_ids.insert(id.clone()) {
-                        return Err(ValidationError::DuplicateId(id.clone()));
+        }
+
+        // Validate transition targets exist.
+        for (path, state) in &states {
+            for (transition_path, transition) in state_transitions(path, state) {
+                for target_id in transition.target.iter().flat_map(|t| t.split_whitespace()) {
+                    if !all_ids.contains(target_id) {
+                        validator.error(
+                            ValidationError::InvalidTarget(target_id.to_string(), transition.span),
+                            "invalid-target",
+                            format!("transition target '{}' does not exist", target_id),
+                            &transition_path,
+                        );
                     }
                 }
-                collect_state_ids(&p.children, all_ids)?;
             }
-            StateLike::Final(f) => {
-                if let Some(ref id) = f.id {
-                    if !all_ids.insert(id.clone()) {
-                        return Err(ValidationError::DuplicateId(id.clone()));
-                    }
+        }
+
+        // Validate initial reference if present.
+        if let Some(ref initial) = scxml.initial
+            && !all_ids.contains(initial.as_str())
+        {
+            validator.error(ValidationError::InvalidTarget(initial.clone(), scxml.span), "invalid-target", format!("initial state '{}' does not exist", initial), "/scxml");
+        }
+
+        // Validate datamodel elements across the document.
+        // Note: SCXML allows empty data elements for late binding, so no check for expr/src/content.
+        let mut data_ids = std::collections::HashSet::new();
+        let top_level = scxml.datamodel_elements.iter().map(|data| ("/scxml/datamodel".to_string(), data));
+        let nested = states.iter().flat_map(|(path, state)| {
+            let datamodel: &[Data] = match state {
+                StateLike::State(s) => &s.datamodel,
+                StateLike::Parallel(p) => &p.datamodel,
+                _ => &[],
+            };
+            datamodel.iter().map(move |data| (format!("{}/datamodel", path), data))
+        });
+        for (path, data) in top_level.chain(nested) {
+            if !data_ids.insert(data.id.as_str()) {
+                validator.error(
+                    ValidationError::DuplicateId(data.id.clone(), data.span),
+                    "duplicate-data-id",
+                    format!("duplicate data ID '{}'", data.id),
+                    &format!("{}/data[@id='{}']", path, data.id),
+                );
+            }
+        }
+
+        // Validate <donedata> contents.
+        for (path, state) in &states {
+            if let StateLike::Final(f) = state
+                && let Some(ref donedata) = f.donedata
+                && donedata.content.is_some()
+                && !donedata.params.is_empty()
+            {
+                validator.error(
+                    ValidationError::InvalidDoneData(f.id.clone().unwrap_or_default(), f.span),
+                    "invalid-donedata",
+                    "<donedata> cannot contain both <content> and <param>".to_string(),
+                    &format!("{}/donedata", path),
+                );
+            }
+        }
+
+        // Eventless, unguarded transitions back into their own state loop forever.
+        for (path, state) in &states {
+            let Some(id) = state_id(state) else { continue };
+            if matches!(state, StateLike::History(_)) {
+                continue;
+            }
+            for (transition_path, transition) in state_transitions(path, state) {
+                if transition.event.is_none()
+                    && transition.cond.is_none()
+                    && transition.target.as_deref().is_some_and(|t| t.split_whitespace().any(|target| target == id))
+                {
+                    validator.note(Severity::Warning, "eventless-self-loop", "eventless transition without a condition re-enters its own state".to_string(), &transition_path, transition.span);
                 }
             }
-            StateLike::History(h) => {
-                if let Some(ref id) = h.id {
-                    if !all_ids.insert(id.clone()) {
-                        return Err(ValidationError::DuplicateId(id.clone()));
-                    }
+        }
+
+        // Check executable content: malformed <cancel>s and custom elements that are never executed.
+        for (path, state) in &states {
+            let (onentry, onexit, invokes): (&[Executable], &[Executable], &[Invoke]) = match state {
+                StateLike::State(s) => (&s.onentry, &s.onexit, &s.invokes),
+                StateLike::Parallel(p) => (&p.onentry, &p.onexit, &p.invokes),
+                StateLike::Final(f) => (&f.onentry, &f.onexit, &[]),
+                StateLike::History(_) => (&[], &[], &[]),
+            };
+            validator.check_executables(onentry, &format!("{}/onentry", path));
+            if let StateLike::State(State { initial_element: Some(initial), .. }) = state {
+                validator.check_executables(&initial.transition.executables, &format!("{}/initial/transition", path));
+            }
+            for (transition_path, transition) in state_transitions(path, state) {
+                validator.check_executables(&transition.executables, &transition_path);
+            }
+            validator.check_executables(onexit, &format!("{}/onexit", path));
+            for (index, invoke) in invokes.iter().enumerate() {
+                if let Some(finalize) = &invoke.finalize {
+                    validator.check_executables(&finalize.executables, &format!("{}/invoke[{}]/finalize", path, index + 1));
                 }
             }
         }
+
+        for (path, state) in &states {
+            if state_id(state).is_none() && !matches!(state, StateLike::History(_)) {
+                validator.note(Severity::Info, "anonymous-state", "state has no id and cannot be the target of a transition".to_string(), path, state_span(state));
+            }
+        }
+
+        validator
     }
-    Ok(())
-}
 
-// Helper function to recursively validate transition targets.
-fn validate_transition_targets(states: &[StateLike], all_ids: &std::collections::HashSet<String>) -> Result<(), ValidationError> {
-    for state in states {
-        match state {
-            StateLike::State(s) => {
-                for transition in &s.transitions {
-                    if let Some(ref target) = transition.target {
-                        for target_id in target.split_whitespace() {
-                            if !all_ids.contains(target_id) {
-                                return Err(ValidationError::InvalidTarget(target_id.to_string()));
-                            }
-                        }
-                    }
+    // Helper to record an error both as a diagnostic and for `validate`.
+    fn error(&mut self, error: ValidationError, code: &'static str, message: String, path: &str) {
+        self.note(Severity::Error, code, message, path, error.span());
+        self.errors.push(error);
+    }
+
+    // Helper to record a diagnostic.
+    fn note(&mut self, severity: Severity, code: &'static str, message: String, path: &str, span: Span) {
+        self.diagnostics.push(Diagnostic { severity, code, message, path: path.to_string(), span });
+    }
+
+    // Helper to recursively check executable content, reporting what is malformed or not understood.
+    fn check_executables(&mut self, executables: &[Executable], path: &str) {
+        for executable in executables {
+            match executable {
+                Executable::Other { name, span, .. } => {
+                    let message = format!("<{}> is not a standard SCXML element and is passed to the datamodel's execute_custom", name);
+                    self.note(Severity::Warning, "unknown-element", message, &format!("{}/{}", path, name), *span);
                 }
-                validate_transition_targets(&s.children, all_ids)?;
-            }
-            StateLike::Parallel(p) => {
-                for transition in &p.transitions {
-                    if let Some(ref target) = transition.target {
-                        for target_id in target.split_whitespace() {
-                            if !all_ids.contains(target_id) {
-                                return Err(ValidationError::InvalidTarget(target_id.to_string()));
-                            }
-                        }
+                Executable::If { branches, else_, .. } => {
+                    for branch in branches {
+                        self.check_executables(&branch.body, &format!("{}/if", path));
                     }
-                }
-                validate_transition_targets(&p.children, all_ids)?;
-            }
-            StateLike::History(h) => {
-                if let Some(ref transition) = h.transition {
-                    if let Some(ref target) = transition.target {
-                        for target_id in target.split_whitespace() {
-                            if !all_ids.contains(target_id) {
-                                return Err(ValidationError::InvalidTarget(target_id.to_string()));
-                            }
-                        }
+                    if let Some(body) = else_ {
+                        self.check_executables(body, &format!("{}/if", path));
                     }
                 }
+                Executable::Foreach { body, .. } => self.check_executables(body, &format!("{}/foreach", path)),
+                Executable::Cancel { sendid, sendidexpr, span } if sendid.is_some() == sendidexpr.is_some() => {
+                    self.error(
+                        ValidationError::InvalidCancel(*span),
+                        "invalid-cancel",
+                        "<cancel> requires exactly one of sendid and sendidexpr".to_string(),
+                        &format!("{}/cancel", path),
+                    );
+                }
+                _ => {}
             }
+        }
+    }
+}
+
+// Helper to list every state in document order together with its element path.
+fn flatten_states<'a>(states: &'a [StateLike], parent: &str, out: &mut Vec<(String, &'a StateLike)>) {
+    for (index, state) in states.iter().enumerate() {
+        let tag = state_tag(state);
+        let path = match state_id(state) {
+            Some(id) => format!("{}/{}[@id='{}']", parent, tag, id),
+            None => {
+                let position = states[..index].iter().filter(|s| state_tag(s) == tag).count() + 1;
+                format!("{}/{}[{}]", parent, tag, position)
+            }
+        };
+        out.push((path.clone(), state));
+        match state {
+            StateLike::State(s) => flatten_states(&s.children, &path, out),
+            StateLike::Parallel(p) => flatten_states(&p.children, &path, out),
             _ => {}
         }
     }
-    Ok(())
 }
 
-// Helper function to validate datamodel constraints, such as unique data IDs.
-fn validate_datamodel_constraints(data_elements: &[Data]) -> Result<(), ValidationError> {
-    let mut data_ids = std::collections::HashSet::new();
+// Helper to list the transitions of a state with their element paths.
+fn state_transitions<'a>(path: &str, state: &'a StateLike) -> Vec<(String, &'a Transition)> {
+    match state {
+        StateLike::State(s) => s.transitions.iter().enumerate().map(|(i, t)| (format!("{}/transition[{}]", path, i + 1), t)).collect(),
+        StateLike::Parallel(p) => p.transitions.iter().enumerate().map(|(i, t)| (format!("{}/transition[{}]", path, i + 1), t)).collect(),
+        StateLike::History(h) => h.transition.iter().map(|t| (format!("{}/transition", path), t)).collect(),
+        StateLike::Final(_) => Vec::new(),
+    }
+}
 
-    for data in data_elements {
-        if !data_ids.insert(data.id.clone()) {
-            return Err(ValidationError::DuplicateId(data.id.clone()));
-        }
-        // Note: SCXML allows empty data elements for late binding, so no check for expr/src/content.
+// Helper to get the element name of a state.
+fn state_tag(state: &StateLike) -> &'static str {
+    match state {
+        StateLike::State(_) => "state",
+        StateLike::Parallel(_) => "parallel",
+        StateLike::Final(_) => "final",
+        StateLike::History(_) => "history",
     }
+}
 
-    Ok(())
+// Helper to get the `id` attribute of a state.
+fn state_id(state: &StateLike) -> Option<&str> {
+    match state {
+        StateLike::State(s) => s.id.as_deref(),
+        StateLike::Parallel(p) => p.id.as_deref(),
+        StateLike::Final(f) => f.id.as_deref(),
+        StateLike::History(h) => h.id.as_deref(),
+    }
+}
+
+// Helper to get the source position of a state.
+fn state_span(state: &StateLike) -> Span {
+    match state {
+        StateLike::State(s) => s.span,
+        StateLike::Parallel(p) => p.span,
+        StateLike::Final(f) => f.span,
+        StateLike::History(h) => h.span,
+    }
 }
 
 /// Serializes the SCXML structure back to an XML string.
@@ -591,38 +1158,29 @@ pub fn to_xml(scxml: &Scxml) -> String {
     output.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
     // Start <scxml> tag with attributes.
     output.push_str("<scxml");
-    output.push_str(&format!(" xmlns=\"{}\"", SCXML_NS));
-    output.push_str(&format!(" version=\"{}\"", scxml.version));
+    push_attribute(&mut output, "xmlns", SCXML_NS);
+    push_attribute(&mut output, "version", &scxml.version);
 
     if let Some(ref initial) = scxml.initial {
-        output.push_str(&format!(" initial=\"{}\"", initial));
+        push_attribute(&mut output, "initial", initial);
+    }
+
+    if let Some(ref name) = scxml.name {
+        push_attribute(&mut output, "name", name);
     }
 
     if let Some(ref datamodel) = scxml.datamodel {
-        output.push_str(&format!(" datamodel=\"{}\"", datamodel));
+        push_attribute(&mut output, "datamodel", datamodel);
+    }
+
+    if let Some(ref binding) = scxml.binding {
+        push_attribute(&mut output, "binding", binding);
     }
 
     output.push_str(">\n");
 
     // Serialize <datamodel> if present.
-    if !scxml.datamodel_elements.is_empty() {
-        output.push_str("    <datamodel>\n");
-        for data in &scxml.datamodel_elements {
-            output.push_str(&format!("        <data id=\"{}\"", data.id));
-            if let Some(ref expr) = data.expr {
-                output.push_str(&format!(" expr=\"{}\"", expr));
-            }
-            if let Some(ref src) = data.src {
-                output.push_str(&format!(" src=\"{}\"", src));
-            }
-            if let Some(ref content) = data.content {
-                output.push_str(&format!(">{}</data>\n", content));
-            } else {
-                output.push_str("/>\n");
-            }
-        }
-        output.push_str("    </datamodel>\n");
-    }
+    serialize_datamodel(&scxml.datamodel_elements, 1, &mut output);
 
     // Serialize child states.
     for state in &scxml.states {
@@ -641,33 +1199,30 @@ fn serialize_state_like(state: &StateLike, indent_level: usize, output: &mut Str
         StateLike::State(s) => {
             output.push_str(&format!("{}<state", indent));
             if let Some(ref id) = s.id {
-                output.push_str(&format!(" id=\"{}\"", id));
+                push_attribute(output, "id", id);
             }
             if let Some(ref initial) = s.initial {
-                output.push_str(&format!(" initial=\"{}\"", initial));
+                push_attribute(output, "initial", initial);
             }
 
             if s.transitions.is_empty() && s.onentry.is_empty() && s.onexit.is_empty()
-                && s.children.is_empty() && s.invokes.is_empty() && s.initial_element.is_none() {
+                && s.children.is_empty() && s.invokes.is_empty() && s.initial_element.is_none()
+                && s.datamodel.is_empty() {
                 output.push_str("/>\n");
                 return;
             }
 
             output.push_str(">\n");
 
+            // Serialize <datamodel> if present.
+            serialize_datamodel(&s.datamodel, indent_level + 1, output);
+
             // Serialize <initial> if present.
             if let Some(ref initial_elem) = s.initial_element {
                 serialize_initial(initial_elem, indent_level + 1, output);
             }
 
-            // Serialize <onentry>.
-            if !s.onentry.is_empty() {
-                output.push_str(&format!("{}    <onentry>\n", indent));
-                for executable in &s.onentry {
-                    serialize_executable(executable, indent_level + 2, output);
-                }
-                output.push_str(&format!("{}    </onentry>\n", indent));
-            }
+            serialize_executable_block("onentry", &s.onentry, indent_level + 1, output);
 
             // Serialize children.
             for child in &s.children {
@@ -679,14 +1234,7 @@ fn serialize_state_like(state: &StateLike, indent_level: usize, output: &mut Str
                 serialize_transition(transition, indent_level + 1, output);
             }
 
-            // Serialize <onexit>.
-            if !s.onexit.is_empty() {
-                output.push_str(&format!("{}    <onexit>\n", indent));
-                for executable in &s.onexit {
-                    serialize_executable(executable, indent_level + 2, output);
-                }
-                output.push_str(&format!("{}    </onexit>\n", indent));
-            }
+            serialize_executable_block("onexit", &s.onexit, indent_level + 1, output);
 
             // Serialize <invoke>s.
             for invoke in &s.invokes {
@@ -698,37 +1246,162 @@ fn serialize_state_like(state: &StateLike, indent_level: usize, output: &mut Str
         StateLike::Parallel(p) => {
             output.push_str(&format!("{}<parallel", indent));
             if let Some(ref id) = p.id {
-                output.push_str(&format!(" id=\"{}\"", id));
+                push_attribute(output, "id", id);
+            }
+
+            if p.transitions.is_empty() && p.onentry.is_empty() && p.onexit.is_empty()
+                && p.children.is_empty() && p.invokes.is_empty() && p.datamodel.is_empty() {
+                output.push_str("/>\n");
+                return;
             }
+
             output.push_str(">\n");
 
+            serialize_datamodel(&p.datamodel, indent_level + 1, output);
+            serialize_executable_block("onentry", &p.onentry, indent_level + 1, output);
+
             // Serialize children (no initial for parallel).
             for child in &p.children {
                 serialize_state_like(child, indent_level + 1, output);
             }
 
-            // TODO: Add serialization for transitions, onentry, onexit, invokes if needed.
+            for transition in &p.transitions {
+                serialize_transition(transition, indent_level + 1, output);
+            }
+
+            serialize_executable_block("onexit", &p.onexit, indent_level + 1, output);
+
+            for invoke in &p.invokes {
+                serialize_invoke(invoke, indent_level + 1, output);
+            }
 
             output.push_str(&format!("{}</parallel>\n", indent));
         }
         StateLike::Final(f) => {
             output.push_str(&format!("{}<final", indent));
             if let Some(ref id) = f.id {
-                output.push_str(&format!(" id=\"{}\"", id));
+                push_attribute(output, "id", id);
             }
-            // TODO: Add onentry/onexit if non-empty.
-            output.push_str("/>\n");
+
+            if f.onentry.is_empty() && f.onexit.is_empty() && f.donedata.is_none() {
+                output.push_str("/>\n");
+                return;
+            }
+
+            output.push_str(">\n");
+            serialize_executable_block("onentry", &f.onentry, indent_level + 1, output);
+            serialize_executable_block("onexit", &f.onexit, indent_level + 1, output);
+            if let Some(ref donedata) = f.donedata {
+                output.push_str(&format!("{}    <donedata>\n", indent));
+                if let Some(ref content) = donedata.content {
+                    serialize_content(content, indent_level + 2, output);
+                }
+                for param in &donedata.params {
+                    serialize_param(param, indent_level + 2, output);
+                }
+                output.push_str(&format!("{}    </donedata>\n", indent));
+            }
+            output.push_str(&format!("{}</final>\n", indent));
         }
         StateLike::History(h) => {
             output.push_str(&format!("{}<history", indent));
             if let Some(ref id) = h.id {
-                output.push_str(&format!(" id=\"{}\"", id));
+                push_attribute(output, "id", id);
             }
-            output.push_str(&format!(" type=\"{}\"", h.type_));
-            // TODO: Serialize transition if present.
+            push_attribute(output, "type", &h.type_);
+            if let Some(ref transition) = h.transition {
+                output.push_str(">\n");
+                serialize_transition(transition, indent_level + 1, output);
+                output.push_str(&format!("{}</history>\n", indent));
+            } else {
+                output.push_str("/>\n");
+            }
+        }
+    }
+}
+
+// Helper to append ` name="value"` with the value escaped for a double-quoted attribute.
+fn push_attribute(output: &mut String, name: &str, value: &str) {
+    output.push(' ');
+    output.push_str(name);
+    output.push_str("=\"");
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            // Escaped so attribute-value normalization does not turn them into spaces.
+            '\n' => output.push_str("&#10;"),
+            '\r' => output.push_str("&#13;"),
+            '\t' => output.push_str("&#9;"),
+            _ => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+// Helper to escape character data.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            // A literal carriage return would be normalized away by the parser.
+            '\r' => escaped.push_str("&#13;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Helper to write a <script> body, using CDATA when it contains markup so scripts stay readable.
+fn escape_script(script: &str) -> String {
+    if !script.contains(['&', '<']) || script.contains('\r') {
+        return escape_text(script);
+    }
+    // A "]]>" inside the script has to be split across two CDATA sections.
+    format!("<![CDATA[{}]]>", script.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+// Helper to serialize a <datamodel> block, omitted when empty.
+fn serialize_datamodel(data_elements: &[Data], indent_level: usize, output: &mut String) {
+    if data_elements.is_empty() {
+        return;
+    }
+    let indent = "    ".repeat(indent_level);
+    output.push_str(&format!("{}<datamodel>\n", indent));
+    for data in data_elements {
+        output.push_str(&format!("{}    <data", indent));
+        push_attribute(output, "id", &data.id);
+        if let Some(ref expr) = data.expr {
+            push_attribute(output, "expr", expr);
+        }
+        if let Some(ref src) = data.src {
+            push_attribute(output, "src", src);
+        }
+        if let Some(ref content) = data.content {
+            output.push_str(&format!(">{}</data>\n", escape_text(content)));
+        } else {
             output.push_str("/>\n");
         }
     }
+    output.push_str(&format!("{}</datamodel>\n", indent));
+}
+
+// Helper to serialize an <onentry>, <onexit> or <finalize> block, omitted when empty.
+fn serialize_executable_block(tag: &str, executables: &[Executable], indent_level: usize, output: &mut String) {
+    if executables.is_empty() {
+        return;
+    }
+    let indent = "    ".repeat(indent_level);
+    output.push_str(&format!("{}<{}>\n", indent, tag));
+    for executable in executables {
+        serialize_executable(executable, indent_level + 1, output);
+    }
+    output.push_str(&format!("{}</{}>\n", indent, tag));
 }
 
 // Helper to serialize <initial>.
@@ -736,7 +1409,7 @@ fn serialize_initial(initial: &Initial, indent_level: usize, output: &mut String
     let indent = "    ".repeat(indent_level);
     output.push_str(&format!("{}<initial", indent));
     if let Some(ref id) = initial.id {
-        output.push_str(&format!(" id=\"{}\"", id));
+        push_attribute(output, "id", id);
     }
     output.push_str(">\n");
     serialize_transition(&initial.transition, indent_level + 1, output);
@@ -749,16 +1422,16 @@ fn serialize_transition(transition: &Transition, indent_level: usize, output: &m
     output.push_str(&format!("{}<transition", indent));
 
     if let Some(ref event) = transition.event {
-        output.push_str(&format!(" event=\"{}\"", event));
+        push_attribute(output, "event", &event.to_string());
     }
     if let Some(ref cond) = transition.cond {
-        output.push_str(&format!(" cond=\"{}\"", cond));
+        push_attribute(output, "cond", cond);
     }
     if let Some(ref target) = transition.target {
-        output.push_str(&format!(" target=\"{}\"", target));
+        push_attribute(output, "target", target);
     }
     if let Some(ref type_) = transition.type_ {
-        output.push_str(&format!(" type=\"{}\"", type_));
+        push_attribute(output, "type", type_);
     }
 
     if transition.executables.is_empty() {
@@ -777,33 +1450,115 @@ fn serialize_executable(executable: &Executable, indent_level: usize, output: &m
     let indent = "    ".repeat(indent_level);
 
     match executable {
-        Executable::Raise { event } => {
-            output.push_str(&format!("{}<raise event=\"{}\"/>\n", indent, event));
+        Executable::Raise { event, .. } => {
+            output.push_str(&format!("{}<raise", indent));
+            push_attribute(output, "event", event);
+            output.push_str("/>\n");
         }
-        Executable::Script { src, content } => {
+        Executable::Script { src, content, .. } => {
             output.push_str(&format!("{}<script", indent));
             if let Some(src) = src {
-                output.push_str(&format!(" src=\"{}\"", src));
+                push_attribute(output, "src", src);
             }
             if let Some(content) = content {
-                output.push_str(&format!(">{}</script>\n", content));
+                output.push_str(&format!(">{}</script>\n", escape_script(content)));
             } else {
                 output.push_str("/>\n");
             }
         }
-        Executable::Assign { location, expr } => {
-            output.push_str(&format!("{}<assign location=\"{}\" expr=\"{}\"/>\n", indent, location, expr));
+        Executable::Assign { location, expr, .. } => {
+            output.push_str(&format!("{}<assign", indent));
+            push_attribute(output, "location", location);
+            push_attribute(output, "expr", expr);
+            output.push_str("/>\n");
         }
-        Executable::Log { label, expr } => {
+        Executable::Log { label, expr, .. } => {
             output.push_str(&format!("{}<log", indent));
             if let Some(label) = label {
-                output.push_str(&format!(" label=\"{}\"", label));
+                push_attribute(output, "label", label);
+            }
+            push_attribute(output, "expr", expr);
+            output.push_str("/>\n");
+        }
+        Executable::If { branches, else_, .. } => {
+            for (i, branch) in branches.iter().enumerate() {
+                output.push_str(&format!("{}<{}", indent, if i == 0 { "if" } else { "elseif" }));
+                push_attribute(output, "cond", &branch.cond);
+                output.push_str(if i == 0 { ">\n" } else { "/>\n" });
+                for executable in &branch.body {
+                    serialize_executable(executable, indent_level + 1, output);
+                }
+            }
+            if let Some(else_) = else_ {
+                output.push_str(&format!("{}<else/>\n", indent));
+                for executable in else_ {
+                    serialize_executable(executable, indent_level + 1, output);
+                }
+            }
+            output.push_str(&format!("{}</if>\n", indent));
+        }
+        Executable::Send(send) => {
+            output.push_str(&format!("{}<send", indent));
+            let attributes = [
+                ("event", &send.event),
+                ("eventexpr", &send.eventexpr),
+                ("target", &send.target),
+                ("targetexpr", &send.targetexpr),
+                ("type", &send.type_),
+                ("typeexpr", &send.typeexpr),
+                ("id", &send.id),
+                ("idlocation", &send.idlocation),
+                ("delay", &send.delay),
+                ("delayexpr", &send.delayexpr),
+                ("namelist", &send.namelist),
+            ];
+            for (name, value) in attributes {
+                if let Some(value) = value {
+                    push_attribute(output, name, value);
+                }
+            }
+            if send.params.is_empty() && send.content.is_none() {
+                output.push_str("/>\n");
+            } else {
+                output.push_str(">\n");
+                for param in &send.params {
+                    serialize_param(param, indent_level + 1, output);
+                }
+                if let Some(ref content) = send.content {
+                    serialize_content(content, indent_level + 1, output);
+                }
+                output.push_str(&format!("{}</send>\n", indent));
+            }
+        }
+        Executable::Foreach { array, item, index, body, .. } => {
+            output.push_str(&format!("{}<foreach", indent));
+            push_attribute(output, "array", array);
+            push_attribute(output, "item", item);
+            if let Some(index) = index {
+                push_attribute(output, "index", index);
+            }
+            if body.is_empty() {
+                output.push_str("/>\n");
+            } else {
+                output.push_str(">\n");
+                for executable in body {
+                    serialize_executable(executable, indent_level + 1, output);
+                }
+                output.push_str(&format!("{}</foreach>\n", indent));
+            }
+        }
+        Executable::Cancel { sendid, sendidexpr, .. } => {
+            output.push_str(&format!("{}<cancel", indent));
+            if let Some(sendid) = sendid {
+                push_attribute(output, "sendid", sendid);
             }
-            output.push_str(&format!(" expr=\"{}\"/>\n", expr));
+            if let Some(sendidexpr) = sendidexpr {
+                push_attribute(output, "sendidexpr", sendidexpr);
+            }
+            output.push_str("/>\n");
         }
-        // TODO: Add serialization for other Executable variants.
-        _ => {
-            output.push_str(&format!("{}<!-- Unsupported executable -->\n", indent));
+        Executable::Other { markup, .. } => {
+            output.push_str(&format!("{}{}\n", indent, markup));
         }
     }
 }
@@ -811,13 +1566,31 @@ fn serialize_executable(executable: &Executable, indent_level: usize, output: &m
 // Helper to serialize <invoke>.
 fn serialize_invoke(invoke: &Invoke, indent_level: usize, output: &mut String) {
     let indent = "    ".repeat(indent_level);
-    output.push_str(&format!("{}<invoke type=\"{}\"", indent, invoke.type_));
+    output.push_str(&format!("{}<invoke", indent));
 
+    if !invoke.type_.is_empty() {
+        push_attribute(output, "type", &invoke.type_);
+    }
+    if let Some(ref typeexpr) = invoke.typeexpr {
+        push_attribute(output, "typeexpr", typeexpr);
+    }
     if let Some(ref src) = invoke.src {
-        output.push_str(&format!(" src=\"{}\"", src));
+        push_attribute(output, "src", src);
+    }
+    if let Some(ref srcexpr) = invoke.srcexpr {
+        push_attribute(output, "srcexpr", srcexpr);
     }
     if let Some(ref id) = invoke.id {
-        output.push_str(&format!(" id=\"{}\"", id));
+        push_attribute(output, "id", id);
+    }
+    if let Some(ref idlocation) = invoke.idlocation {
+        push_attribute(output, "idlocation", idlocation);
+    }
+    if let Some(ref namelist) = invoke.namelist {
+        push_attribute(output, "namelist", namelist);
+    }
+    if invoke.autoforward {
+        push_attribute(output, "autoforward", "true");
     }
 
     if invoke.params.is_empty() && invoke.finalize.is_none() && invoke.content.is_none() {
@@ -829,21 +1602,52 @@ fn serialize_invoke(invoke: &Invoke, indent_level: usize, output: &mut String) {
 
     // Serialize <param>s.
     for param in &invoke.params {
-        output.push_str(&format!("{}    <param name=\"{}\"", indent, param.name));
-        if let Some(ref expr) = param.expr {
-            output.push_str(&format!(" expr=\"{}\"", expr));
-        }
-        if let Some(ref location) = param.location {
-            output.push_str(&format!(" location=\"{}\"", location));
-        }
-        output.push_str("/>\n");
+        serialize_param(param, indent_level + 1, output);
     }
 
-    // TODO: Serialize finalize and content if present.
+    if let Some(ref content) = invoke.content {
+        serialize_content(content, indent_level + 1, output);
+    }
+
+    if let Some(ref finalize) = invoke.finalize {
+        if finalize.executables.is_empty() {
+            output.push_str(&format!("{}    <finalize/>\n", indent));
+        } else {
+            serialize_executable_block("finalize", &finalize.executables, indent_level + 1, output);
+        }
+    }
 
     output.push_str(&format!("{}</invoke>\n", indent));
 }
 
+// Helper to serialize <param>.
+fn serialize_param(param: &Param, indent_level: usize, output: &mut String) {
+    let indent = "    ".repeat(indent_level);
+    output.push_str(&format!("{}<param", indent));
+    push_attribute(output, "name", &param.name);
+    if let Some(ref expr) = param.expr {
+        push_attribute(output, "expr", expr);
+    }
+    if let Some(ref location) = param.location {
+        push_attribute(output, "location", location);
+    }
+    output.push_str("/>\n");
+}
+
+// Helper to serialize <content>.
+fn serialize_content(content: &Content, indent_level: usize, output: &mut String) {
+    let indent = "    ".repeat(indent_level);
+    output.push_str(&format!("{}<content", indent));
+    if let Some(ref expr) = content.expr {
+        push_attribute(output, "expr", expr);
+    }
+    match content.content {
+        Some(ContentBody::Text(ref text)) => output.push_str(&format!(">{}</content>\n", escape_text(text))),
+        Some(ContentBody::Markup(ref markup)) => output.push_str(&format!(">{}</content>\n", markup)),
+        None => output.push_str("/>\n"),
+    }
+}
+
 // Helper to parse <state>.
 fn parse_state(node: &Node) -> Result<State, ParseError> {
     let id = node.attribute("id").map(|s| s.to_string());
@@ -855,6 +1659,7 @@ fn parse_state(node: &Node) -> Result<State, ParseError> {
     let mut onexit = Vec::new();
     let mut children = Vec::new();
     let mut invokes = Vec::new();
+    let mut datamodel = Vec::new();
 
     // Process child elements.
     for child in node.children() {
@@ -871,11 +1676,12 @@ fn parse_state(node: &Node) -> Result<State, ParseError> {
             "final" => children.push(StateLike::Final(parse_final(&child)?)),
             "history" => children.push(StateLike::History(parse_history(&child)?)),
             "invoke" => invokes.push(parse_invoke(&child)?),
+            "datamodel" => datamodel.extend(parse_datamodel(&child)?),
             _ => {},  // Ignore unsupported
         }
     }
 
-    Ok(State { id, initial, initial_element, transitions, onentry, onexit, children, invokes })
+    Ok(State { id, initial, initial_element, transitions, onentry, onexit, children, invokes, datamodel, span: Span::of(node) })
 }
 
 // Helper to parse <parallel>.
@@ -886,6 +1692,7 @@ fn parse_parallel(node: &Node) -> Result<Parallel, ParseError> {
     let mut onexit = Vec::new();
     let mut children = Vec::new();
     let mut invokes = Vec::new();
+    let mut datamodel = Vec::new();
 
     // Process child elements.
     for child in node.children() {
@@ -901,11 +1708,12 @@ fn parse_parallel(node: &Node) -> Result<Parallel, ParseError> {
             "final" => children.push(StateLike::Final(parse_final(&child)?)),
             "history" => children.push(StateLike::History(parse_history(&child)?)),
             "invoke" => invokes.push(parse_invoke(&child)?),
+            "datamodel" => datamodel.extend(parse_datamodel(&child)?),
             _ => {},
         }
     }
 
-    Ok(Parallel { id, transitions, onentry, onexit, children, invokes })
+    Ok(Parallel { id, transitions, onentry, onexit, children, invokes, datamodel, span: Span::of(node) })
 }
 
 // Helper to parse <final>.
@@ -913,6 +1721,7 @@ fn parse_final(node: &Node) -> Result<Final, ParseError> {
     let id = node.attribute("id").map(|s| s.to_string());
     let mut onentry = Vec::new();
     let mut onexit = Vec::new();
+    let mut donedata = None;
 
     // Process child elements.
     for child in node.children() {
@@ -922,11 +1731,36 @@ fn parse_final(node: &Node) -> Result<Final, ParseError> {
         match child.tag_name().name() {
             "onentry" => onentry.extend(parse_executables(&child)?),
             "onexit" => onexit.extend(parse_executables(&child)?),
+            "donedata" => donedata = Some(parse_donedata(&child)?),
             _ => {},
         }
     }
 
-    Ok(Final { id, onentry, onexit })
+    Ok(Final { id, onentry, onexit, donedata, span: Span::of(node) })
+}
+
+// Helper to parse <donedata>.
+fn parse_donedata(node: &Node) -> Result<DoneData, ParseError> {
+    let mut donedata = DoneData::default();
+    for child in node.children() {
+        if !child.is_element() {
+            continue;
+        }
+        match child.tag_name().name() {
+            "content" => {
+                if donedata.content.is_some() {
+                    return Err(ParseError::InvalidStructure(
+                        "<donedata> can have only one <content>".into(),
+                        Span::of(&child),
+                    ));
+                }
+                donedata.content = Some(parse_content(&child)?);
+            }
+            "param" => donedata.params.push(parse_param(&child)?),
+            _ => {},
+        }
+    }
+    Ok(donedata)
 }
 
 // Helper to parse <transition>.
@@ -934,11 +1768,12 @@ fn parse_transition(node: &Node) -> Result<Transition, ParseError> {
     let executables = parse_executables(node)?;
 
     Ok(Transition {
-        event: node.attribute("event").map(|s| s.to_string()),
+        event: node.attribute("event").map(EventDescriptor::new),
         cond: node.attribute("cond").map(|s| s.to_string()),
         target: node.attribute("target").map(|s| s.to_string()),
         type_: node.attribute("type").map(|s| s.to_string()),
         executables,
+        span: Span::of(node),
     })
 }
 
@@ -954,7 +1789,8 @@ fn parse_initial(node: &Node) -> Result<Initial, ParseError> {
         }
     }
 
-    let transition = transition.ok_or(ParseError::InvalidStructure("Initial must have a transition".into()))?;
+    let transition = transition
+        .ok_or_else(|| ParseError::InvalidStructure("Initial must have a transition".into(), Span::of(node)))?;
     Ok(Initial { id, transition })
 }
 
@@ -971,14 +1807,19 @@ fn parse_history(node: &Node) -> Result<History, ParseError> {
         }
     }
 
-    Ok(History { id, type_, transition })
+    Ok(History { id, type_, transition, span: Span::of(node) })
 }
 
 // Helper to parse <invoke>.
 fn parse_invoke(node: &Node) -> Result<Invoke, ParseError> {
     let type_ = node.attribute("type").unwrap_or("").to_string();
+    let typeexpr = node.attribute("typeexpr").map(|s| s.to_string());
     let src = node.attribute("src").map(|s| s.to_string());
+    let srcexpr = node.attribute("srcexpr").map(|s| s.to_string());
     let id = node.attribute("id").map(|s| s.to_string());
+    let idlocation = node.attribute("idlocation").map(|s| s.to_string());
+    let namelist = node.attribute("namelist").map(|s| s.to_string());
+    let autoforward = node.attribute("autoforward") == Some("true");
 
     let mut params = Vec::new();
     let mut finalize = None;
@@ -997,18 +1838,69 @@ fn parse_invoke(node: &Node) -> Result<Invoke, ParseError> {
         }
     }
 
-    Ok(Invoke { type_, src, id, params, finalize, content })
+    Ok(Invoke {
+        type_,
+        typeexpr,
+        src,
+        srcexpr,
+        id,
+        idlocation,
+        namelist,
+        autoforward,
+        params,
+        finalize,
+        content,
+        span: Span::of(node),
+    })
 }
 
 // Helper to parse <param>.
 fn parse_param(node: &Node) -> Result<Param, ParseError> {
-    let name = node.attribute("name").ok_or(ParseError::MissingAttribute("param name".into()))?.to_string();
+    let name = node
+        .attribute("name")
+        .ok_or_else(|| ParseError::MissingAttribute("param name".into(), Span::of(node)))?
+        .to_string();
     let expr = node.attribute("expr").map(|s| s.to_string());
     let location = node.attribute("location").map(|s| s.to_string());
 
     Ok(Param { name, expr, location })
 }
 
+// Helper to parse <send>.
+fn parse_send(node: &Node) -> Result<SendElement, ParseError> {
+    let attribute = |name: &str| node.attribute(name).map(|s| s.to_string());
+
+    let mut params = Vec::new();
+    let mut content = None;
+    for child in node.children() {
+        if !child.is_element() {
+            continue;
+        }
+        match child.tag_name().name() {
+            "param" => params.push(parse_param(&child)?),
+            "content" => content = Some(parse_content(&child)?),
+            _ => {},
+        }
+    }
+
+    Ok(SendElement {
+        event: attribute("event"),
+        eventexpr: attribute("eventexpr"),
+        target: attribute("target"),
+        targetexpr: attribute("targetexpr"),
+        type_: attribute("type"),
+        typeexpr: attribute("typeexpr"),
+        id: attribute("id"),
+        idlocation: attribute("idlocation"),
+        delay: attribute("delay"),
+        delayexpr: attribute("delayexpr"),
+        namelist: attribute("namelist"),
+        params,
+        content,
+        span: Span::of(node),
+    })
+}
+
 // Helper to parse <finalize>.
 fn parse_finalize(node: &Node) -> Result<Finalize, ParseError> {
     let executables = parse_executables(node)?;
@@ -1018,7 +1910,12 @@ fn parse_finalize(node: &Node) -> Result<Finalize, ParseError> {
 // Helper to parse <content>.
 fn parse_content(node: &Node) -> Result<Content, ParseError> {
     let expr = node.attribute("expr").map(|s| s.to_string());
-    let content = node.text().map(|s| s.to_string());
+    // Child elements, such as an inline <scxml> document, are kept as markup.
+    let content = if node.children().any(|child| child.is_element()) {
+        Some(ContentBody::Markup(children_markup(node)))
+    } else {
+        node.text().map(|s| ContentBody::Text(s.to_string()))
+    };
 
     Ok(Content { expr, content })
 }
@@ -1028,11 +1925,14 @@ fn parse_datamodel(node: &Node) -> Result<Vec<Data>, ParseError> {
     let mut data_elements = Vec::new();
     for child in node.children() {
         if child.is_element() && child.tag_name().name() == "data" {
-            let id = child.attribute("id").ok_or(ParseError::MissingAttribute("data id".into()))?.to_string();
+            let id = child
+                .attribute("id")
+                .ok_or_else(|| ParseError::MissingAttribute("data id".into(), Span::of(&child)))?
+                .to_string();
             let expr = child.attribute("expr").map(|s| s.to_string());
             let src = child.attribute("src").map(|s| s.to_string());
             let content = child.text().map(|s| s.to_string());
-            data_elements.push(Data { id, expr, src, content });
+            data_elements.push(Data { id, expr, src, content, span: Span::of(&child) });
         }
     }
     Ok(data_elements)
@@ -1052,59 +1952,147 @@ fn parse_executables(node: &Node) -> Result<Vec<Executable>, ParseError> {
 
 // Helper to parse a single executable element.
 fn parse_single_executable(node: &Node) -> Result<Executable, ParseError> {
+    let span = Span::of(node);
     match node.tag_name().name() {
         "raise" => Ok(Executable::Raise {
             event: node.attribute("event").unwrap_or("").to_string(),
+            span,
         }),
         "if" => {
             let cond = node.attribute("cond").unwrap_or("").to_string();
-            let mut then = Vec::new();
-            let mut else_ = Vec::new();
-            let mut in_else = false;
+            let mut branches = vec![IfBranch { cond, body: Vec::new() }];
+            let mut else_: Option<Vec<Executable>> = None;
             for subchild in node.children() {
-                if subchild.is_element() {
-                    if subchild.tag_name().name() == "else" {
-                        in_else = true;
-                        continue;
+                if !subchild.is_element() {
+                    continue;
+                }
+                match subchild.tag_name().name() {
+                    "elseif" => {
+                        if else_.is_some() {
+                            return Err(ParseError::InvalidStructure(
+                                "<elseif> cannot follow <else>".into(),
+                                Span::of(&subchild),
+                            ));
+                        }
+                        let cond = subchild
+                            .attribute("cond")
+                            .ok_or_else(|| ParseError::MissingAttribute("elseif cond".into(), Span::of(&subchild)))?;
+                        branches.push(IfBranch { cond: cond.to_string(), body: Vec::new() });
                     }
-                    let sub_exec = parse_single_executable(&subchild)?;
-                    if in_else {
-                        else_.push(sub_exec);
-                    } else {
-                        then.push(sub_exec);
+                    "else" => {
+                        if else_.is_some() {
+                            return Err(ParseError::InvalidStructure(
+                                "<if> can have only one <else>".into(),
+                                Span::of(&subchild),
+                            ));
+                        }
+                        else_ = Some(Vec::new());
+                    }
+                    _ => {
+                        let sub_exec = parse_single_executable(&subchild)?;
+                        match else_ {
+                            Some(ref mut body) => body.push(sub_exec),
+                            None => branches.last_mut().expect("<if> has a first branch").body.push(sub_exec),
+                        }
                     }
                 }
             }
-            Ok(Executable::If { cond, then, else_ })
+            Ok(Executable::If { branches, else_, span })
         }
         "foreach" => {
             let array = node.attribute("array").unwrap_or("").to_string();
             let item = node.attribute("item").unwrap_or("").to_string();
             let index = node.attribute("index").map(|s| s.to_string());
             let body = parse_executables(node)?;
-            Ok(Executable::Foreach { array, item, index, body })
+            Ok(Executable::Foreach { array, item, index, body, span })
         }
-        "send" => Ok(Executable::Send {
-            event: node.attribute("event").unwrap_or("").to_string(),
-            target: node.attribute("target").map(|s| s.to_string()),
-        }),
+        "send" => Ok(Executable::Send(Box::new(parse_send(node)?))),
         "script" => Ok(Executable::Script {
             src: node.attribute("src").map(|s| s.to_string()),
             content: node.text().map(|s| s.to_string()),
+            span,
         }),
         "assign" => Ok(Executable::Assign {
             location: node.attribute("location").unwrap_or("").to_string(),
             expr: node.attribute("expr").unwrap_or("").to_string(),
+            span,
         }),
         "log" => Ok(Executable::Log {
             label: node.attribute("label").map(|s| s.to_string()),
             expr: node.attribute("expr").unwrap_or("").to_string(),
+            span,
         }),
         "cancel" => Ok(Executable::Cancel {
-            sendid: node.attribute("sendid").unwrap_or("").to_string(),
+            sendid: node.attribute("sendid").map(|s| s.to_string()),
+            sendidexpr: node.attribute("sendidexpr").map(|s| s.to_string()),
+            span,
         }),
-        _ => Ok(Executable::Other(node.tag_name().name().to_string())),
+        _ => Ok(Executable::Other { name: node.tag_name().name().to_string(), markup: element_markup(node), span }),
+    }
+}
+
+// Helper to capture an element as its source markup. Namespace prefixes the element or its
+// descendants use but inherit from an ancestor are declared on it, so the markup stands on
+// its own wherever it is written back. The SCXML namespace is left to the enclosing document.
+fn element_markup(node: &Node) -> String {
+    let source = &node.document().input_text()[node.range()];
+    let start_tag = &source[..start_tag_len(source)];
+    let inherited: Vec<_> = node.parent_element().map(|parent| parent.namespaces().collect()).unwrap_or_default();
+    let used: std::collections::HashSet<&str> = node
+        .descendants()
+        .filter(|n| n.is_element())
+        .flat_map(|n| n.tag_name().namespace().into_iter().chain(n.attributes().filter_map(|a| a.namespace())))
+        .collect();
+
+    let mut declarations = String::new();
+    for namespace in node.namespaces() {
+        let attribute = match namespace.name() {
+            Some("xml") => continue,
+            Some(prefix) => format!("xmlns:{}", prefix),
+            None if namespace.uri() == SCXML_NS => continue,
+            None => "xmlns".to_string(),
+        };
+        let is_inherited = inherited.iter().any(|n| n.name() == namespace.name() && n.uri() == namespace.uri());
+        let is_declared = start_tag.split(|c: char| c.is_whitespace()).any(|token| token.split('=').next() == Some(attribute.as_str()));
+        if is_inherited && !is_declared && used.contains(namespace.uri()) {
+            push_attribute(&mut declarations, &attribute, namespace.uri());
+        }
+    }
+
+    let name_end = source.find(|c: char| c.is_whitespace() || c == '/' || c == '>').unwrap_or(source.len());
+    format!("{}{}{}", &source[..name_end], declarations, &source[name_end..])
+}
+
+// Helper to capture the children of an element as markup: the source between its tags,
+// with each child element captured by `element_markup`.
+fn children_markup(node: &Node) -> String {
+    let source = node.document().input_text();
+    let (Some(first), Some(last)) = (node.first_child(), node.last_child()) else {
+        return String::new();
+    };
+    let mut markup = String::new();
+    let mut position = first.range().start;
+    for child in node.children().filter(|child| child.is_element()) {
+        markup.push_str(&source[position..child.range().start]);
+        markup.push_str(&element_markup(&child));
+        position = child.range().end;
     }
+    markup.push_str(&source[position..last.range().end]);
+    markup
+}
+
+// Helper to find the length of the start tag at the beginning of an element's markup.
+fn start_tag_len(markup: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in markup.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    markup.len()
 }
 
 #[cfg(test)]
@@ -1141,10 +2129,11 @@ mod tests {
         assert_eq!(scxml.datamodel_elements[0].id, "var1");
         if let StateLike::State(state) = &scxml.states[0] {
             assert_eq!(state.id, Some("start".to_string()));
-            if let Executable::If { cond, then, else_ } = &state.onentry[0] {
-                assert_eq!(cond, "var1 == 0");
-                assert_eq!(then.len(), 1);
-                assert_eq!(else_.len(), 1);
+            if let Executable::If { branches, else_, .. } = &state.onentry[0] {
+                assert_eq!(branches.len(), 1);
+                assert_eq!(branches[0].cond, "var1 == 0");
+                assert_eq!(branches[0].body.len(), 1);
+                assert_eq!(else_.as_ref().map(Vec::len), Some(1));
             }
             if let Executable::Foreach { array, item, .. } = &state.onentry[1] {
                 assert_eq!(array, "items");
@@ -1166,7 +2155,7 @@ mod tests {
             <state id="start"/>
         </scxml>
         "#;
-        assert!(matches!(parse_scxml(xml), Err(ParseError::InvalidNamespace(_))));
+        assert!(matches!(parse_scxml(xml), Err(ParseError::InvalidNamespace(..))));
     }
 
     #[test]
@@ -1175,7 +2164,7 @@ mod tests {
         let blackjack_xml = fs::read_to_string("examples/blackjack.scxml")
             .expect("Should read blackjack.scxml");
 
-        let options = ParseOptions { relaxed_namespace: true };
+        let options = ParseOptions { relaxed_namespace: true, ..Default::default() };
         let result = parse_scxml_with_options(&blackjack_xml, options);
         assert!(result.is_ok(), "Blackjack SCXML should parse successfully: {:?}", result.err());
 
@@ -1225,7 +2214,7 @@ mod tests {
 
         let scxml = parse_scxml(xml).unwrap();
         let result = validate(&scxml);
-        assert!(matches!(result, Err(ValidationError::DuplicateId(_))));
+        assert!(matches!(result, Err(ValidationError::DuplicateId(..))));
     }
 
     #[test]
@@ -1238,7 +2227,7 @@ mod tests {
 
         let scxml = parse_scxml(xml).unwrap();
         let result = validate(&scxml);
-        assert!(matches!(result, Err(ValidationError::InvalidTarget(_))));
+        assert!(matches!(result, Err(ValidationError::InvalidTarget(..))));
     }
 
     #[test]
@@ -1280,7 +2269,7 @@ mod tests {
         for filename in &example_files {
             let path = format!("examples/{}", filename);
             if let Ok(content) = fs::read_to_string(&path) {
-                let options = ParseOptions { relaxed_namespace: true };
+                let options = ParseOptions { relaxed_namespace: true, ..Default::default() };
                 let result = parse_scxml_with_options(&content, options);
                 assert!(result.is_ok(), "Failed to parse {}: {:?}", filename, result.err());
 
@@ -1295,6 +2284,332 @@ mod tests {
         }
     }
 
+    // Helper to reset every span, so documents parsed from different sources compare by structure.
+    fn without_spans(mut scxml: Scxml) -> Scxml {
+        scxml.span = Span::default();
+        clear_data_spans(&mut scxml.datamodel_elements);
+        clear_state_spans(&mut scxml.states);
+        scxml
+    }
+
+    fn clear_state_spans(states: &mut [StateLike]) {
+        for state in states {
+            match state {
+                StateLike::State(s) => {
+                    s.span = Span::default();
+                    if let Some(initial) = &mut s.initial_element {
+                        clear_transition_spans(&mut initial.transition);
+                    }
+                    s.transitions.iter_mut().for_each(clear_transition_spans);
+                    clear_executable_spans(&mut s.onentry);
+                    clear_executable_spans(&mut s.onexit);
+                    clear_state_spans(&mut s.children);
+                    clear_invoke_spans(&mut s.invokes);
+                    clear_data_spans(&mut s.datamodel);
+                }
+                StateLike::Parallel(p) => {
+                    p.span = Span::default();
+                    p.transitions.iter_mut().for_each(clear_transition_spans);
+                    clear_executable_spans(&mut p.onentry);
+                    clear_executable_spans(&mut p.onexit);
+                    clear_state_spans(&mut p.children);
+                    clear_invoke_spans(&mut p.invokes);
+                    clear_data_spans(&mut p.datamodel);
+                }
+                StateLike::Final(f) => {
+                    f.span = Span::default();
+                    clear_executable_spans(&mut f.onentry);
+                    clear_executable_spans(&mut f.onexit);
+                }
+                StateLike::History(h) => {
+                    h.span = Span::default();
+                    h.transition.iter_mut().for_each(clear_transition_spans);
+                }
+            }
+        }
+    }
+
+    fn clear_transition_spans(transition: &mut Transition) {
+        transition.span = Span::default();
+        clear_executable_spans(&mut transition.executables);
+    }
+
+    fn clear_invoke_spans(invokes: &mut [Invoke]) {
+        for invoke in invokes {
+            invoke.span = Span::default();
+            if let Some(finalize) = &mut invoke.finalize {
+                clear_executable_spans(&mut finalize.executables);
+            }
+        }
+    }
+
+    fn clear_data_spans(data: &mut [Data]) {
+        data.iter_mut().for_each(|d| d.span = Span::default());
+    }
+
+    fn clear_executable_spans(executables: &mut [Executable]) {
+        for executable in executables {
+            match executable {
+                Executable::Send(send) => send.span = Span::default(),
+                Executable::If { branches, else_, span } => {
+                    *span = Span::default();
+                    branches.iter_mut().for_each(|branch| clear_executable_spans(&mut branch.body));
+                    else_.iter_mut().for_each(|body| clear_executable_spans(body));
+                }
+                Executable::Foreach { body, span, .. } => {
+                    *span = Span::default();
+                    clear_executable_spans(body);
+                }
+                Executable::Raise { span, .. }
+                | Executable::Script { span, .. }
+                | Executable::Assign { span, .. }
+                | Executable::Log { span, .. }
+                | Executable::Cancel { span, .. }
+                | Executable::Other { span, .. } => *span = Span::default(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_examples() {
+        use std::fs;
+
+        let mut paths: Vec<_> = fs::read_dir("examples")
+            .expect("Should read examples directory")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "scxml"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let content = fs::read_to_string(&path).unwrap();
+            let options = ParseOptions { relaxed_namespace: true, ..Default::default() };
+            let parsed = parse_scxml_with_options(&content, options).unwrap();
+            let serialized = to_xml(&parsed);
+            let reparsed = parse_scxml(&serialized)
+                .unwrap_or_else(|e| panic!("Serialized {} does not parse: {}", path.display(), e));
+            assert_eq!(to_xml(&reparsed), serialized, "Serialization of {} is not stable", path.display());
+            assert_eq!(without_spans(parsed), without_spans(reparsed), "Round trip changed {}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_serialize_all_elements() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" xmlns:my="urn:example:my" version="1.0" name="all" binding="late">
+            <parallel id="p">
+                <datamodel><data id="local" expr="1"/></datamodel>
+                <onentry><cancel sendid="t1"/></onentry>
+                <state id="r1">
+                    <history id="h" type="deep"><transition target="r1a"><raise event="restored"/></transition></history>
+                    <state id="r1a"/>
+                </state>
+                <transition event="stop" target="end"/>
+                <onexit>
+                    <foreach array="items" item="item" index="i"><log label="item" expr="item"/></foreach>
+                </onexit>
+                <invoke type="scxml" id="child">
+                    <content>inline</content>
+                    <finalize><script>done()</script></finalize>
+                </invoke>
+                <invoke typeexpr="'scxml'" srcexpr="'a.scxml'" idlocation="childid" namelist="x y"/>
+            </parallel>
+            <final id="end">
+                <onentry><raise event="bye"/></onentry>
+                <onexit>
+                    <custom mode="fast"/>
+                    <my:notify channel="ops" level="2"><my:arg value="a &amp; b"/></my:notify>
+                </onexit>
+            </final>
+        </scxml>"#;
+
+        let scxml = parse_scxml(xml).unwrap();
+        let serialized = to_xml(&scxml);
+        assert!(!serialized.contains("Unsupported"));
+        assert!(serialized.contains(r#"name="all""#));
+        assert!(serialized.contains(r#"<cancel sendid="t1"/>"#));
+        assert!(serialized.contains(r#"<foreach array="items" item="item" index="i">"#));
+        assert!(serialized.contains("<finalize>"));
+        assert!(serialized.contains("<content>inline</content>"));
+        assert!(serialized.contains(r#"<invoke typeexpr="'scxml'" srcexpr="'a.scxml'" idlocation="childid" namelist="x y"/>"#));
+        assert!(serialized.contains(r#"<custom mode="fast"/>"#));
+        assert!(serialized.contains(r#"<my:notify xmlns:my="urn:example:my" channel="ops" level="2"><my:arg value="a &amp; b"/></my:notify>"#));
+        assert_eq!(without_spans(parse_scxml(&serialized).unwrap()), without_spans(scxml));
+    }
+
+    #[test]
+    fn test_content_markup_round_trip() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" xmlns:ext="urn:example:ext" version="1.0">
+            <state id="s">
+                <invoke type="scxml" id="child">
+                    <content>
+                        <scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0"><state id="inner"><ext:hook/></state></scxml>
+                    </content>
+                </invoke>
+                <onentry>
+                    <send event="mixed"><content>before <ext:item n="1"/> after &amp; more</content></send>
+                    <send event="text"><content>a &lt; b</content></send>
+                </onentry>
+            </state>
+        </scxml>"#;
+
+        let scxml = parse_scxml(xml).unwrap();
+        let serialized = to_xml(&scxml);
+        assert!(!serialized.contains("&lt;scxml"));
+        assert!(serialized.contains(r#"<content>before <ext:item xmlns:ext="urn:example:ext" n="1"/> after &amp; more</content>"#));
+        assert!(serialized.contains("<content>a &lt; b</content>"));
+        let reparsed = parse_scxml(&serialized).unwrap();
+        assert_eq!(to_xml(&reparsed), serialized);
+        assert_eq!(without_spans(reparsed.clone()), without_spans(scxml));
+
+        // The serialized <content> holds elements, not text.
+        let document = Document::parse(&serialized).unwrap();
+        let content_of = |parent: &str, index: usize| {
+            let parents = document.descendants().filter(|n| n.has_tag_name((SCXML_NS, parent)));
+            parents.filter_map(|n| n.first_element_child()).nth(index).unwrap()
+        };
+        let inline = content_of("invoke", 0).first_element_child().unwrap();
+        assert!(inline.has_tag_name((SCXML_NS, "scxml")));
+        assert!(inline.descendants().any(|n| n.has_tag_name(("urn:example:ext", "hook"))));
+        assert!(content_of("send", 0).children().any(|n| n.has_tag_name(("urn:example:ext", "item"))));
+        assert_eq!(content_of("send", 1).text(), Some("a < b"));
+
+        // The markup of the inline document parses on its own.
+        let StateLike::State(s) = &reparsed.states[0] else { panic!("expected <state>") };
+        let Some(ContentBody::Markup(markup)) = &s.invokes[0].content.as_ref().unwrap().content else { panic!("expected markup") };
+        let inner = parse_scxml(markup.trim()).unwrap();
+        let StateLike::State(inner_state) = &inner.states[0] else { panic!("expected <state>") };
+        assert_eq!(inner_state.id.as_deref(), Some("inner"));
+    }
+
+    #[test]
+    fn test_serialization_escapes() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <datamodel>
+                <data id="d">a &lt; b &amp;&amp; c</data>
+            </datamodel>
+            <state id="s">
+                <transition event="go" cond="x &lt; 3 &amp;&amp; y &gt; &quot;a&quot; &amp;&amp; z != 'q'" target="s">
+                    <log label="multi&#10;line" expr="'tab&#9;here'"/>
+                    <script>if (a &lt; b &amp;&amp; s != "]]&gt;") { go(); }</script>
+                </transition>
+            </state>
+        </scxml>"#;
+
+        let scxml = parse_scxml(xml).unwrap();
+        let serialized = to_xml(&scxml);
+        assert!(serialized.contains(r#"cond="x &lt; 3 &amp;&amp; y &gt; &quot;a&quot; &amp;&amp; z != 'q'""#));
+        assert!(serialized.contains(r#"label="multi&#10;line""#));
+        assert!(serialized.contains("<![CDATA[if (a < b && s != \"]]]]><![CDATA[>\") { go(); }]]>"));
+        assert!(serialized.contains("a &lt; b &amp;&amp; c</data>"));
+        assert_eq!(without_spans(parse_scxml(&serialized).unwrap()), without_spans(scxml));
+    }
+
+    #[test]
+    fn test_spans() {
+        let xml = "<scxml xmlns=\"http://www.w3.org/2005/07/scxml\" version=\"1.0\">\n  <state id=\"a\">\n    <transition event=\"go\" target=\"b\"><raise event=\"x\"/></transition>\n  </state>\n  <state id=\"a\"/>\n</scxml>";
+
+        let scxml = parse_scxml(xml).unwrap();
+        let StateLike::State(state) = &scxml.states[0] else { panic!("expected <state>") };
+        assert_eq!((state.span.line, state.span.column), (2, 3));
+        assert!(xml[state.span.range()].starts_with("<state id=\"a\">"));
+        assert!(xml[state.span.range()].ends_with("</state>"));
+        let transition = &state.transitions[0];
+        assert_eq!((transition.span.line, transition.span.column), (3, 5));
+        assert_eq!(transition.executables[0].span().line, 3);
+        assert_ne!(parse_scxml(&xml.replace("\n  ", "\n")).unwrap(), scxml);
+
+        let error = validate(&scxml).unwrap_err();
+        assert!(matches!(error, ValidationError::DuplicateId(ref id, _) if id == "a"));
+        assert_eq!(error.span().line, 5);
+        assert!(error.to_string().ends_with("at 5:3"));
+
+        let missing = "<scxml xmlns=\"http://www.w3.org/2005/07/scxml\" version=\"1.0\">\n<datamodel>\n  <data expr=\"1\"/>\n</datamodel>\n</scxml>";
+        let error = parse_scxml(missing).unwrap_err();
+        assert!(matches!(error, ParseError::MissingAttribute(..)));
+        assert_eq!((error.span().line, error.span().column), (3, 3));
+
+        let error = parse_scxml("<scxml>").unwrap_err();
+        assert_eq!(error.span().line, 1);
+    }
+
+    #[test]
+    fn test_validate_all() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" initial="missing">
+            <datamodel><data id="x"/></datamodel>
+            <state id="a">
+                <datamodel><data id="x"/></datamodel>
+                <onentry><custom/></onentry>
+                <transition target="a"/>
+                <transition event="go" target="nowhere b"/>
+            </state>
+            <state id="a">
+                <initial><transition target="inner"><cancel/></transition></initial>
+                <state id="inner"/>
+                <invoke type="scxml"><finalize><custom/></finalize></invoke>
+                <state/>
+            </state>
+            <final id="f"><donedata><content expr="1"/><param name="p" expr="2"/></donedata></final>
+        </scxml>"#;
+        let scxml = parse_scxml(xml).unwrap();
+        let diagnostics = validate_all(&scxml);
+        let summary: Vec<(Severity, &str, &str)> = diagnostics.iter().map(|d| (d.severity, d.code, d.path.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Severity::Error, "duplicate-id", "/scxml/state[@id='a']"),
+                (Severity::Error, "invalid-target", "/scxml/state[@id='a']/transition[2]"),
+                (Severity::Error, "invalid-target", "/scxml/state[@id='a']/transition[2]"),
+                (Severity::Error, "invalid-target", "/scxml"),
+                (Severity::Error, "duplicate-data-id", "/scxml/state[@id='a']/datamodel/data[@id='x']"),
+                (Severity::Error, "invalid-donedata", "/scxml/final[@id='f']/donedata"),
+                (Severity::Warning, "eventless-self-loop", "/scxml/state[@id='a']/transition[1]"),
+                (Severity::Warning, "unknown-element", "/scxml/state[@id='a']/onentry/custom"),
+                (Severity::Error, "invalid-cancel", "/scxml/state[@id='a']/initial/transition/cancel"),
+                (Severity::Warning, "unknown-element", "/scxml/state[@id='a']/invoke[1]/finalize/custom"),
+                (Severity::Info, "anonymous-state", "/scxml/state[@id='a']/state[2]"),
+            ]
+        );
+        assert_eq!(diagnostics[0].span.line, 9);
+        assert!(diagnostics[1].message.contains("nowhere"));
+        assert!(diagnostics[2].message.contains("'b'"));
+        assert!(diagnostics[0].to_string().starts_with("error[duplicate-id] duplicate state ID 'a'"));
+
+        // `validate` reports the first error only.
+        assert!(matches!(validate(&scxml), Err(ValidationError::DuplicateId(id, _)) if id == "a"));
+    }
+
+    #[test]
+    fn test_event_descriptor() {
+        let digit = EventDescriptor::new("DIGIT");
+        assert!(digit.matches("DIGIT"));
+        assert!(digit.matches("DIGIT.0"));
+        assert!(digit.matches("DIGIT.0.extra"));
+        assert!(!digit.matches("DIGITS"));
+        assert!(!digit.matches("OPER.DIGIT"));
+
+        assert!(EventDescriptor::new("DIGIT.*").matches("DIGIT.9"));
+        assert!(EventDescriptor::new("DIGIT.").matches("DIGIT"));
+        assert!(EventDescriptor::new("*").matches("anything.at.all"));
+        assert!(!EventDescriptor::new("OPER.PLUS").matches("OPER"));
+
+        let list = EventDescriptor::new("  OPER   error.* ");
+        assert!(list.matches("OPER.MINUS"));
+        assert!(list.matches("error.execution"));
+        assert!(!list.matches("done.state.a"));
+        assert_eq!(list.descriptors().collect::<Vec<_>>(), vec!["OPER", "error.*"]);
+        assert!(!EventDescriptor::new("").matches("a"));
+
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="a"><transition event="DIGIT OPER" target="a"/></state>
+        </scxml>"#;
+        let scxml = parse_scxml(xml).unwrap();
+        let StateLike::State(state) = &scxml.states[0] else { panic!("expected <state>") };
+        let event = state.transitions[0].event.as_ref().unwrap();
+        assert!(event.matches("OPER.MINUS"));
+        assert!(to_xml(&scxml).contains(r#"event="DIGIT OPER""#));
+    }
+
     #[test]
     fn test_invoke_with_params() {
         let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
@@ -1323,6 +2638,173 @@ mod tests {
     }
 
     #[test]
+    fn test_generate_ids() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="_state_1">
+                <state/>
+                <final/>
+            </state>
+            <state/>
+        </scxml>"#;
+
+        let scxml = parse_scxml(xml).unwrap();
+        assert!(scxml.generated_ids.is_empty());
+
+        let options = ParseOptions { generate_ids: true, ..Default::default() };
+        let scxml = parse_scxml_with_options(xml, options).unwrap();
+        assert_eq!(scxml.generated_ids, vec!["_state_0_0", "_final_0_1", "_state_1_1"]);
+        let StateLike::State(outer) = &scxml.states[0] else { panic!("expected <state>") };
+        let StateLike::Final(f) = &outer.children[1] else { panic!("expected <final>") };
+        assert_eq!(f.id.as_deref(), Some("_final_0_1"));
+        assert!(validate(&scxml).is_ok());
+    }
+
+    #[test]
+    fn test_send_attributes() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s">
+                <onentry>
+                    <send target="_internal" event="OP.INSERT" id="op" delay="1s" namelist="a b">
+                        <param name="operator" expr="_event.name"/>
+                    </send>
+                    <send eventexpr="'ev'" targetexpr="dest" typeexpr="kind" idlocation="sid" delayexpr="d">
+                        <content expr="payload"/>
+                    </send>
+                </onentry>
+            </state>
+        </scxml>"#;
+
+        let scxml = parse_scxml(xml).unwrap();
+        let StateLike::State(state) = &scxml.states[0] else { panic!("expected <state>") };
+        let Executable::Send(send) = &state.onentry[0] else { panic!("expected <send>") };
+        assert_eq!(send.event.as_deref(), Some("OP.INSERT"));
+        assert_eq!(send.target.as_deref(), Some("_internal"));
+        assert_eq!(send.id.as_deref(), Some("op"));
+        assert_eq!(send.delay.as_deref(), Some("1s"));
+        assert_eq!(send.namelist.as_deref(), Some("a b"));
+        assert_eq!(send.params[0].name, "operator");
+        assert_eq!(send.params[0].expr.as_deref(), Some("_event.name"));
+
+        let Executable::Send(send) = &state.onentry[1] else { panic!("expected <send>") };
+        assert_eq!(send.event, None);
+        assert_eq!(send.eventexpr.as_deref(), Some("'ev'"));
+        assert_eq!(send.idlocation.as_deref(), Some("sid"));
+        assert_eq!(send.content.as_ref().unwrap().expr.as_deref(), Some("payload"));
+
+        let serialized = to_xml(&scxml);
+        assert!(serialized.contains(r#"<send event="OP.INSERT" target="_internal" id="op" delay="1s" namelist="a b">"#));
+        assert!(serialized.contains(r#"<param name="operator" expr="_event.name"/>"#));
+        assert!(serialized.contains(r#"<content expr="payload"/>"#));
+        let reparsed = parse_scxml(&serialized).unwrap();
+        let StateLike::State(state) = &reparsed.states[0] else { panic!("expected <state>") };
+        assert!(matches!(&state.onentry[1], Executable::Send(s) if s.delayexpr.as_deref() == Some("d")));
+    }
+
+    #[test]
+    fn test_elseif_branches() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s">
+                <onentry>
+                    <if cond="x == 1">
+                        <raise event="one"/>
+                    <elseif cond="x == 2"/>
+                        <raise event="two"/>
+                        <raise event="deux"/>
+                    <elseif cond="x == 3"/>
+                    <else/>
+                        <raise event="other"/>
+                    </if>
+                </onentry>
+            </state>
+        </scxml>"#;
+
+        let scxml = parse_scxml(xml).unwrap();
+        let StateLike::State(state) = &scxml.states[0] else { panic!("expected <state>") };
+        let Executable::If { branches, else_, .. } = &state.onentry[0] else { panic!("expected <if>") };
+        let conds: Vec<&str> = branches.iter().map(|b| b.cond.as_str()).collect();
+        assert_eq!(conds, vec!["x == 1", "x == 2", "x == 3"]);
+        assert_eq!(branches.iter().map(|b| b.body.len()).collect::<Vec<_>>(), vec![1, 2, 0]);
+        assert_eq!(else_.as_ref().unwrap().len(), 1);
+
+        let serialized = to_xml(&scxml);
+        assert!(serialized.contains(r#"<elseif cond="x == 2"/>"#));
+        let reparsed = parse_scxml(&serialized).unwrap();
+        let StateLike::State(state) = &reparsed.states[0] else { panic!("expected <state>") };
+        assert!(matches!(&state.onentry[0], Executable::If { branches, else_: Some(_), .. } if branches.len() == 3));
+
+        let misplaced = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s"><onentry><if cond="a"><else/><elseif cond="b"/></if></onentry></state>
+        </scxml>"#;
+        assert!(matches!(parse_scxml(misplaced), Err(ParseError::InvalidStructure(..))));
+    }
+
+    #[test]
+    fn test_donedata() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s">
+                <final id="f1">
+                    <donedata>
+                        <param name="result" expr="42"/>
+                        <param name="where" location="here"/>
+                    </donedata>
+                </final>
+            </state>
+            <final id="f2">
+                <donedata><content expr="payload"/></donedata>
+            </final>
+        </scxml>"#;
+
+        let scxml = parse_scxml(xml).unwrap();
+        assert!(validate(&scxml).is_ok());
+        let StateLike::State(s) = &scxml.states[0] else { panic!("expected <state>") };
+        let StateLike::Final(f1) = &s.children[0] else { panic!("expected <final>") };
+        let donedata = f1.donedata.as_ref().unwrap();
+        assert_eq!(donedata.params.len(), 2);
+        assert_eq!(donedata.params[1].location.as_deref(), Some("here"));
+
+        let serialized = to_xml(&scxml);
+        let reparsed = parse_scxml(&serialized).unwrap();
+        let StateLike::Final(f2) = &reparsed.states[1] else { panic!("expected <final>") };
+        let content = f2.donedata.as_ref().unwrap().content.as_ref().unwrap();
+        assert_eq!(content.expr.as_deref(), Some("payload"));
+
+        let mixed = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <final id="f"><donedata><content expr="a"/><param name="b" expr="c"/></donedata></final>
+        </scxml>"#;
+        let scxml = parse_scxml(mixed).unwrap();
+        assert!(matches!(validate(&scxml), Err(ValidationError::InvalidDoneData(id, _)) if id == "f"));
+    }
+
+    #[test]
+    fn test_cancel_attributes() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s">
+                <onentry><cancel sendid="t1"/></onentry>
+                <transition event="stop"><if cond="true"><cancel sendidexpr="pending"/></if></transition>
+            </state>
+        </scxml>"#;
+        let scxml = parse_scxml(xml).unwrap();
+        assert!(validate(&scxml).is_ok());
+        let StateLike::State(s) = &scxml.states[0] else { panic!("expected <state>") };
+        let Executable::If { branches, .. } = &s.transitions[0].executables[0] else { panic!("expected <if>") };
+        let Executable::Cancel { sendid, sendidexpr, .. } = &branches[0].body[0] else { panic!("expected <cancel>") };
+        assert_eq!((sendid.as_deref(), sendidexpr.as_deref()), (None, Some("pending")));
+        assert!(to_xml(&scxml).contains(r#"<cancel sendidexpr="pending"/>"#));
+
+        let invalid = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s">
+                <onentry><cancel/></onentry>
+                <onexit><cancel sendid="a" sendidexpr="b"/></onexit>
+            </state>
+        </scxml>"#;
+        let diagnostics = validate_all(&parse_scxml(invalid).unwrap());
+        let paths: Vec<_> = diagnostics.iter().filter(|d| d.code == "invalid-cancel").map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, ["/scxml/state[@id='s']/onentry/cancel", "/scxml/state[@id='s']/onexit/cancel"]);
+        assert!(matches!(validate(&parse_scxml(invalid).unwrap()), Err(ValidationError::InvalidCancel(span)) if span.line == 3));
+    }
+
+    #[test]
+    #[allow(clippy::collapsible_if)]
     fn test_history_states() {
         let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
             <state id="parent">
@@ -0,0 +1,466 @@
+//! The Basic HTTP Event I/O Processor, enabled by the `http-ioprocessor` feature.
+//!
+//! A `<send type="http://www.w3.org/TR/scxml/#BasicHTTPEventProcessor">` POSTs
+//! its event to the `http://` URL in `target` as an
+//! `application/x-www-form-urlencoded` body: the event name in the
+//! `_scxmleventname` field, followed by one field per `namelist` variable and
+//! `<param>`. With `<content>` the content is the body instead, and the event name
+//! is passed in the query string. A connection failure or a response status
+//! other than 2xx raises `error.communication`.
+//!
+//! With the `tokio` feature, a `Session` and the sessions it invokes make their
+//! POSTs on a blocking task so a slow server does not stall the runtime. They are
+//! still made in the order they were sent, and a failure reaches the session as
+//! an external `error.communication` event. A plain [`Interpreter`] always POSTs
+//! synchronously, even on a tokio runtime.
+//!
+//! An [`HttpListener`] accepts such POSTs on a background thread and delivers
+//! them to the session named by the request path, where they are processed as
+//! external events the next time the session runs, or as they arrive in a
+//! `Session`. The event is named by the
+//! `_scxmleventname` field, or `HTTP.POST` without one; the other form fields
+//! become `_event.data`, or the raw body if it is not form-encoded.
+//!
+//! ```rust
+//! use harel::parse_scxml;
+//! use harel::runtime::Interpreter;
+//! use harel::runtime::http::HttpListener;
+//!
+//! let listener = HttpListener::bind("127.0.0.1:0").unwrap();
+//! let receiver = parse_scxml(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+//!     <state id="waiting"><transition event="order.placed" target="placed"/></state>
+//!     <state id="placed"/>
+//! </scxml>"#).unwrap();
+//! let mut receiver = Interpreter::new(&receiver).unwrap();
+//! listener.attach(&mut receiver);
+//! receiver.start().unwrap();
+//!
+//! let sender = parse_scxml(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+//!     <datamodel><data id="kiosk" expr="''"/></datamodel>
+//!     <state id="ordering">
+//!         <transition event="order" target="sent">
+//!             <assign location="kiosk" expr="_event.data"/>
+//!             <send event="order.placed" targetexpr="kiosk"
+//!                   type="http://www.w3.org/TR/scxml/#BasicHTTPEventProcessor">
+//!                 <param name="item" expr="'coffee'"/>
+//!             </send>
+//!         </transition>
+//!     </state>
+//!     <state id="sent"/>
+//! </scxml>"#).unwrap();
+//! let mut sender = Interpreter::new(&sender).unwrap();
+//! sender.start().unwrap();
+//!
+//! let location = listener.location(receiver.sessionid());
+//! sender.send(harel::runtime::Event::new("order").with_data(location)).unwrap();
+//! receiver.tick().unwrap();
+//! assert!(receiver.is_in_state("placed"));
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::datamodel::Value;
+
+use super::{Event, Interpreter, ioprocessor};
+
+/// Type URI of the Basic HTTP Event I/O Processor.
+pub const BASIC_HTTP_EVENT_PROCESSOR: &str = "http://www.w3.org/TR/scxml/#BasicHTTPEventProcessor";
+
+// How long a POST may take to connect, or to send or receive, before it fails.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Requests with a larger body are rejected.
+const MAX_BODY: usize = 1 << 20;
+
+/// Accepts events POSTed by the Basic HTTP Event I/O Processor and delivers them to
+/// running sessions.
+///
+/// A POST to `/<sessionid>` is delivered to that session; see
+/// [`location`](HttpListener::location). The listener stops when dropped.
+#[derive(Debug)]
+pub struct HttpListener {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HttpListener {
+    /// Binds the listener and starts accepting requests on a background thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to listen on; port 0 picks a free port.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(HttpListener)` - The running listener.
+    /// * `Err(std::io::Error)` - If the address cannot be bound.
+    pub fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let _ = serve(stream);
+                }
+            }
+        });
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    /// Returns the address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the URL that events for the given session are POSTed to.
+    pub fn location(&self, sessionid: &str) -> String {
+        format!("http://{}/{}", self.addr, sessionid)
+    }
+
+    /// Advertises the listener as the session's Basic HTTP Event I/O Processor in
+    /// `_ioprocessors`. Call it before [`Interpreter::start`].
+    pub fn attach(&self, interpreter: &mut Interpreter) {
+        let location = self.location(&interpreter.system.sessionid);
+        interpreter.system.ioprocessors.insert(BASIC_HTTP_EVENT_PROCESSOR.to_string(), location.clone());
+        interpreter.system.ioprocessors.insert("basichttp".to_string(), location);
+    }
+}
+
+impl Drop for HttpListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake the accept loop so it sees the flag.
+        let _ = TcpStream::connect_timeout(&self.addr, TIMEOUT);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Handles one request, answering 204 once the event has been delivered.
+fn serve(stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let mut length = 0;
+    let mut form = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else { continue };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => length = value.trim().parse().unwrap_or(usize::MAX),
+            "content-type" => form = value.trim().starts_with("application/x-www-form-urlencoded"),
+            _ => {}
+        }
+    }
+    let status = if method != "POST" {
+        "405 Method Not Allowed"
+    } else if length > MAX_BODY {
+        "413 Content Too Large"
+    } else {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let event = incoming_event(query, &String::from_utf8_lossy(&body), form);
+        match ioprocessor::deliver(path.trim_start_matches('/'), event) {
+            Ok(()) => "204 No Content",
+            Err(_) => "404 Not Found",
+        }
+    };
+    let mut stream = &stream;
+    write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)?;
+    stream.flush()
+}
+
+// Builds the external event for a POST from its query string and body.
+fn incoming_event(query: &str, body: &str, form: bool) -> Event {
+    let mut fields = decode_form(query);
+    if form {
+        fields.extend(decode_form(body));
+    }
+    let name = match fields.remove("_scxmleventname") {
+        Some(Value::String(name)) if !name.is_empty() => name,
+        _ => "HTTP.POST".to_string(),
+    };
+    let data = if !form && !body.is_empty() {
+        Value::String(body.to_string())
+    } else if fields.is_empty() {
+        Value::Null
+    } else {
+        Value::Object(fields)
+    };
+    Event { origintype: Some(BASIC_HTTP_EVENT_PROCESSOR.to_string()), ..Event::new(name).with_data(data) }
+}
+
+// Makes a `Session`'s POSTs on a blocking task of its runtime, started with the first POST.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub(super) struct Poster {
+    runtime: tokio::runtime::Handle,
+    // Target URL, send ID and event of each POST not yet made.
+    queue: Option<std::sync::mpsc::Sender<(String, String, Event)>>,
+}
+
+#[cfg(feature = "tokio")]
+impl Poster {
+    pub(super) fn new(runtime: tokio::runtime::Handle) -> Self {
+        Self { runtime, queue: None }
+    }
+
+    // A poster for a session invoked by this one, on the same runtime.
+    pub(super) fn for_child(&self) -> Self {
+        Self::new(self.runtime.clone())
+    }
+
+    // Queues a POST, delivering its failure to `mailbox`. The task ends once the poster is dropped.
+    fn send(&mut self, url: &str, sendid: &str, event: Event, mailbox: &Arc<ioprocessor::Mailbox>) {
+        let queue = self.queue.get_or_insert_with(|| {
+            let (queue, posts) = std::sync::mpsc::channel::<(String, String, Event)>();
+            let mailbox = mailbox.clone();
+            self.runtime.spawn_blocking(move || {
+                for (url, sendid, event) in posts {
+                    if let Err(cause) = post(&url, &event) {
+                        mailbox.push(super::communication_error(&sendid, format!("cannot deliver '{}' over HTTP: {}", event.name, cause)));
+                    }
+                }
+            });
+            queue
+        });
+        let _ = queue.send((url.to_string(), sendid.to_string(), event));
+    }
+}
+
+impl Interpreter {
+    // POSTs an event to an `http://` URL, raising `error.communication` if it is not accepted.
+    pub(super) fn post(&mut self, target: Option<&str>, sendid: &str, event: Event) {
+        let Some(url) = target else {
+            let cause = "the Basic HTTP Event I/O Processor needs a target URL";
+            return self.raise_communication_error(sendid, format!("cannot deliver '{}' over HTTP: {}", event.name, cause));
+        };
+        #[cfg(feature = "tokio")]
+        if let Some(poster) = &mut self.poster {
+            return poster.send(url, sendid, event, &self.mailbox);
+        }
+        if let Err(cause) = post(url, &event) {
+            self.raise_communication_error(sendid, format!("cannot deliver '{}' over HTTP: {}", event.name, cause));
+        }
+    }
+}
+
+
+// Sends the POST request for an event and checks the response status.
+fn post(url: &str, event: &Event) -> Result<(), String> {
+    let rest = url.strip_prefix("http://").ok_or_else(|| format!("'{}' is not an http:// URL", url))?;
+    let (authority, path) = rest.find(['/', '?']).map_or((rest, "/"), |i| rest.split_at(i));
+    let path = if path.starts_with('?') { format!("/{}", path) } else { path.to_string() };
+    let addr = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+    let addr = addr
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve '{}': {}", authority, e))?
+        .next()
+        .ok_or_else(|| format!("cannot resolve '{}'", authority))?;
+
+    let name_field = format!("_scxmleventname={}", encode(&event.name));
+    let (path, content_type, body) = match &event.data {
+        Value::Object(fields) => {
+            let fields = fields.iter().map(|(key, value)| format!("{}={}", encode(key), encode(&value.to_string())));
+            let body = std::iter::once(name_field).chain(fields).collect::<Vec<_>>().join("&");
+            (path, "application/x-www-form-urlencoded", body)
+        }
+        Value::Null => (path, "application/x-www-form-urlencoded", name_field),
+        content => {
+            let separator = if path.contains('?') { '&' } else { '?' };
+            (format!("{}{}{}", path, separator, name_field), "text/plain; charset=utf-8", content.to_string())
+        }
+    };
+
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        content_type,
+        body.len(),
+        body
+    )
+    .map_err(|e| e.to_string())?;
+    let mut status_line = String::new();
+    BufReader::new(&stream).read_line(&mut status_line).map_err(|e| e.to_string())?;
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        Some(_) => Err(format!("the server answered '{}'", status_line.trim_end())),
+        None => Err("the server sent no response".to_string()),
+    }
+}
+
+// Percent-encodes a form field name or value.
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Decodes `application/x-www-form-urlencoded` text into string fields.
+fn decode_form(text: &str) -> BTreeMap<String, Value> {
+    text.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), Value::String(decode(value)))
+        })
+        .collect()
+}
+
+// Reverses `encode`, also accepting `+` for a space.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_scxml;
+
+    fn start(xml: &str) -> Interpreter {
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::new(&scxml).unwrap();
+        interpreter.start().unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn test_form_encoding() {
+        assert_eq!(encode("a b&c=d/é"), "a%20b%26c%3Dd%2F%C3%A9");
+        assert_eq!(decode("a+b%26c%3dd%2F%C3%A9"), "a b&c=d/é");
+        assert_eq!(decode("100%+%zz%4"), "100% %zz%4");
+        let fields = decode_form("_scxmleventname=go&x=1&empty=&flag");
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields["x"], Value::from("1"));
+        assert_eq!(fields["flag"], Value::from(""));
+    }
+
+    #[test]
+    fn test_post_to_listener() {
+        let listener = HttpListener::bind("127.0.0.1:0").unwrap();
+        let scxml = parse_scxml(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="received" expr="[]"/></datamodel>
+            <state id="listening">
+                <transition event="*"><assign location="received" expr="received.concat([[_event.name, _event.origintype, _event.data]])"/></transition>
+            </state>
+        </scxml>"#).unwrap();
+        let mut receiver = Interpreter::new(&scxml).unwrap();
+        listener.attach(&mut receiver);
+        receiver.start().unwrap();
+        let location = listener.location(receiver.sessionid());
+        assert_eq!(receiver.evaluate("_ioprocessors.basichttp.location").unwrap(), Value::String(location.clone()));
+
+        let sender = start(&format!(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="count" expr="3"/></datamodel>
+            <state id="s">
+                <onentry>
+                    <send event="order.placed" target="{location}" type="basichttp" namelist="count">
+                        <param name="item" expr="'flat white &amp; cake'"/>
+                    </send>
+                    <send event="note" target="{location}" type="{BASIC_HTTP_EVENT_PROCESSOR}"><content>extra hot</content></send>
+                    <send event="nowhere" target="{}/no-such-session" type="basichttp"/>
+                </onentry>
+                <transition event="error.communication" target="failed"/>
+            </state>
+            <state id="failed"/>
+        </scxml>"#, listener.location("").trim_end_matches('/')));
+        assert!(sender.is_in_state("failed"));
+
+        receiver.tick().unwrap();
+        let received = receiver.evaluate("received").unwrap();
+        let Value::Array(received) = received else { panic!("{:?}", received) };
+        let order = Value::Object(BTreeMap::from([
+            ("count".to_string(), Value::from("3")),
+            ("item".to_string(), Value::from("flat white & cake")),
+        ]));
+        let origintype = Value::from(BASIC_HTTP_EVENT_PROCESSOR);
+        assert_eq!(received, [
+            Value::Array(vec![Value::from("order.placed"), origintype.clone(), order]),
+            Value::Array(vec![Value::from("note"), origintype, Value::from("extra hot")]),
+        ]);
+    }
+
+    // Starts a chart whose onentry POSTs to a port nothing listens on.
+    fn start_unreachable_sender() -> Interpreter {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        start(&format!(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="cause" expr="null"/></datamodel>
+            <state id="s">
+                <onentry><send id="out" event="ping" target="http://{addr}/x" type="basichttp"/></onentry>
+                <transition event="error.communication" cond="_event.sendid == 'out'" target="failed">
+                    <assign location="cause" expr="_event.data"/>
+                </transition>
+            </state>
+            <state id="failed"/>
+        </scxml>"#))
+    }
+
+    #[test]
+    fn test_unreachable_server() {
+        let mut sender = start_unreachable_sender();
+        assert!(sender.is_in_state("failed"));
+        let Value::String(cause) = sender.evaluate("cause").unwrap() else { panic!() };
+        assert!(cause.starts_with("cannot deliver 'ping' over HTTP: "), "{}", cause);
+    }
+
+    // Only a `Session` moves POSTs off the runtime; a plain interpreter behaves as without tokio.
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_plain_interpreter_on_tokio_posts_synchronously() {
+        let sender = start_unreachable_sender();
+        assert!(sender.is_in_state("failed"));
+    }
+}
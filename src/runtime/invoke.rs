@@ -0,0 +1,560 @@
+//! Invocations started by `<invoke>`.
+//!
+//! Entering a state queues its `<invoke>` elements; they are started once the
+//! macrostep that entered the state has completed, as the interpretation
+//! algorithm requires. An `<invoke type="scxml">` starts a child [`Interpreter`]
+//! owned by its parent, sharing the parent's clock, document loader and invoke
+//! handlers. Any other `type` is started by the [`InvokeHandler`] registered for
+//! it with [`Interpreter::register_invoke_handler`]; with none registered,
+//! `error.execution` is raised.
+//!
+//! Events an invocation sends back are collected into the parent's external
+//! queue, tagged with the invoke ID, whenever the parent runs. The invocation is
+//! cancelled when the invoking state is exited.
+//!
+//! ```rust
+//! use harel::parse_scxml;
+//! use harel::runtime::{Event, InvokeRequest, InvokedSession, Interpreter};
+//!
+//! // Answers every `ask` with an `answer` carrying the `greeting` param.
+//! #[derive(Debug)]
+//! struct Echo {
+//!     greeting: harel::datamodel::Value,
+//!     outbox: Vec<Event>,
+//! }
+//!
+//! impl InvokedSession for Echo {
+//!     fn send(&mut self, event: Event) -> Result<(), String> {
+//!         if event.name == "ask" {
+//!             self.outbox.push(Event::new("answer").with_data(self.greeting.clone()));
+//!         }
+//!         Ok(())
+//!     }
+//!     fn poll(&mut self) -> Vec<Event> {
+//!         std::mem::take(&mut self.outbox)
+//!     }
+//!     fn done_data(&self) -> Option<harel::datamodel::Value> {
+//!         None
+//!     }
+//!     fn cancel(&mut self) {}
+//! }
+//!
+//! let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+//!     <state id="asking">
+//!         <invoke type="urn:example:echo" autoforward="true"><param name="greeting" expr="'hi'"/></invoke>
+//!         <transition event="answer" cond="_event.data == 'hi'" target="answered"/>
+//!     </state>
+//!     <state id="answered"/>
+//! </scxml>"#;
+//!
+//! let scxml = parse_scxml(xml).unwrap();
+//! let mut interpreter = Interpreter::new(&scxml).unwrap();
+//! interpreter.register_invoke_handler("urn:example:echo", |request: InvokeRequest| {
+//!     let greeting = request.params.get("greeting").cloned().unwrap_or_default();
+//!     Ok(Box::new(Echo { greeting, outbox: Vec::new() }) as Box<dyn InvokedSession>)
+//! });
+//! interpreter.start().unwrap();
+//! interpreter.send(Event::new("ask")).unwrap();
+//! assert!(interpreter.is_in_state("answered"));
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::chart::StateId;
+use crate::datamodel::{Context, DatamodelError, Value};
+use crate::{Executable, Invoke, ParseOptions, parse_scxml_with_options};
+
+use super::{Event, Interpreter, SCXML_INVOKE_TYPE};
+
+/// Starts the invocations of one `<invoke>` type.
+///
+/// Handlers are registered by type URI with [`Interpreter::register_invoke_handler`]
+/// and shared with the child sessions the interpreter invokes. Any
+/// `Fn(InvokeRequest) -> Result<Box<dyn InvokedSession>, String>` closure is a handler.
+pub trait InvokeHandler: Send + Sync {
+    /// Starts an invocation.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The evaluated `<invoke>` element.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Box<dyn InvokedSession>)` - The running invocation.
+    /// * `Err(String)` - Why it could not be started, raised as `error.execution`.
+    fn start(&self, request: InvokeRequest) -> Result<Box<dyn InvokedSession>, String>;
+}
+
+impl<F> InvokeHandler for F
+where
+    F: Fn(InvokeRequest) -> Result<Box<dyn InvokedSession>, String> + Send + Sync,
+{
+    fn start(&self, request: InvokeRequest) -> Result<Box<dyn InvokedSession>, String> {
+        self(request)
+    }
+}
+
+/// An `<invoke>` element with its expressions evaluated, passed to an [`InvokeHandler`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvokeRequest {
+    /// The invoke ID, given by `id` or generated.
+    pub id: String,
+    /// The `type` attribute.
+    pub type_: String,
+    /// The `src` attribute, if any.
+    pub src: Option<String>,
+    /// The value of the `<content>` child: its `expr`, or its markup as a string.
+    pub content: Option<Value>,
+    /// The values of the `<param>` children, by name.
+    pub params: BTreeMap<String, Value>,
+}
+
+/// A running invocation, owned by the interpreter that started it.
+pub trait InvokedSession: std::fmt::Debug + Send {
+    /// Delivers an event sent to `#_<invokeid>`, or forwarded because of `autoforward="true"`.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(String)` - Why the event cannot be delivered, raised as `error.communication`
+    ///   when it was sent by `<send>`.
+    fn send(&mut self, event: Event) -> Result<(), String>;
+
+    /// Returns the events sent back to the invoking session since the last call.
+    ///
+    /// The interpreter polls whenever it runs; events produced on another thread
+    /// are picked up by the next [`Interpreter::send`] or [`Interpreter::tick`].
+    fn poll(&mut self) -> Vec<Event>;
+
+    /// Returns the payload of `done.invoke.<id>` once the invocation has finished, and `None` until then.
+    fn done_data(&self) -> Option<Value>;
+
+    /// Stops the invocation because the invoking state was exited.
+    fn cancel(&mut self);
+
+    /// Returns how long until the invocation next needs polling, if it has timed work pending.
+    fn next_timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// An invoked SCXML session.
+#[derive(Debug)]
+struct ScxmlSession {
+    interpreter: Box<Interpreter>,
+}
+
+impl InvokedSession for ScxmlSession {
+    fn send(&mut self, event: Event) -> Result<(), String> {
+        self.interpreter.send(event).map_err(|error| error.to_string())
+    }
+
+    fn poll(&mut self) -> Vec<Event> {
+        if self.interpreter.is_running() {
+            let _ = self.interpreter.tick();
+        }
+        self.interpreter.parent_outbox.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn done_data(&self) -> Option<Value> {
+        match self.interpreter.is_running() {
+            true => None,
+            false => Some(self.interpreter.done_data().cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    // Runs the onexit handlers of the child's active states; what they send is dropped.
+    fn cancel(&mut self) {
+        self.interpreter.halt();
+    }
+
+    fn next_timeout(&self) -> Option<Duration> {
+        self.interpreter.next_timeout()
+    }
+}
+
+// A running `<invoke>` and the session it started.
+#[derive(Debug)]
+pub(super) struct Invocation {
+    id: String,
+    type_: String,
+    // The state whose <invoke> started the session; exiting it cancels the session.
+    state: StateId,
+    autoforward: bool,
+    finalize: Vec<Executable>,
+    pub(super) session: Box<dyn InvokedSession>,
+    // Set once `done.invoke.<id>` has been queued.
+    done: bool,
+}
+
+impl Interpreter {
+    /// Registers the handler that starts `<invoke>` elements of the given type,
+    /// replacing any handler registered for it before.
+    ///
+    /// The type is matched exactly against the `type` attribute. A handler registered
+    /// for `scxml` or [`SCXML_INVOKE_TYPE`] replaces the built-in SCXML sessions for that value.
+    ///
+    /// # Arguments
+    ///
+    /// * `type_` - The type URI, e.g. `"http://www.w3.org/TR/voicexml30/"` or `"vxml3"`.
+    /// * `handler` - Starts the invocations.
+    pub fn register_invoke_handler(&mut self, type_: impl Into<String>, handler: impl InvokeHandler + 'static) {
+        self.invoke_handlers.insert(type_.into(), Arc::new(handler));
+    }
+
+    // Starts the invocations of the states entered by the last macrostep, in document order.
+    pub(super) fn start_invocations(&mut self) {
+        let states = std::mem::take(&mut self.states_to_invoke);
+        for state in states {
+            let invokes = self.chart.state(state).invokes.clone();
+            for invoke in &invokes {
+                if let Err(error) = self.invoke(state, invoke) {
+                    self.raise_error("error.execution", error);
+                }
+            }
+        }
+    }
+
+    // Starts the session for one <invoke> element.
+    fn invoke(&mut self, state: StateId, invoke: &Invoke) -> Result<(), DatamodelError> {
+        let id = match &invoke.id {
+            Some(id) => id.clone(),
+            None => {
+                self.next_invokeid += 1;
+                let id = format!("{}.invoke{}", self.chart.state(state).id, self.next_invokeid);
+                if let Some(location) = &invoke.idlocation {
+                    let ctx = Context::new(&self.chart, &self.configuration);
+                    self.datamodel.assign(location, Value::String(id.clone()), &ctx)?;
+                }
+                id
+            }
+        };
+        let content = match &invoke.content {
+            Some(content) => match (&content.expr, &content.content) {
+                (Some(expr), _) => Some(self.evaluate(expr)?),
+                (None, body) => body.as_ref().map(|body| Value::from(body.as_str())),
+            },
+            None => None,
+        };
+        let type_ = match &invoke.typeexpr {
+            Some(expr) if invoke.type_.is_empty() => self.evaluate(expr)?.to_string(),
+            _ => invoke.type_.clone(),
+        };
+        let src = match (&invoke.src, &invoke.srcexpr) {
+            (Some(src), _) => Some(src.clone()),
+            (None, Some(expr)) => Some(self.evaluate(expr)?.to_string()),
+            (None, None) => None,
+        };
+        let params = match self.event_data(&invoke.params, invoke.namelist.as_deref(), None)? {
+            Value::Object(fields) => fields,
+            _ => BTreeMap::new(),
+        };
+        let request = InvokeRequest { id, type_, src, content, params };
+        let session = match self.invoke_handlers.get(&request.type_).cloned() {
+            Some(handler) => handler
+                .start(request.clone())
+                .map_err(|cause| DatamodelError::Evaluation(format!("cannot start <invoke id=\"{}\">: {}", request.id, cause)))?,
+            None if matches!(request.type_.as_str(), "" | "scxml" | SCXML_INVOKE_TYPE | "http://www.w3.org/TR/scxml") => {
+                self.start_scxml_session(&request)?
+            }
+            None => {
+                return Err(DatamodelError::Unsupported(format!("<invoke> type '{}': no invoke handler is registered for it", request.type_)));
+            }
+        };
+        self.invocations.push(Invocation {
+            id: request.id,
+            type_: request.type_,
+            state,
+            autoforward: invoke.autoforward,
+            finalize: invoke.finalize.as_ref().map(|f| f.executables.clone()).unwrap_or_default(),
+            session,
+            done: false,
+        });
+        Ok(())
+    }
+
+    // Starts a child interpreter for the document in `<content>` or at `src`.
+    fn start_scxml_session(&mut self, request: &InvokeRequest) -> Result<Box<dyn InvokedSession>, DatamodelError> {
+        let source = match (&request.content, &request.src) {
+            (Some(content), _) => content.to_string(),
+            (None, Some(src)) => {
+                (self.loader)(src).map_err(|e| DatamodelError::Evaluation(format!("cannot read <invoke> src '{}': {}", src, e)))?
+            }
+            (None, None) => return Err(DatamodelError::Evaluation("<invoke> needs a src or <content>".to_string())),
+        };
+        let options = ParseOptions { relaxed_namespace: true, ..ParseOptions::default() };
+        let scxml = parse_scxml_with_options(&source, options)
+            .map_err(|e| DatamodelError::Evaluation(format!("invalid document for <invoke id=\"{}\">: {}", request.id, e)))?;
+        let mut child = Interpreter::new(&scxml)
+            .map_err(|e| DatamodelError::Evaluation(format!("cannot start <invoke id=\"{}\">: {}", request.id, e)))?;
+        child.clock = self.clock.clone();
+        child.loader = self.loader.clone();
+        child.invoke_handlers = self.invoke_handlers.clone();
+        child.parent_outbox = Some(Vec::new());
+        child.params = request.params.clone();
+        #[cfg(feature = "tokio")]
+        {
+            child.mailbox = Arc::new(super::ioprocessor::Mailbox::sharing_waker(&self.mailbox));
+        }
+        #[cfg(all(feature = "http-ioprocessor", feature = "tokio"))]
+        {
+            child.poster = self.poster.as_ref().map(super::http::Poster::for_child);
+        }
+        child
+            .start()
+            .map_err(|e| DatamodelError::Evaluation(format!("cannot start <invoke id=\"{}\">: {}", request.id, e)))?;
+        Ok(Box::new(ScxmlSession { interpreter: Box::new(child) }))
+    }
+
+    // Moves the events the invocations sent back onto the external queue, followed by
+    // `done.invoke.<id>` for those that have finished.
+    pub(super) fn poll_invocations(&mut self) {
+        for invocation in &mut self.invocations {
+            for event in invocation.session.poll() {
+                self.external_queue.push_back(Event {
+                    origin: event.origin.or_else(|| Some(format!("#_{}", invocation.id))),
+                    origintype: event.origintype.or_else(|| Some(invocation.type_.clone())),
+                    invokeid: Some(invocation.id.clone()),
+                    ..event
+                });
+            }
+            if invocation.done {
+                continue;
+            }
+            if let Some(data) = invocation.session.done_data() {
+                invocation.done = true;
+                let event = Event::platform(format!("done.invoke.{}", invocation.id)).with_data(data);
+                self.external_queue.push_back(Event { invokeid: Some(invocation.id.clone()), ..event });
+            }
+        }
+    }
+
+    // Runs the <finalize> of the invocation an external event came from, and forwards
+    // the event to every unfinished invocation whose <invoke> has `autoforward="true"`.
+    pub(super) fn process_invocation_event(&mut self, event: &Event) {
+        let mut finalize = Vec::new();
+        for invocation in &mut self.invocations {
+            if event.invokeid.as_deref() == Some(invocation.id.as_str()) {
+                finalize = invocation.finalize.clone();
+            }
+            if invocation.autoforward && !invocation.done {
+                let _ = invocation.session.send(event.clone());
+            }
+        }
+        self.execute_content(&finalize);
+    }
+
+    // Delivers an event to the invocation with the given invoke ID.
+    //
+    // # Returns
+    //
+    // * `Err(String)` - Why the target cannot be reached, if there is no such invocation or it has finished.
+    pub(super) fn send_to_invocation(&mut self, id: &str, event: Event) -> Result<(), String> {
+        let invocation = self
+            .invocations
+            .iter_mut()
+            .find(|invocation| invocation.id == id)
+            .ok_or_else(|| format!("unknown target '#_{}'", id))?;
+        if invocation.done {
+            return Err(format!("invoked session '{}', which has finished", id));
+        }
+        invocation.session.send(event).map_err(|cause| format!("invoked session '{}': {}", id, cause))
+    }
+
+    // Cancels the unfinished invocations started by a state being exited. Events they
+    // sent that have not been processed yet are dropped.
+    pub(super) fn cancel_invocations(&mut self, state: StateId) {
+        let (cancelled, kept) = std::mem::take(&mut self.invocations).into_iter().partition(|invocation| invocation.state == state);
+        self.invocations = kept;
+        for mut invocation in cancelled {
+            if !invocation.done {
+                invocation.session.cancel();
+            }
+            self.external_queue.retain(|event| event.invokeid.as_deref() != Some(invocation.id.as_str()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_scxml;
+    use std::sync::Mutex;
+
+    fn start(xml: &str) -> Interpreter {
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::new(&scxml).unwrap();
+        interpreter.start().unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn test_invoke_params_finalize_and_done() {
+        let mut interpreter = start(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="result" expr="0"/><data id="payload" expr="null"/></datamodel>
+            <state id="waiting">
+                <invoke id="worker" type="scxml">
+                    <param name="count" expr="21"/>
+                    <content>
+                        <scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+                            <datamodel><data id="count" expr="1"/></datamodel>
+                            <final id="finished">
+                                <onentry>
+                                    <send event="reply" target="#_parent"><param name="value" expr="count * 2"/></send>
+                                </onentry>
+                                <donedata><content expr="'bye'"/></donedata>
+                            </final>
+                        </scxml>
+                    </content>
+                    <finalize>
+                        <if cond="_event.name == 'reply'"><assign location="result" expr="_event.data.value"/></if>
+                    </finalize>
+                </invoke>
+                <transition event="done.invoke.worker" target="done">
+                    <assign location="payload" expr="_event.data"/>
+                </transition>
+            </state>
+            <final id="done"/>
+        </scxml>"##);
+        assert!(!interpreter.is_running());
+        assert_eq!(interpreter.evaluate("result").unwrap(), Value::Number(42.0));
+        assert_eq!(interpreter.evaluate("payload").unwrap(), Value::from("bye"));
+    }
+
+    #[test]
+    fn test_autoforward_and_cancel_on_exit() {
+        let mut interpreter = start(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="childid" expr="null"/></datamodel>
+            <state id="talking">
+                <invoke idlocation="childid" autoforward="true">
+                    <content>
+                        <scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+                            <state id="idle">
+                                <transition event="ping" target="idle"><send event="pong" target="#_parent"/></transition>
+                            </state>
+                        </scxml>
+                    </content>
+                </invoke>
+                <transition event="pong" target="silent"/>
+            </state>
+            <state id="silent">
+                <onentry><send event="ping" targetexpr="'#_' + childid"/></onentry>
+                <transition event="error.communication" target="unreachable"/>
+            </state>
+            <state id="unreachable"/>
+        </scxml>"##);
+        assert_eq!(interpreter.evaluate("childid").unwrap(), Value::from("talking.invoke1"));
+        assert_eq!(interpreter.invocations.len(), 1);
+        interpreter.send(Event::new("ping")).unwrap();
+        assert!(interpreter.invocations.is_empty());
+        assert_eq!(interpreter.configuration(), vec!["unreachable"]);
+    }
+
+    #[test]
+    fn test_send_to_child_and_unsupported_type() {
+        let mut interpreter = start(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <parallel id="p">
+                <state id="echo">
+                    <invoke id="echo">
+                        <content>
+                            <scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+                                <state id="idle">
+                                    <transition event="hello" target="idle"><send event="echoed" target="#_parent"/></transition>
+                                </state>
+                            </scxml>
+                        </content>
+                    </invoke>
+                    <transition event="go"><send event="hello" target="#_echo"/></transition>
+                    <transition event="echoed" cond="_event.origin == '#_echo'" target="replied" type="internal"/>
+                    <state id="asking"/>
+                    <state id="replied"/>
+                </state>
+                <state id="other">
+                    <invoke type="vxml3" src="dialog.vxml"/>
+                    <transition event="error.execution" target="failed" type="internal"/>
+                    <state id="running"/>
+                    <state id="failed"/>
+                </state>
+            </parallel>
+        </scxml>"##);
+        assert!(interpreter.is_in_state("failed"));
+        interpreter.send(Event::new("go")).unwrap();
+        assert!(interpreter.is_in_state("replied"));
+    }
+
+    // Records what the interpreter asks of it, acknowledges `ping` and finishes on `stop`.
+    #[derive(Debug)]
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+        outbox: Vec<Event>,
+        finished: bool,
+    }
+
+    impl InvokedSession for Recorder {
+        fn send(&mut self, event: Event) -> Result<(), String> {
+            self.log.lock().unwrap().push(format!("send {}", event.name));
+            match event.name.as_str() {
+                "ping" => self.outbox.push(Event::new("ack").with_data(event.name)),
+                "stop" => self.finished = true,
+                _ => {}
+            }
+            Ok(())
+        }
+        fn poll(&mut self) -> Vec<Event> {
+            std::mem::take(&mut self.outbox)
+        }
+        fn done_data(&self) -> Option<Value> {
+            self.finished.then(|| Value::from("stopped"))
+        }
+        fn cancel(&mut self) {
+            self.log.lock().unwrap().push("cancel".to_string());
+        }
+    }
+
+    #[test]
+    fn test_registered_handler() {
+        let scxml = parse_scxml(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="acks" expr="''"/></datamodel>
+            <state id="using">
+                <state id="first">
+                    <invoke id="rec" type="urn:test:recorder" src="rec://one"><param name="n" expr="1 + 1"/></invoke>
+                    <invoke type="urn:test:recorder" autoforward="true"/>
+                    <transition event="next" target="second"/>
+                </state>
+                <state id="second">
+                    <invoke id="rec2" typeexpr="'urn:test:' + 'recorder'" srcexpr="'rec://' + acks" namelist="acks"/>
+                    <transition event="go"><send event="stop" target="#_rec2"/></transition>
+                    <transition event="done.invoke.rec2" target="done"><assign location="acks" expr="acks + _event.data"/></transition>
+                </state>
+                <transition event="ack"><assign location="acks" expr="acks + _event.data + ','"/></transition>
+            </state>
+            <final id="done"/>
+        </scxml>"##).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = Interpreter::new(&scxml).unwrap();
+        let handler_log = log.clone();
+        interpreter.register_invoke_handler("urn:test:recorder", move |request: InvokeRequest| {
+            let n = request.params.get("n").or(request.params.get("acks"));
+            handler_log.lock().unwrap().push(format!("start {} {:?} {:?}", request.id, request.src, n));
+            Ok(Box::new(Recorder { log: handler_log.clone(), outbox: Vec::new(), finished: false }) as Box<dyn InvokedSession>)
+        });
+        interpreter.start().unwrap();
+        interpreter.send(Event::new("ping")).unwrap();
+        interpreter.send(Event::new("next")).unwrap();
+        interpreter.send(Event::new("go")).unwrap();
+        assert!(!interpreter.is_running());
+        assert_eq!(interpreter.evaluate("acks").unwrap(), Value::from("ping,stopped"));
+        assert_eq!(*log.lock().unwrap(), [
+            "start rec Some(\"rec://one\") Some(Number(2.0))",
+            "start first.invoke1 None None",
+            "send ping",
+            "send ack",
+            "send next",
+            "cancel",
+            "cancel",
+            "start rec2 Some(\"rec://ping,\") Some(String(\"ping,\"))",
+            "send stop",
+        ]);
+    }
+}
@@ -0,0 +1,198 @@
+//! The SCXML Event I/O Processor, which routes `<send>` events between sessions.
+//!
+//! Every running session registers a mailbox under its session ID in a
+//! process-wide directory, and advertises `#_scxml_<sessionid>` as its location
+//! in `_ioprocessors`. A `<send>` is resolved by its `target`:
+//!
+//! - none: the sending session's own external queue;
+//! - `#_parent`: the session that invoked this one;
+//! - `#_<invokeid>`: a session (or other invocation) this one invoked;
+//! - `#_scxml_<sessionid>`: any running session in the process, whose mailbox is
+//!   moved onto its external queue the next time it runs.
+//!
+//! Delivered events have `origintype` set to [`SCXML_EVENT_PROCESSOR`] and
+//! `origin` set to the sender's location, so the receiver can reply with
+//! `<send targetexpr="_event.origin">`. Events from an invoked session instead
+//! carry the invoke ID, with `#_<invokeid>` as their origin. A target that cannot
+//! be resolved, or a session that has ended, raises `error.communication`.
+//!
+//! With the `tokio` feature, delivering to a mailbox also wakes the task of a
+//! `Session`, which shares its wakeup with the sessions it invoked.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, Weak};
+
+use super::{Event, Interpreter, SCXML_EVENT_PROCESSOR};
+
+// Events sent to a session by session ID, waiting for it to run.
+#[derive(Debug, Default)]
+pub(super) struct Mailbox {
+    events: Mutex<VecDeque<Event>>,
+    // Notified on every delivery, for the task running the session.
+    #[cfg(feature = "tokio")]
+    pub(super) waker: Arc<tokio::sync::Notify>,
+}
+
+impl Mailbox {
+    // Creates an empty mailbox whose deliveries wake the same task as `other`'s.
+    #[cfg(feature = "tokio")]
+    pub(super) fn sharing_waker(other: &Mailbox) -> Self {
+        Self { events: Mutex::default(), waker: other.waker.clone() }
+    }
+
+    // Adds an event for the session to collect the next time it runs.
+    pub(super) fn push(&self, event: Event) {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).push_back(event);
+        #[cfg(feature = "tokio")]
+        self.waker.notify_one();
+    }
+}
+
+// The mailboxes of the running sessions in this process, by session ID.
+static SESSIONS: Mutex<BTreeMap<String, Weak<Mailbox>>> = Mutex::new(BTreeMap::new());
+
+// Returns the location of a session, as advertised in `_ioprocessors`.
+pub(super) fn location(sessionid: &str) -> String {
+    format!("#_scxml_{}", sessionid)
+}
+
+impl Interpreter {
+    // Makes the session reachable as `#_scxml_<sessionid>`.
+    pub(super) fn register_session(&self) {
+        let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.insert(self.system.sessionid.clone(), Arc::downgrade(&self.mailbox));
+    }
+
+    // Removes the session from the directory once it has ended.
+    pub(super) fn unregister_session(&self) {
+        let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+        if sessions.get(&self.system.sessionid).is_some_and(|mailbox| mailbox.as_ptr() == Arc::as_ptr(&self.mailbox)) {
+            sessions.remove(&self.system.sessionid);
+        }
+    }
+
+    // Moves the events other sessions sent to this one onto the external queue.
+    pub(super) fn collect_mail(&mut self) {
+        let mut events = self.mailbox.events.lock().unwrap_or_else(|e| e.into_inner());
+        self.external_queue.extend(events.drain(..));
+    }
+
+    // Delivers an event sent by <send> through the named Event I/O Processor, raising
+    // `error.communication` with the ID of the <send> if it cannot be delivered.
+    pub(super) fn dispatch(&mut self, processor: &str, target: Option<&str>, sendid: &str, event: Event) {
+        #[cfg(feature = "http-ioprocessor")]
+        if processor == super::http::BASIC_HTTP_EVENT_PROCESSOR {
+            return self.post(target, sendid, event);
+        }
+        debug_assert_eq!(processor, SCXML_EVENT_PROCESSOR);
+        let origintype = Some(SCXML_EVENT_PROCESSOR.to_string());
+        let result = match target {
+            None => {
+                let origin = Some(location(&self.system.sessionid));
+                self.external_queue.push_back(Event { origin, origintype, ..event });
+                Ok(())
+            }
+            // The invoking session tags the event with the invoke ID and `#_<invokeid>` as origin.
+            Some("#_parent") => match &mut self.parent_outbox {
+                Some(outbox) => {
+                    outbox.push(Event { origintype, ..event });
+                    Ok(())
+                }
+                None => Err(format!("cannot deliver '{}' to #_parent: the session was not invoked", event.name)),
+            },
+            Some(target) => {
+                let name = event.name.clone();
+                let event = Event { origin: Some(location(&self.system.sessionid)), origintype, ..event };
+                match (target.strip_prefix("#_scxml_"), target.strip_prefix("#_")) {
+                    (Some(sessionid), _) => deliver(sessionid, event),
+                    (None, Some(id)) => self.send_to_invocation(id, event),
+                    (None, None) => Err(format!("unknown target '{}'", target)),
+                }
+                .map_err(|cause| format!("cannot deliver '{}' to {}", name, cause))
+            }
+        };
+        if let Err(cause) = result {
+            self.raise_communication_error(sendid, cause);
+        }
+    }
+}
+
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        self.unregister_session();
+    }
+}
+
+// Puts an event in the mailbox of the session with the given ID.
+pub(super) fn deliver(sessionid: &str, event: Event) -> Result<(), String> {
+    let mailbox = SESSIONS.lock().unwrap_or_else(|e| e.into_inner()).get(sessionid).and_then(Weak::upgrade);
+    let mailbox = mailbox.ok_or_else(|| format!("unknown target '{}': no such session is running", location(sessionid)))?;
+    mailbox.push(event);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datamodel::Value;
+    use crate::parse_scxml;
+
+    fn start(xml: &str) -> Interpreter {
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::new(&scxml).unwrap();
+        interpreter.start().unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn test_send_between_sessions() {
+        let mut server = start(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <state id="serving">
+                <transition event="request">
+                    <send event="response" targetexpr="_event.origin"><content expr="_event.data * 2"/></send>
+                </transition>
+            </state>
+        </scxml>"##);
+        let mut client = start(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="server" expr="null"/><data id="answer" expr="null"/><data id="self" expr="null"/></datamodel>
+            <state id="idle">
+                <transition event="ask" target="waiting">
+                    <assign location="server" expr="_event.data"/>
+                    <assign location="self" expr="_ioprocessors.scxml.location"/>
+                    <send event="request" targetexpr="'#_scxml_' + server"><content expr="21"/></send>
+                </transition>
+            </state>
+            <state id="waiting">
+                <transition event="response" cond="_event.origin == '#_scxml_' + server" target="done">
+                    <assign location="answer" expr="_event.data"/>
+                </transition>
+            </state>
+            <final id="done"/>
+        </scxml>"##);
+        client.send(Event::new("ask").with_data(server.sessionid())).unwrap();
+        assert_eq!(client.evaluate("self").unwrap(), Value::String(location(client.sessionid())));
+        assert!(client.is_in_state("waiting"));
+
+        // Each session picks up its mail the next time it runs.
+        server.tick().unwrap();
+        client.tick().unwrap();
+        assert!(!client.is_running());
+        assert_eq!(client.evaluate("answer").unwrap(), Value::Number(42.0));
+    }
+
+    #[test]
+    fn test_unknown_and_ended_sessions() {
+        let ended = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0"><final id="end"/></scxml>"#);
+        let mut sender = start(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="errors" expr="[]"/></datamodel>
+            <state id="s">
+                <transition event="go"><send id="out" event="hi" targetexpr="'#_scxml_' + _event.data"/></transition>
+                <transition event="error.communication"><assign location="errors" expr="errors.concat([_event.sendid])"/></transition>
+            </state>
+        </scxml>"##);
+        sender.send(Event::new("go").with_data(ended.sessionid())).unwrap();
+        sender.send(Event::new("go").with_data("no-such-session")).unwrap();
+        assert_eq!(sender.evaluate("errors.length").unwrap(), Value::Number(2.0));
+        assert_eq!(sender.evaluate("errors[1]").unwrap(), Value::from("out"));
+    }
+}
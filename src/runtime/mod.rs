@@ -0,0 +1,1459 @@
+//! Execution of parsed SCXML state machines.
+//!
+//! The [`Interpreter`] follows the W3C "Algorithm for SCXML Interpretation"
+//! (Appendix D of the SCXML 1.0 recommendation). It is driven synchronously:
+//! [`Interpreter::start`] enters the initial configuration and
+//! [`Interpreter::send`] processes one external event, each running macrosteps
+//! until the machine is stable again. Events sent with a `delay` wait in a
+//! [`TimerQueue`] until the interpreter's [`Clock`] reaches their due time;
+//! [`Interpreter::tick`] delivers the ones that have come due. Entering a state
+//! with an `<invoke type="scxml">` starts a child session that is driven along
+//! with its parent; other `<invoke>` types are started by an [`InvokeHandler`].
+//! A `<send>` reaches other sessions in the process through the SCXML Event I/O
+//! Processor, with the targets `#_parent`, `#_<invokeid>` and `#_scxml_<sessionid>`.
+//!
+//! ```rust
+//! use harel::parse_scxml;
+//! use harel::runtime::{Event, Interpreter};
+//!
+//! let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" initial="idle">
+//!     <state id="idle">
+//!         <transition event="go" target="busy"/>
+//!     </state>
+//!     <state id="busy"/>
+//! </scxml>"#;
+//!
+//! let scxml = parse_scxml(xml).unwrap();
+//! let mut interpreter = Interpreter::new(&scxml).unwrap();
+//! interpreter.start().unwrap();
+//! assert_eq!(interpreter.configuration(), vec!["idle"]);
+//!
+//! interpreter.send(Event::new("go")).unwrap();
+//! assert_eq!(interpreter.configuration(), vec!["busy"]);
+//! ```
+//!
+//! ## Errors
+//!
+//! Failures while running the chart never surface as a Rust error or a panic.
+//! As the specification requires, they are placed on the internal queue as
+//! platform events, with a description of the cause in `_event.data`, so the
+//! chart handles them with ordinary `<transition event="error.*">` elements:
+//!
+//! - `error.execution` when a `cond`, `expr` or `location` cannot be evaluated,
+//!   `<assign>` targets an invalid location, a `<data>` element cannot be
+//!   initialized, a `<send>` names an unsupported `type`, or an `<invoke>` cannot
+//!   be started, including when no [`InvokeHandler`] is registered for its `type`.
+//!   A failing guard or `<if>` condition counts as false; any other failing element stops the rest of
+//!   its block;
+//! - `error.communication` when a `<send>` target cannot be reached, such as an
+//!   unknown `#_<invokeid>` or a `#_scxml_<sessionid>` that is not running. The
+//!   event's `sendid` is the ID of the failed `<send>`, and the block carries on.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::chart::{StateChart, StateId, StateKind, TransitionId};
+use crate::datamodel::{self, Context, Datamodel, DatamodelError, SystemVariables, Value};
+#[cfg(doc)]
+use crate::datamodel::NullDatamodel;
+use crate::{Content, Data, Executable, Param, Scxml, SendElement, ValidationError, validate};
+
+#[cfg(feature = "http-ioprocessor")]
+pub mod http;
+pub mod invoke;
+mod ioprocessor;
+#[cfg(feature = "tokio")]
+pub mod session;
+pub mod timer;
+
+pub use invoke::{InvokeHandler, InvokeRequest, InvokedSession};
+pub use timer::{Clock, ManualClock, SystemClock, TimerQueue};
+
+/// Type URI of the SCXML Event I/O Processor, the default for `<send>`.
+pub const SCXML_EVENT_PROCESSOR: &str = "http://www.w3.org/TR/scxml/#SCXMLEventProcessor";
+
+/// Type URI of an invoked SCXML session, accepted by `<invoke>` along with `scxml`.
+pub const SCXML_INVOKE_TYPE: &str = "http://www.w3.org/TR/scxml/";
+
+/// Errors returned when driving an [`Interpreter`].
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error("Invalid document: {0}")]
+    Invalid(#[from] ValidationError),
+    #[error("Interpreter has already been started")]
+    AlreadyStarted,
+    #[error("Interpreter is not running")]
+    NotRunning,
+    #[error("Unsupported datamodel: {0}")]
+    UnsupportedDatamodel(String),
+}
+
+/// The origin of an [`Event`], as exposed through `_event.type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    /// Raised by the platform itself (e.g. `done.state.*`, `error.*`).
+    Platform,
+    /// Raised by `<raise>` or `<send target="#_internal">`.
+    Internal,
+    /// Delivered through the external queue.
+    External,
+}
+
+/// An event processed by the interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Dotted event name, e.g. `"done.state.main"`.
+    pub name: String,
+    /// Whether the event is platform, internal or external.
+    pub type_: EventType,
+    /// The `id` of the `<send>` that produced the event, if any.
+    pub sendid: Option<String>,
+    /// URI of the sender, if any.
+    pub origin: Option<String>,
+    /// Type of the Event I/O Processor of the sender, if any.
+    pub origintype: Option<String>,
+    /// The invoke ID when the event comes from an invoked child.
+    pub invokeid: Option<String>,
+    /// The payload, exposed as `_event.data`.
+    pub data: Value,
+}
+
+impl Event {
+    /// Creates an external event with the given name.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            type_: EventType::External,
+            sendid: None,
+            origin: None,
+            origintype: None,
+            invokeid: None,
+            data: Value::Null,
+        }
+    }
+
+    /// Attaches a payload to the event.
+    pub fn with_data(mut self, data: impl Into<Value>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    pub(crate) fn internal(name: impl Into<String>) -> Self {
+        Self { type_: EventType::Internal, ..Self::new(name) }
+    }
+
+    pub(crate) fn platform(name: impl Into<String>) -> Self {
+        Self { type_: EventType::Platform, ..Self::new(name) }
+    }
+}
+
+/// Receives the label and value of every `<log>` element executed.
+pub type Logger = Box<dyn FnMut(Option<&str>, &Value) + Send>;
+
+/// Reads the document named by the `src` attribute of an `<invoke>`.
+pub type Loader = Arc<dyn Fn(&str) -> std::io::Result<String> + Send + Sync>;
+
+/// Executes a [`Scxml`] document according to the W3C interpretation algorithm.
+pub struct Interpreter {
+    chart: StateChart,
+    datamodel: Box<dyn Datamodel>,
+    system: SystemVariables,
+    late_binding: bool,
+    // States whose <datamodel> has been bound, for late binding.
+    bound: BTreeSet<StateId>,
+    configuration: BTreeSet<StateId>,
+    // The states recorded by each history pseudo-state when its parent was last exited.
+    history_value: BTreeMap<StateId, Vec<StateId>>,
+    states_to_invoke: BTreeSet<StateId>,
+    internal_queue: VecDeque<Event>,
+    external_queue: VecDeque<Event>,
+    clock: Arc<dyn Clock>,
+    timers: TimerQueue<Delayed>,
+    // Counter for the send IDs generated for <send> elements without an `id`.
+    next_sendid: u64,
+    // Events sent to `#_scxml_<sessionid>` by other sessions.
+    mailbox: Arc<ioprocessor::Mailbox>,
+    // Hands HTTP POSTs to a blocking task when the session runs as a `Session`.
+    #[cfg(all(feature = "http-ioprocessor", feature = "tokio"))]
+    poster: Option<http::Poster>,
+    loader: Loader,
+    // Child sessions started by <invoke>, in the order they were started.
+    invocations: Vec<invoke::Invocation>,
+    // Handlers for non-SCXML <invoke> types, keyed by type URI and shared with child sessions.
+    invoke_handlers: BTreeMap<String, Arc<dyn InvokeHandler>>,
+    // Counter for the invoke IDs generated for <invoke> elements without an `id`.
+    next_invokeid: u64,
+    // Events sent to `#_parent`, collected by the invoking session; `None` for a top-level session.
+    parent_outbox: Option<Vec<Event>>,
+    // `<param>` values passed by the invoking session, overriding top-level `<data>`.
+    params: BTreeMap<String, Value>,
+    logger: Option<Logger>,
+    started: bool,
+    running: bool,
+    // The payload of the top-level <final> state that ended the session.
+    done_data: Option<Value>,
+}
+
+impl std::fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("sessionid", &self.system.sessionid)
+            .field("datamodel", &self.datamodel)
+            .field("configuration", &self.configuration())
+            .field("internal_queue", &self.internal_queue)
+            .field("external_queue", &self.external_queue)
+            .field("timers", &self.timers)
+            .field("invocations", &self.invocations)
+            .field("invoke_handlers", &self.invoke_handlers.keys().collect::<Vec<_>>())
+            .field("running", &self.running)
+            .finish_non_exhaustive()
+    }
+}
+
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
+impl Interpreter {
+    /// Validates and compiles a parsed document into an interpreter.
+    ///
+    /// Expressions are evaluated with the built-in datamodel named by the `datamodel`
+    /// attribute: the [`NullDatamodel`] when it is absent or `null`, and the
+    /// [`EcmaScriptDatamodel`](crate::datamodel::EcmaScriptDatamodel) for `ecmascript`,
+    /// and, with the `json-datamodel` feature, the JSON datamodel for `json`.
+    ///
+    /// # Arguments
+    ///
+    /// * `scxml` - The parsed SCXML document to execute.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Interpreter)` - An interpreter ready to be started.
+    /// * `Err(RuntimeError)` - If the document fails validation or names a datamodel that is not built in.
+    pub fn new(scxml: &Scxml) -> Result<Self, RuntimeError> {
+        let name = scxml.datamodel.as_deref();
+        let datamodel = datamodel::builtin(name).ok_or_else(|| RuntimeError::UnsupportedDatamodel(name.unwrap_or_default().to_string()))?;
+        Self::with_datamodel(scxml, datamodel)
+    }
+
+    /// Validates and compiles a parsed document into an interpreter that evaluates
+    /// expressions with the given datamodel.
+    ///
+    /// # Arguments
+    ///
+    /// * `scxml` - The parsed SCXML document to execute.
+    /// * `datamodel` - The datamodel used for every `cond`, `expr`, `location` and `<script>`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Interpreter)` - An interpreter ready to be started.
+    /// * `Err(RuntimeError)` - If the document fails validation.
+    pub fn with_datamodel(scxml: &Scxml, datamodel: Box<dyn Datamodel>) -> Result<Self, RuntimeError> {
+        validate(scxml)?;
+        let session = NEXT_SESSION.fetch_add(1, Ordering::Relaxed);
+        let sessionid = format!("session-{}-{}", std::process::id(), session);
+        let location = ioprocessor::location(&sessionid);
+        Ok(Self {
+            chart: StateChart::compile(scxml)?,
+            datamodel,
+            system: SystemVariables {
+                sessionid,
+                name: scxml.name.clone(),
+                ioprocessors: BTreeMap::from([
+                    (SCXML_EVENT_PROCESSOR.to_string(), location.clone()),
+                    ("scxml".to_string(), location),
+                ]),
+            },
+            late_binding: scxml.binding.as_deref() == Some("late"),
+            bound: BTreeSet::new(),
+            configuration: BTreeSet::new(),
+            history_value: BTreeMap::new(),
+            states_to_invoke: BTreeSet::new(),
+            internal_queue: VecDeque::new(),
+            external_queue: VecDeque::new(),
+            clock: Arc::new(SystemClock::new()),
+            timers: TimerQueue::new(),
+            next_sendid: 0,
+            mailbox: Arc::default(),
+            #[cfg(all(feature = "http-ioprocessor", feature = "tokio"))]
+            poster: None,
+            loader: Arc::new(|src: &str| std::fs::read_to_string(src.strip_prefix("file:").unwrap_or(src))),
+            invocations: Vec::new(),
+            invoke_handlers: BTreeMap::new(),
+            next_invokeid: 0,
+            parent_outbox: None,
+            params: BTreeMap::new(),
+            logger: None,
+            started: false,
+            running: false,
+            done_data: None,
+        })
+    }
+
+    /// Installs a callback receiving the output of `<log>` elements; without one, logs are discarded.
+    pub fn set_logger(&mut self, logger: impl FnMut(Option<&str>, &Value) + Send + 'static) {
+        self.logger = Some(Box::new(logger));
+    }
+
+    /// Replaces the clock delayed events are scheduled against; the default is a [`SystemClock`].
+    ///
+    /// Events already scheduled keep their due times, which are read against the new clock.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Replaces the function that reads `<invoke src="...">` documents. The default reads
+    /// the file system, accepting plain paths and `file:` URLs.
+    pub fn set_loader(&mut self, loader: impl Fn(&str) -> std::io::Result<String> + Send + Sync + 'static) {
+        self.loader = Arc::new(loader);
+    }
+
+    /// Enters the initial configuration and runs until the machine is stable.
+    pub fn start(&mut self) -> Result<(), RuntimeError> {
+        if self.started {
+            return Err(RuntimeError::AlreadyStarted);
+        }
+        self.started = true;
+        self.running = true;
+        self.register_session();
+        if let Err(error) = self.datamodel.init(&self.system) {
+            self.raise_error("error.execution", error);
+        }
+        let root = self.chart.root();
+        if self.late_binding {
+            self.bind_data(root);
+        } else {
+            let states: Vec<StateId> = self.chart.states().map(|(id, _)| id).collect();
+            for state in states {
+                self.bind_data(state);
+            }
+        }
+        if let Some(initial) = self.chart.state(root).initial {
+            self.enter_states(&[initial]);
+        }
+        self.run();
+        Ok(())
+    }
+
+    /// Queues an external event and processes it along with any resulting internal events.
+    pub fn send(&mut self, event: Event) -> Result<(), RuntimeError> {
+        if !self.running {
+            return Err(RuntimeError::NotRunning);
+        }
+        self.external_queue.push_back(event);
+        self.run();
+        Ok(())
+    }
+
+    /// Delivers the delayed events that have come due on the interpreter's clock and processes them.
+    pub fn tick(&mut self) -> Result<(), RuntimeError> {
+        if !self.running {
+            return Err(RuntimeError::NotRunning);
+        }
+        self.run();
+        Ok(())
+    }
+
+    /// Returns how long until the earliest pending delayed event is due, or `None` if none is pending.
+    ///
+    /// A driver can sleep for this long and then call [`tick`](Interpreter::tick).
+    pub fn next_timeout(&self) -> Option<Duration> {
+        let own = self.timers.next_due().map(|due| due.saturating_sub(self.clock.now()));
+        self.invocations.iter().filter_map(|invocation| invocation.session.next_timeout()).chain(own).min()
+    }
+
+    /// Returns the IDs of the active states in document order.
+    pub fn configuration(&self) -> Vec<&str> {
+        self.configuration.iter().map(|&s| self.chart.state(s).id.as_str()).collect()
+    }
+
+    /// Returns true if the state with the given ID is part of the active configuration.
+    pub fn is_in_state(&self, id: &str) -> bool {
+        self.chart.find(id).is_some_and(|s| self.configuration.contains(&s))
+    }
+
+    /// Returns the compiled chart the interpreter executes.
+    pub fn chart(&self) -> &StateChart {
+        &self.chart
+    }
+
+    /// Returns true until a top-level `<final>` state has been reached.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Returns the payload of the top-level `<final>` state's `<donedata>` once the
+    /// session has ended by reaching it; [`Value::Null`] if that state has no `<donedata>`.
+    ///
+    /// Returns `None` while the session is running.
+    pub fn done_data(&self) -> Option<&Value> {
+        self.done_data.as_ref()
+    }
+
+    /// Returns the session ID exposed to the chart as `_sessionid`.
+    pub fn sessionid(&self) -> &str {
+        &self.system.sessionid
+    }
+
+    /// Returns the datamodel, e.g. to read its data back with `downcast_ref`.
+    pub fn datamodel(&self) -> &dyn Datamodel {
+        self.datamodel.as_ref()
+    }
+
+    /// Returns the datamodel mutably, e.g. to update a context struct between events.
+    pub fn datamodel_mut(&mut self) -> &mut dyn Datamodel {
+        self.datamodel.as_mut()
+    }
+
+    /// Evaluates an expression against the current datamodel and configuration,
+    /// for inspecting a running chart.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - A value expression in the chart's datamodel language.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Value)` - The value of the expression.
+    /// * `Err(DatamodelError)` - If the datamodel cannot evaluate it.
+    pub fn evaluate(&mut self, expr: &str) -> Result<Value, DatamodelError> {
+        let ctx = Context::new(&self.chart, &self.configuration);
+        self.datamodel.evaluate(expr, &ctx)
+    }
+
+    // Creates the variables declared in a state's <datamodel>, once.
+    fn bind_data(&mut self, state: StateId) {
+        if !self.bound.insert(state) {
+            return;
+        }
+        let root = self.chart.root();
+        for data in &self.chart.state(state).datamodel {
+            let ctx = Context::new(&self.chart, &self.configuration);
+            // A value passed by the invoking session replaces the initial value of top-level data.
+            let result = match self.params.get(&data.id).filter(|_| state == root) {
+                Some(value) => {
+                    let declaration = Data { expr: None, src: None, content: None, ..data.clone() };
+                    self.datamodel.init_data(&declaration, &ctx).and_then(|()| self.datamodel.assign(&data.id, value.clone(), &ctx))
+                }
+                None => self.datamodel.init_data(data, &ctx),
+            };
+            if let Err(error) = result {
+                self.internal_queue.push_back(error_event("error.execution", &error));
+            }
+        }
+    }
+
+    // Queues a platform error event carrying the cause of the failure.
+    fn raise_error(&mut self, name: &str, error: DatamodelError) {
+        self.internal_queue.push_back(error_event(name, &error));
+    }
+
+    // Queues `error.communication` for a <send> whose event could not be delivered.
+    fn raise_communication_error(&mut self, sendid: &str, cause: String) {
+        self.internal_queue.push_back(communication_error(sendid, cause));
+    }
+
+    // Helper to evaluate a guard against the current configuration.
+    fn evaluate_bool(&mut self, expr: &str) -> Result<bool, DatamodelError> {
+        let ctx = Context::new(&self.chart, &self.configuration);
+        self.datamodel.evaluate_bool(expr, &ctx)
+    }
+
+    // The main event loop, run until both queues are drained or the machine halts.
+    fn run(&mut self) {
+        while self.running {
+            self.macrostep();
+            if !self.running {
+                break;
+            }
+            self.start_invocations();
+            if !self.internal_queue.is_empty() {
+                continue;
+            }
+            self.collect_mail();
+            let now = self.clock.now();
+            while let Some(delayed) = self.timers.pop_due(now) {
+                self.dispatch(delayed.processor, delayed.target.as_deref(), &delayed.sendid, delayed.event);
+            }
+            self.poll_invocations();
+            if !self.internal_queue.is_empty() {
+                continue;
+            }
+            let Some(event) = self.external_queue.pop_front() else {
+                return;
+            };
+            self.datamodel.set_event(&event);
+            self.process_invocation_event(&event);
+            let enabled = self.select_transitions(Some(&event));
+            if !enabled.is_empty() {
+                self.microstep(&enabled);
+            }
+        }
+        self.exit_interpreter();
+    }
+
+    // Takes eventless transitions and internal events until the configuration is stable.
+    fn macrostep(&mut self) {
+        while self.running {
+            let mut enabled = self.select_transitions(None);
+            if enabled.is_empty() {
+                let Some(event) = self.internal_queue.pop_front() else {
+                    break;
+                };
+                self.datamodel.set_event(&event);
+                enabled = self.select_transitions(Some(&event));
+            }
+            if !enabled.is_empty() {
+                self.microstep(&enabled);
+            }
+        }
+    }
+
+    // Stops a running session early, running the onexit handlers of its active states.
+    fn halt(&mut self) {
+        if self.running {
+            self.running = false;
+            self.exit_interpreter();
+        }
+    }
+
+    fn exit_interpreter(&mut self) {
+        let root = self.chart.root();
+        let states: Vec<StateId> = self.configuration.iter().rev().copied().collect();
+        for state in states {
+            let onexit = self.chart.state(state).onexit.clone();
+            self.execute_content(&onexit);
+            self.cancel_invocations(state);
+            self.configuration.remove(&state);
+            if self.chart.state(state).kind == StateKind::Final && self.chart.state(state).parent == Some(root) {
+                self.done_data = Some(self.evaluate_done_data(state));
+            }
+        }
+        self.internal_queue.clear();
+        self.external_queue.clear();
+        self.timers.clear();
+        self.unregister_session();
+    }
+
+    // Selects the enabled transitions for an event, or the eventless ones when `event` is None.
+    fn select_transitions(&mut self, event: Option<&Event>) -> Vec<TransitionId> {
+        let atomic: Vec<StateId> =
+            self.configuration.iter().copied().filter(|&s| self.chart.is_atomic(s)).collect();
+        let mut enabled = Vec::new();
+        for state in atomic {
+            let lineage = std::iter::once(state).chain(self.chart.proper_ancestors(state, None));
+            'lineage: for s in lineage {
+                for &t in &self.chart.state(s).transitions.clone() {
+                    let transition = self.chart.transition(t);
+                    let matched = match (event, &transition.event) {
+                        (None, None) => true,
+                        (Some(event), Some(descriptor)) => descriptor.matches(&event.name),
+                        _ => false,
+                    };
+                    if matched && self.condition_matches(t) {
+                        if !enabled.contains(&t) {
+                            enabled.push(t);
+                        }
+                        break 'lineage;
+                    }
+                }
+            }
+        }
+        self.remove_conflicting_transitions(enabled)
+    }
+
+    fn condition_matches(&mut self, transition: TransitionId) -> bool {
+        let Some(cond) = self.chart.transition(transition).cond.clone() else {
+            return true;
+        };
+        self.condition_holds(&cond)
+    }
+
+    // Evaluates a transition guard or <if> condition; one that fails raises
+    // `error.execution` and counts as false.
+    fn condition_holds(&mut self, cond: &str) -> bool {
+        match self.evaluate_bool(cond) {
+            Ok(value) => value,
+            Err(error) => {
+                self.raise_error("error.execution", error);
+                false
+            }
+        }
+    }
+
+    fn remove_conflicting_transitions(&self, enabled: Vec<TransitionId>) -> Vec<TransitionId> {
+        let mut filtered: Vec<TransitionId> = Vec::new();
+        for t1 in enabled {
+            let exit1 = self.compute_exit_set(&[t1]);
+            let mut preempted = false;
+            let mut to_remove = Vec::new();
+            for &t2 in &filtered {
+                let exit2 = self.compute_exit_set(&[t2]);
+                if exit1.intersection(&exit2).next().is_some() {
+                    let source1 = self.chart.transition(t1).source;
+                    let source2 = self.chart.transition(t2).source;
+                    if self.chart.is_descendant(source1, source2) {
+                        to_remove.push(t2);
+                    } else {
+                        preempted = true;
+                        break;
+                    }
+                }
+            }
+            if !preempted {
+                filtered.retain(|t| !to_remove.contains(t));
+                filtered.push(t1);
+            }
+        }
+        filtered
+    }
+
+    fn microstep(&mut self, enabled: &[TransitionId]) {
+        self.exit_states(enabled);
+        for &t in enabled {
+            let executables = self.chart.transition(t).executables.clone();
+            self.execute_content(&executables);
+        }
+        self.enter_states(enabled);
+    }
+
+    fn exit_states(&mut self, enabled: &[TransitionId]) {
+        let to_exit = self.compute_exit_set(enabled);
+        for state in &to_exit {
+            self.states_to_invoke.remove(state);
+        }
+        for &state in &to_exit {
+            self.record_history(state);
+        }
+        for &state in to_exit.iter().rev() {
+            let onexit = self.chart.state(state).onexit.clone();
+            self.execute_content(&onexit);
+            self.cancel_invocations(state);
+            self.configuration.remove(&state);
+        }
+    }
+
+    // Records the active children (shallow) or active atomic descendants (deep) of a state
+    // about to be exited, for each of its history pseudo-states.
+    fn record_history(&mut self, state: StateId) {
+        for &history in &self.chart.state(state).children {
+            let StateKind::History { deep } = self.chart.state(history).kind else { continue };
+            let recorded = self
+                .configuration
+                .iter()
+                .copied()
+                .filter(|&s| {
+                    if deep {
+                        self.chart.is_atomic(s) && self.chart.is_descendant(s, state)
+                    } else {
+                        self.chart.state(s).parent == Some(state)
+                    }
+                })
+                .collect();
+            self.history_value.insert(history, recorded);
+        }
+    }
+
+    fn compute_exit_set(&self, transitions: &[TransitionId]) -> BTreeSet<StateId> {
+        let mut to_exit = BTreeSet::new();
+        for &t in transitions {
+            if self.chart.transition(t).targets.is_empty() {
+                continue;
+            }
+            if let Some(domain) = self.transition_domain(t) {
+                for &s in &self.configuration {
+                    if self.chart.is_descendant(s, domain) {
+                        to_exit.insert(s);
+                    }
+                }
+            }
+        }
+        to_exit
+    }
+
+    fn enter_states(&mut self, enabled: &[TransitionId]) {
+        let mut entry = EntrySet::default();
+        for &t in enabled {
+            self.compute_entry_set(t, &mut entry);
+        }
+        for &state in &entry.states {
+            self.configuration.insert(state);
+            self.states_to_invoke.insert(state);
+            if self.late_binding {
+                self.bind_data(state);
+            }
+            let onentry = self.chart.state(state).onentry.clone();
+            self.execute_content(&onentry);
+            if entry.default_entry.contains(&state)
+                && let Some(initial) = self.chart.state(state).initial
+            {
+                let executables = self.chart.transition(initial).executables.clone();
+                self.execute_content(&executables);
+            }
+            if let Some(&(_, history)) = entry.default_history.iter().find(|(parent, _)| *parent == state) {
+                let executables = self.chart.transition(history).executables.clone();
+                self.execute_content(&executables);
+            }
+            if self.chart.state(state).kind == StateKind::Final {
+                self.enter_final(state);
+            }
+        }
+    }
+
+    // Raises the completion events triggered by entering a <final> state.
+    fn enter_final(&mut self, state: StateId) {
+        let Some(parent) = self.chart.state(state).parent else { return };
+        let Some(grandparent) = self.chart.state(parent).parent else {
+            self.running = false;
+            return;
+        };
+        let parent_id = self.chart.state(parent).id.clone();
+        let data = self.evaluate_done_data(state);
+        self.internal_queue.push_back(Event::platform(format!("done.state.{}", parent_id)).with_data(data));
+        if self.chart.state(grandparent).kind == StateKind::Parallel
+            && self.chart.child_states(grandparent).all(|child| self.is_in_final_state(child))
+        {
+            let grandparent_id = self.chart.state(grandparent).id.clone();
+            self.internal_queue.push_back(Event::platform(format!("done.state.{}", grandparent_id)));
+        }
+    }
+
+    // Evaluates the <donedata> of a final state. On failure `error.execution` is raised
+    // and the payload is left empty.
+    fn evaluate_done_data(&mut self, state: StateId) -> Value {
+        let Some(donedata) = self.chart.state(state).donedata.clone() else {
+            return Value::Null;
+        };
+        match self.event_data(&donedata.params, None, donedata.content.as_ref()) {
+            Ok(data) => data,
+            Err(error) => {
+                self.raise_error("error.execution", error);
+                Value::Null
+            }
+        }
+    }
+
+    fn is_in_final_state(&self, state: StateId) -> bool {
+        match self.chart.state(state).kind {
+            StateKind::Compound => self.chart.child_states(state).any(|child| {
+                self.chart.state(child).kind == StateKind::Final && self.configuration.contains(&child)
+            }),
+            StateKind::Parallel => self.chart.child_states(state).all(|child| self.is_in_final_state(child)),
+            _ => false,
+        }
+    }
+
+    fn compute_entry_set(&self, transition: TransitionId, entry: &mut EntrySet) {
+        for &s in &self.chart.transition(transition).targets {
+            self.add_descendant_states_to_enter(s, entry);
+        }
+        let ancestor = self.transition_domain(transition);
+        for s in self.effective_target_states(transition) {
+            self.add_ancestor_states_to_enter(s, ancestor, entry);
+        }
+    }
+
+    fn add_descendant_states_to_enter(&self, state: StateId, entry: &mut EntrySet) {
+        let node = self.chart.state(state);
+        if let StateKind::History { .. } = node.kind {
+            let parent = node.parent.expect("history state has a parent");
+            if let Some(recorded) = self.history_value.get(&state) {
+                for &s in recorded {
+                    self.add_descendant_states_to_enter(s, entry);
+                }
+                for &s in recorded {
+                    self.add_ancestor_states_to_enter(s, Some(parent), entry);
+                }
+            } else if let Some(default) = node.initial {
+                // Nothing has been recorded yet, so the default transition is taken.
+                entry.default_history.push((parent, default));
+                for &s in &self.chart.transition(default).targets {
+                    self.add_descendant_states_to_enter(s, entry);
+                }
+                for &s in &self.chart.transition(default).targets {
+                    self.add_ancestor_states_to_enter(s, Some(parent), entry);
+                }
+            }
+            return;
+        }
+        entry.states.insert(state);
+        match node.kind {
+            StateKind::Compound => {
+                entry.default_entry.insert(state);
+                if let Some(initial) = node.initial {
+                    for &s in &self.chart.transition(initial).targets {
+                        self.add_descendant_states_to_enter(s, entry);
+                    }
+                    for &s in &self.chart.transition(initial).targets {
+                        self.add_ancestor_states_to_enter(s, Some(state), entry);
+                    }
+                }
+            }
+            StateKind::Parallel => self.add_parallel_children(state, entry),
+            _ => {}
+        }
+    }
+
+    fn add_ancestor_states_to_enter(&self, state: StateId, ancestor: Option<StateId>, entry: &mut EntrySet) {
+        for anc in self.chart.proper_ancestors(state, ancestor) {
+            entry.states.insert(anc);
+            if self.chart.state(anc).kind == StateKind::Parallel {
+                self.add_parallel_children(anc, entry);
+            }
+        }
+    }
+
+    fn add_parallel_children(&self, state: StateId, entry: &mut EntrySet) {
+        for child in self.chart.child_states(state) {
+            if !entry.states.iter().any(|&s| s == child || self.chart.is_descendant(s, child)) {
+                self.add_descendant_states_to_enter(child, entry);
+            }
+        }
+    }
+
+    fn transition_domain(&self, transition: TransitionId) -> Option<StateId> {
+        let targets = self.effective_target_states(transition);
+        if targets.is_empty() {
+            return None;
+        }
+        let t = self.chart.transition(transition);
+        if t.internal
+            && self.chart.is_compound(t.source)
+            && targets.iter().all(|&s| self.chart.is_descendant(s, t.source))
+        {
+            return Some(t.source);
+        }
+        let mut states = vec![t.source];
+        states.extend(targets);
+        self.chart.lcca(&states)
+    }
+
+    fn effective_target_states(&self, transition: TransitionId) -> Vec<StateId> {
+        let mut targets = Vec::new();
+        for &s in &self.chart.transition(transition).targets {
+            let node = self.chart.state(s);
+            match (node.kind, node.initial) {
+                (StateKind::History { .. }, _) if self.history_value.contains_key(&s) => {
+                    for &t in &self.history_value[&s] {
+                        if !targets.contains(&t) {
+                            targets.push(t);
+                        }
+                    }
+                }
+                (StateKind::History { .. }, Some(default)) => {
+                    for t in self.effective_target_states(default) {
+                        if !targets.contains(&t) {
+                            targets.push(t);
+                        }
+                    }
+                }
+                (StateKind::History { .. }, None) => {}
+                _ => {
+                    if !targets.contains(&s) {
+                        targets.push(s);
+                    }
+                }
+            }
+        }
+        targets
+    }
+
+    // Runs a block of executable content, raising `error.execution` if any element fails.
+    fn execute_content(&mut self, executables: &[Executable]) {
+        if let Err(error) = self.execute_block(executables) {
+            self.raise_error("error.execution", error);
+        }
+    }
+
+    // Executes elements in order, stopping at the first error as the spec requires.
+    fn execute_block(&mut self, executables: &[Executable]) -> Result<(), DatamodelError> {
+        for executable in executables {
+            match executable {
+                Executable::Raise { event, .. } => self.internal_queue.push_back(Event::internal(event.clone())),
+                Executable::Send(send) => self.execute_send(send)?,
+                Executable::If { branches, else_, .. } => {
+                    let mut taken = None;
+                    for branch in branches {
+                        if self.condition_holds(&branch.cond) {
+                            taken = Some(&branch.body);
+                            break;
+                        }
+                    }
+                    if let Some(body) = taken.or(else_.as_ref()) {
+                        self.execute_block(body)?;
+                    }
+                }
+                Executable::Foreach { array, item, index, body, .. } => {
+                    let items = match self.evaluate(array)? {
+                        Value::Array(items) => items,
+                        other => return Err(DatamodelError::Evaluation(format!("'{}' is not an array: {}", array, other))),
+                    };
+                    // The item and index variables are created if the chart did not declare them.
+                    let ctx = Context::new(&self.chart, &self.configuration);
+                    self.datamodel.declare(item, &ctx)?;
+                    if let Some(index) = index {
+                        self.datamodel.declare(index, &ctx)?;
+                    }
+                    for (i, value) in items.into_iter().enumerate() {
+                        let ctx = Context::new(&self.chart, &self.configuration);
+                        self.datamodel.assign(item, value, &ctx)?;
+                        if let Some(index) = index {
+                            self.datamodel.assign(index, Value::Number(i as f64), &ctx)?;
+                        }
+                        self.execute_block(body)?;
+                    }
+                }
+                Executable::Assign { location, expr, .. } => {
+                    let ctx = Context::new(&self.chart, &self.configuration);
+                    self.datamodel.execute_assign(location, expr, &ctx)?;
+                }
+                Executable::Script { src: Some(src), .. } => {
+                    return Err(DatamodelError::Unsupported(format!("<script src=\"{}\">", src)));
+                }
+                Executable::Script { content, .. } => {
+                    let ctx = Context::new(&self.chart, &self.configuration);
+                    self.datamodel.run_script(content.as_deref().unwrap_or(""), &ctx)?;
+                }
+                Executable::Log { label, expr, .. } => {
+                    let value = if expr.is_empty() { Value::Null } else { self.evaluate(expr)? };
+                    if let Some(logger) = self.logger.as_mut() {
+                        logger(label.as_deref(), &value);
+                    }
+                }
+                Executable::Other { name, .. } => {
+                    let ctx = Context::new(&self.chart, &self.configuration);
+                    self.datamodel.execute_custom(name, &ctx)?;
+                }
+                Executable::Cancel { sendid, sendidexpr, .. } => {
+                    let sendid = match (sendid, sendidexpr) {
+                        (Some(sendid), _) => sendid.clone(),
+                        (None, Some(expr)) => self.evaluate(expr)?.to_string(),
+                        (None, None) => return Err(DatamodelError::Evaluation("<cancel> needs a sendid or sendidexpr".to_string())),
+                    };
+                    self.timers.cancel(&sendid);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_send(&mut self, send: &SendElement) -> Result<(), DatamodelError> {
+        let sendid = match &send.id {
+            Some(id) => id.clone(),
+            None => {
+                self.next_sendid += 1;
+                let id = format!("{}.send{}", self.system.sessionid, self.next_sendid);
+                if let Some(location) = &send.idlocation {
+                    let ctx = Context::new(&self.chart, &self.configuration);
+                    self.datamodel.assign(location, Value::String(id.clone()), &ctx)?;
+                }
+                id
+            }
+        };
+        let name = match (&send.event, &send.eventexpr) {
+            (Some(event), _) => event.clone(),
+            (None, Some(expr)) => self.evaluate(expr)?.to_string(),
+            (None, None) => return Err(DatamodelError::Evaluation("<send> needs an event or eventexpr".to_string())),
+        };
+        let target = match (&send.target, &send.targetexpr) {
+            (Some(target), _) => Some(target.clone()),
+            (None, Some(expr)) => Some(self.evaluate(expr)?.to_string()),
+            (None, None) => None,
+        };
+        let type_ = match (&send.type_, &send.typeexpr) {
+            (Some(type_), _) => Some(type_.clone()),
+            (None, Some(expr)) => Some(self.evaluate(expr)?.to_string()),
+            (None, None) => None,
+        };
+        let processor = match type_.as_deref() {
+            None | Some(SCXML_EVENT_PROCESSOR | "scxml") => SCXML_EVENT_PROCESSOR,
+            #[cfg(feature = "http-ioprocessor")]
+            Some(http::BASIC_HTTP_EVENT_PROCESSOR | "basichttp") => http::BASIC_HTTP_EVENT_PROCESSOR,
+            Some(type_) => return Err(DatamodelError::Unsupported(format!("<send> type '{}'", type_))),
+        };
+        let delay = match (&send.delay, &send.delayexpr) {
+            (Some(delay), _) => Some(delay.clone()),
+            (None, Some(expr)) => Some(self.evaluate(expr)?.to_string()),
+            (None, None) => None,
+        };
+        let delay = match delay {
+            Some(delay) => timer::parse_delay(&delay)
+                .ok_or_else(|| DatamodelError::Evaluation(format!("invalid <send> delay '{}'", delay)))?,
+            None => Duration::ZERO,
+        };
+        let data = self.event_data(&send.params, send.namelist.as_deref(), send.content.as_ref())?;
+        match target.as_deref() {
+            Some("#_internal") if processor == SCXML_EVENT_PROCESSOR && !delay.is_zero() => {
+                return Err(DatamodelError::Evaluation("a <send> to #_internal cannot be delayed".to_string()));
+            }
+            Some("#_internal") if processor == SCXML_EVENT_PROCESSOR => {
+                let event = Event { sendid: send.id.clone(), ..Event::internal(name).with_data(data) };
+                self.internal_queue.push_back(event);
+            }
+            _ => {
+                let event = Event { sendid: send.id.clone(), ..Event::new(name).with_data(data) };
+                if delay.is_zero() {
+                    self.dispatch(processor, target.as_deref(), &sendid, event);
+                } else {
+                    let delayed = Delayed { processor, target, sendid: sendid.clone(), event };
+                    self.timers.schedule(self.clock.now() + delay, sendid, delayed);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Builds an event payload from `<param>`s and a `namelist`, or from `<content>`.
+    fn event_data(&mut self, params: &[Param], namelist: Option<&str>, content: Option<&Content>) -> Result<Value, DatamodelError> {
+        if let Some(content) = content {
+            return match (&content.expr, &content.content) {
+                (Some(expr), _) => self.evaluate(expr),
+                (None, Some(body)) => Ok(Value::String(body.as_str().trim().to_string())),
+                (None, None) => Ok(Value::Null),
+            };
+        }
+        if params.is_empty() && namelist.is_none() {
+            return Ok(Value::Null);
+        }
+        let mut fields = BTreeMap::new();
+        for name in namelist.unwrap_or("").split_whitespace() {
+            fields.insert(name.to_string(), self.evaluate(name)?);
+        }
+        for param in params {
+            let value = match (&param.expr, &param.location) {
+                (Some(expr), _) => self.evaluate(expr)?,
+                (None, Some(location)) => self.evaluate(location)?,
+                (None, None) => Value::Null,
+            };
+            fields.insert(param.name.clone(), value);
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+// Builds a platform error event whose data describes the cause.
+fn error_event(name: &str, error: &DatamodelError) -> Event {
+    Event::platform(name).with_data(error.to_string())
+}
+
+// Builds the `error.communication` event for a <send> that could not be delivered.
+fn communication_error(sendid: &str, cause: String) -> Event {
+    Event { sendid: Some(sendid.to_string()), ..Event::platform("error.communication").with_data(cause) }
+}
+
+// An event scheduled by a delayed <send>, with the Event I/O Processor and target it
+// is dispatched to when due.
+#[derive(Debug)]
+struct Delayed {
+    processor: &'static str,
+    target: Option<String>,
+    sendid: String,
+    event: Event,
+}
+
+// States collected by computeEntrySet, kept in document (entry) order.
+#[derive(Default)]
+struct EntrySet {
+    states: BTreeSet<StateId>,
+    default_entry: BTreeSet<StateId>,
+    default_history: Vec<(StateId, TransitionId)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_scxml;
+
+    fn start(xml: &str) -> Interpreter {
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::new(&scxml).unwrap();
+        interpreter.start().unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn test_initial_configuration() {
+        let interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="outer">
+                <state id="a"/>
+                <state id="b"/>
+            </state>
+            <state id="other"/>
+        </scxml>"#);
+        assert_eq!(interpreter.configuration(), vec!["outer", "a"]);
+    }
+
+    #[test]
+    fn test_raise_and_eventless_transitions() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="a">
+                <transition event="go" target="b">
+                    <raise event="next"/>
+                </transition>
+            </state>
+            <state id="b">
+                <transition event="next" target="c"/>
+            </state>
+            <state id="c">
+                <transition target="d"/>
+            </state>
+            <state id="d"/>
+        </scxml>"#);
+        interpreter.send(Event::new("go")).unwrap();
+        assert_eq!(interpreter.configuration(), vec!["d"]);
+    }
+
+    #[test]
+    fn test_parallel_done_event() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <parallel id="p">
+                <state id="r1">
+                    <state id="r1a"><transition event="e1" target="r1done"/></state>
+                    <final id="r1done"/>
+                </state>
+                <state id="r2">
+                    <state id="r2a"><transition event="e2" target="r2done"/></state>
+                    <final id="r2done"/>
+                </state>
+                <transition event="done.state.p" target="end"/>
+            </parallel>
+            <final id="end"/>
+        </scxml>"#);
+        assert_eq!(interpreter.configuration(), vec!["p", "r1", "r1a", "r2", "r2a"]);
+        interpreter.send(Event::new("e1")).unwrap();
+        assert!(interpreter.is_in_state("r1done"));
+        assert!(interpreter.is_running());
+        interpreter.send(Event::new("e2")).unwrap();
+        assert!(!interpreter.is_running());
+        assert!(matches!(interpreter.send(Event::new("e1")), Err(RuntimeError::NotRunning)));
+    }
+
+    #[test]
+    fn test_done_data() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="result" expr="null"/></datamodel>
+            <state id="job">
+                <state id="running"><transition event="finish" target="finished"/></state>
+                <final id="finished">
+                    <donedata><param name="code" expr="7"/><param name="who" expr="'worker'"/></donedata>
+                </final>
+                <transition event="done.state.job" target="end"><assign location="result" expr="_event.data"/></transition>
+            </state>
+            <final id="end"><donedata><content expr="result.code * 6"/></donedata></final>
+        </scxml>"#);
+        assert_eq!(interpreter.done_data(), None);
+        interpreter.send(Event::new("finish")).unwrap();
+        assert!(!interpreter.is_running());
+        assert_eq!(interpreter.done_data(), Some(&Value::Number(42.0)));
+        assert_eq!(interpreter.evaluate("result.who").unwrap(), Value::from("worker"));
+    }
+
+    #[test]
+    fn test_done_data_error_leaves_payload_empty() {
+        let interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <state id="s">
+                <final id="f"><donedata><param name="x" expr="undefined_variable"/></donedata></final>
+                <transition event="error.execution" target="failed"/>
+                <transition event="done.state.s" target="wrong"/>
+            </state>
+            <final id="failed"/>
+            <final id="wrong"/>
+        </scxml>"#);
+        assert_eq!(interpreter.done_data(), Some(&Value::Null));
+        assert!(!interpreter.is_running());
+    }
+
+    #[test]
+    fn test_error_events() {
+        let mut interpreter = start(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="errors" expr="[]"/><data id="after" expr="false"/></datamodel>
+            <state id="s">
+                <transition event="check" cond="missing.field" target="wrong"/>
+                <transition event="assign"><assign location="undeclared" expr="1"/><assign location="after" expr="true"/></transition>
+                <transition event="send">
+                    <send id="out" event="hello" target="#_nowhere"/>
+                    <assign location="after" expr="true"/>
+                </transition>
+                <transition event="typed"><send event="hello" type="http://example.com/unknown"/></transition>
+                <transition event="error.*">
+                    <assign location="errors" expr="errors.concat([[_event.name, _event.type, _event.sendid, _event.data]])"/>
+                </transition>
+            </state>
+            <state id="wrong"/>
+        </scxml>"##);
+        // Each handled error is recorded as [name, type, sendid, data].
+        let error = |interpreter: &mut Interpreter, event: &str| -> Vec<Value> {
+            interpreter.send(Event::new(event)).unwrap();
+            match interpreter.evaluate("errors[errors.length - 1]").unwrap() {
+                Value::Array(fields) => fields,
+                other => panic!("unexpected record {}", other),
+            }
+        };
+
+        let check = error(&mut interpreter, "check");
+        assert_eq!(check[..3], [Value::from("error.execution"), "platform".into(), Value::Null]);
+        assert!(check[3].as_str().unwrap().contains("missing"));
+        assert!(interpreter.is_in_state("s"));
+
+        let assign = error(&mut interpreter, "assign");
+        assert_eq!(assign[3], Value::from("Invalid location: undeclared is not declared"));
+        assert_eq!(interpreter.evaluate("after").unwrap(), Value::Bool(false));
+
+        let send = error(&mut interpreter, "send");
+        assert_eq!(send[..3], [Value::from("error.communication"), "platform".into(), "out".into()]);
+        assert_eq!(send[3], Value::from("cannot deliver 'hello' to unknown target '#_nowhere'"));
+        assert_eq!(interpreter.evaluate("after").unwrap(), Value::Bool(true));
+
+        let typed = error(&mut interpreter, "typed");
+        assert_eq!(typed[0], Value::from("error.execution"));
+        assert_eq!(typed[3], Value::from("Not supported by this datamodel: <send> type 'http://example.com/unknown'"));
+    }
+
+    #[test]
+    fn test_internal_transition_does_not_exit_source() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="outer">
+                <onexit><raise event="left"/></onexit>
+                <state id="a"/>
+                <state id="b"/>
+                <transition event="go" type="internal" target="b"/>
+                <transition event="left" target="gone"/>
+            </state>
+            <state id="gone"/>
+        </scxml>"#);
+        interpreter.send(Event::new("go")).unwrap();
+        assert_eq!(interpreter.configuration(), vec!["outer", "b"]);
+    }
+
+    #[test]
+    fn test_elseif_takes_first_true_branch() {
+        let interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s">
+                <onentry>
+                    <if cond="false">
+                        <raise event="a"/>
+                    <elseif cond="In('s')"/>
+                        <raise event="b"/>
+                    <elseif cond="true"/>
+                        <raise event="c"/>
+                    <else/>
+                        <raise event="d"/>
+                    </if>
+                </onentry>
+                <transition event="a" target="a"/>
+                <transition event="b" target="b"/>
+                <transition event="c" target="c"/>
+                <transition event="d" target="d"/>
+            </state>
+            <state id="a"/>
+            <state id="b"/>
+            <state id="c"/>
+            <state id="d"/>
+        </scxml>"#);
+        assert_eq!(interpreter.configuration(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_failing_if_condition_counts_as_false() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="trace" expr="''"/></datamodel>
+            <state id="s">
+                <onentry>
+                    <if cond="nope.x">
+                        <assign location="trace" expr="trace + 'if '"/>
+                    <elseif cond="nope.y"/>
+                        <assign location="trace" expr="trace + 'elseif '"/>
+                    <else/>
+                        <assign location="trace" expr="trace + 'else '"/>
+                    </if>
+                    <assign location="trace" expr="trace + 'after'"/>
+                </onentry>
+                <transition event="error.execution" target="failed"/>
+            </state>
+            <state id="failed"/>
+        </scxml>"#);
+        assert_eq!(interpreter.evaluate("trace").unwrap(), Value::from("else after"));
+        assert_eq!(interpreter.configuration(), vec!["failed"]);
+    }
+
+    #[test]
+    fn test_foreach_declares_item_and_index() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <datamodel><data id="sum" expr="0"/></datamodel>
+            <state id="s">
+                <onentry>
+                    <foreach array="[1, 2, 3]" item="x" index="i">
+                        <assign location="sum" expr="sum + x * i"/>
+                    </foreach>
+                </onentry>
+                <transition event="error.execution" target="failed"/>
+            </state>
+            <state id="failed"/>
+        </scxml>"#);
+        assert_eq!(interpreter.configuration(), vec!["s"]);
+        assert_eq!(interpreter.evaluate("sum").unwrap(), Value::Number(8.0));
+        assert_eq!(interpreter.evaluate("[x, i]").unwrap(), Value::Array(vec![3.0.into(), 2.0.into()]));
+    }
+
+    #[test]
+    fn test_descriptor_matching() {
+        let interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="a">
+                <onentry><raise event="DIGIT.4"/></onentry>
+                <transition event="OPER DIGIT" target="b"/>
+            </state>
+            <state id="b"/>
+        </scxml>"#);
+        assert_eq!(interpreter.configuration(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_history_restores_recorded_states() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="work">
+                <history id="shallow"><transition target="b"/></history>
+                <history id="deep" type="deep"><transition target="a"/></history>
+                <state id="a">
+                    <state id="a1"><transition event="next" target="a2"/></state>
+                    <state id="a2"/>
+                </state>
+                <state id="b"/>
+                <transition event="pause" target="paused"/>
+            </state>
+            <state id="paused">
+                <transition event="resume.shallow" target="shallow"/>
+                <transition event="resume.deep" target="deep"/>
+            </state>
+        </scxml>"#);
+        assert_eq!(interpreter.configuration(), vec!["work", "a", "a1"]);
+        interpreter.send(Event::new("pause")).unwrap();
+        interpreter.send(Event::new("resume.shallow")).unwrap();
+        assert_eq!(interpreter.configuration(), vec!["work", "a", "a1"]);
+
+        interpreter.send(Event::new("next")).unwrap();
+        interpreter.send(Event::new("pause")).unwrap();
+        interpreter.send(Event::new("resume.shallow")).unwrap();
+        // Shallow history restores `a`, which is entered through its initial state.
+        assert_eq!(interpreter.configuration(), vec!["work", "a", "a1"]);
+
+        interpreter.send(Event::new("next")).unwrap();
+        interpreter.send(Event::new("pause")).unwrap();
+        interpreter.send(Event::new("resume.deep")).unwrap();
+        assert_eq!(interpreter.configuration(), vec!["work", "a", "a2"]);
+    }
+
+    #[test]
+    fn test_history_default_transition() {
+        let mut interpreter = start(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="idle"><transition event="go" target="h"/></state>
+            <state id="work">
+                <history id="h"><transition target="b"><raise event="defaulted"/></transition></history>
+                <state id="a"/>
+                <state id="b"><transition event="defaulted" target="c"/></state>
+                <state id="c"/>
+            </state>
+        </scxml>"#);
+        interpreter.send(Event::new("go")).unwrap();
+        assert_eq!(interpreter.configuration(), vec!["work", "c"]);
+    }
+
+    // A datamodel whose expressions are variable names, numbers or quoted strings.
+    #[derive(Debug, Default)]
+    struct Variables {
+        vars: BTreeMap<String, Value>,
+        event: Option<Event>,
+    }
+
+    impl Datamodel for Variables {
+        fn init(&mut self, system: &SystemVariables) -> Result<(), DatamodelError> {
+            self.vars.insert("_sessionid".into(), system.sessionid.clone().into());
+            Ok(())
+        }
+
+        fn init_data(&mut self, data: &Data, ctx: &Context) -> Result<(), DatamodelError> {
+            let value = match data.expr {
+                Some(ref expr) => self.evaluate(expr, ctx)?,
+                None => Value::Null,
+            };
+            self.vars.insert(data.id.clone(), value);
+            Ok(())
+        }
+
+        fn set_event(&mut self, event: &Event) {
+            self.event = Some(event.clone());
+        }
+
+        fn evaluate(&mut self, expr: &str, _ctx: &Context) -> Result<Value, DatamodelError> {
+            if let Ok(n) = expr.parse::<f64>() {
+                return Ok(Value::Number(n));
+            }
+            if let Some(s) = expr.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+                return Ok(s.into());
+            }
+            if let Some(field) = expr.strip_prefix("_event.") {
+                let event = self.event.as_ref().ok_or_else(|| DatamodelError::Evaluation(expr.to_string()))?;
+                return Ok(if field == "name" { event.name.clone().into() } else { event.data.clone() });
+            }
+            if expr == "items" {
+                return Ok(Value::Array(vec![1.0.into(), 2.0.into(), 3.0.into()]));
+            }
+            self.vars.get(expr).cloned().ok_or_else(|| DatamodelError::Evaluation(expr.to_string()))
+        }
+
+        fn assign(&mut self, location: &str, value: Value, _ctx: &Context) -> Result<(), DatamodelError> {
+            match self.vars.get_mut(location) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(DatamodelError::InvalidLocation(location.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_datamodel() {
+        let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <datamodel>
+                <data id="last" expr="0"/>
+                <data id="seen"/>
+            </datamodel>
+            <state id="a">
+                <onentry>
+                    <foreach array="items" item="last"/>
+                    <log label="last" expr="last"/>
+                    <raise event="ping"/>
+                </onentry>
+                <transition event="ping" target="b">
+                    <assign location="seen" expr="_event.name"/>
+                    <send event="out" namelist="seen"><param name="n" expr="last"/></send>
+                </transition>
+            </state>
+            <state id="b">
+                <transition event="out" target="c"><log label="data" expr="_event.data"/></transition>
+            </state>
+            <state id="c">
+                <onentry><assign location="missing" expr="1"/></onentry>
+                <transition event="error.execution" target="failed"><log label="error" expr="_event.data"/></transition>
+            </state>
+            <state id="failed"/>
+        </scxml>"#;
+        let scxml = parse_scxml(xml).unwrap();
+        let mut interpreter = Interpreter::with_datamodel(&scxml, Box::new(Variables::default())).unwrap();
+        let logs = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = logs.clone();
+        interpreter.set_logger(move |label, value| sink.lock().unwrap().push((label.map(str::to_string), value.clone())));
+        interpreter.start().unwrap();
+
+        // The <send> went to the external queue and was processed before start() returned;
+        // the failing <assign> in c then raised error.execution carrying the cause.
+        assert_eq!(interpreter.configuration(), vec!["failed"]);
+        let logs = logs.lock().unwrap();
+        assert_eq!(logs[0], (Some("last".to_string()), Value::Number(3.0)));
+        assert_eq!(logs[1].1.get("seen"), Some(&Value::from("ping")));
+        assert_eq!(logs[1].1.get("n"), Some(&Value::Number(3.0)));
+        assert_eq!(logs[2].1, Value::from("Invalid location: missing"));
+    }
+}
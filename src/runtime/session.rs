@@ -0,0 +1,359 @@
+//! Sessions run as tokio tasks, enabled by the `tokio` feature.
+//!
+//! A [`Session`] moves an [`Interpreter`] into a task that processes the events
+//! queued with [`Session::send`] and delivers delayed `<send>` events on
+//! `tokio::time`. Subscribers receive a [`SessionUpdate`] whenever the active
+//! configuration changes, for every event the chart sends to `#_parent`, and when
+//! the session ends. Dropping the handle cancels the session: its active states
+//! are exited, running their onexit handlers, and the task finishes.
+//!
+//! Events from other sessions, invoked processes or an HTTP listener are processed
+//! as soon as they arrive.
+//!
+//! ```rust
+//! use harel::parse_scxml;
+//! use harel::runtime::Event;
+//! use harel::runtime::session::{Session, SessionUpdate};
+//!
+//! let xml = r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+//!     <state id="idle">
+//!         <transition event="start" target="running"/>
+//!     </state>
+//!     <state id="running">
+//!         <onentry><send event="started" target="#_parent"/></onentry>
+//!     </state>
+//! </scxml>"##;
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+//! runtime.block_on(async {
+//!     let session = Session::spawn(&parse_scxml(xml).unwrap()).unwrap();
+//!     let mut updates = session.subscribe();
+//!     assert_eq!(session.configuration(), vec!["idle"]);
+//!
+//!     session.send(Event::new("start")).await.unwrap();
+//!     assert!(matches!(updates.recv().await.unwrap(), SessionUpdate::Emitted(e) if e.name == "started"));
+//!     assert!(matches!(updates.recv().await.unwrap(), SessionUpdate::Configuration(c) if c == ["running"]));
+//! });
+//! ```
+
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc, watch};
+
+use crate::Scxml;
+use crate::datamodel::Value;
+
+use super::{Clock, Event, Interpreter, RuntimeError};
+
+// Events that can be queued before `Session::send` waits for the session to catch up.
+const INBOX_CAPACITY: usize = 64;
+
+// Updates kept for subscribers that fall behind.
+const UPDATES_CAPACITY: usize = 256;
+
+/// A clock following `tokio::time`, so that paused or advanced test time also
+/// moves delayed events.
+#[derive(Debug, Clone, Copy)]
+pub struct TokioClock {
+    start: tokio::time::Instant,
+}
+
+impl TokioClock {
+    /// Creates a clock whose epoch is the current tokio instant.
+    pub fn new() -> Self {
+        Self { start: tokio::time::Instant::now() }
+    }
+}
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A change published by a running [`Session`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionUpdate {
+    /// The IDs of the active states in document order, after they changed.
+    Configuration(Vec<String>),
+    /// An event the chart sent to `#_parent`.
+    Emitted(Event),
+    /// The session reached a top-level `<final>` state, with its done data, or was cancelled (`None`).
+    Done(Option<Value>),
+}
+
+/// A handle to an interpreter running as a tokio task.
+///
+/// Dropping the handle cancels the session.
+#[derive(Debug)]
+pub struct Session {
+    sessionid: String,
+    inbox: mpsc::Sender<Event>,
+    updates: broadcast::Sender<SessionUpdate>,
+    configuration: watch::Receiver<Vec<String>>,
+}
+
+impl Session {
+    /// Compiles a document and runs it as a new task with the built-in datamodel it names.
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `scxml` - The parsed SCXML document to execute.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Session)` - The handle to the started session.
+    /// * `Err(RuntimeError)` - If the document fails validation or names a datamodel that is not built in.
+    pub fn spawn(scxml: &Scxml) -> Result<Self, RuntimeError> {
+        Self::from_interpreter(Interpreter::new(scxml)?)
+    }
+
+    /// Starts an interpreter, configured with its datamodel, logger and invoke handlers,
+    /// and runs it as a new task. Its clock is replaced by a [`TokioClock`].
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `interpreter` - An interpreter that has not been started.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Session)` - The handle to the started session.
+    /// * `Err(RuntimeError)` - If the interpreter has already been started.
+    pub fn from_interpreter(mut interpreter: Interpreter) -> Result<Self, RuntimeError> {
+        if interpreter.started {
+            return Err(RuntimeError::AlreadyStarted);
+        }
+        interpreter.set_clock(TokioClock::new());
+        interpreter.parent_outbox = Some(Vec::new());
+        #[cfg(feature = "http-ioprocessor")]
+        {
+            interpreter.poster = Some(super::http::Poster::new(tokio::runtime::Handle::current()));
+        }
+        interpreter.start()?;
+
+        let sessionid = interpreter.sessionid().to_string();
+        let (inbox, events) = mpsc::channel(INBOX_CAPACITY);
+        let (updates, _) = broadcast::channel(UPDATES_CAPACITY);
+        let (configuration, configuration_rx) = watch::channel(active_states(&interpreter));
+        tokio::spawn(drive(interpreter, events, updates.clone(), configuration));
+        Ok(Self { sessionid, inbox, updates, configuration: configuration_rx })
+    }
+
+    /// Queues an external event, waiting while the inbox is full.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(RuntimeError::NotRunning)` - If the session has ended.
+    pub async fn send(&self, event: Event) -> Result<(), RuntimeError> {
+        self.inbox.send(event).await.map_err(|_| RuntimeError::NotRunning)
+    }
+
+    /// Subscribes to the updates published from now on. Events the chart emitted while
+    /// starting may already have been published; the configuration it started in is
+    /// returned by [`configuration`](Session::configuration).
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Returns the IDs of the active states after the last processed event, in document order.
+    pub fn configuration(&self) -> Vec<String> {
+        self.configuration.borrow().clone()
+    }
+
+    /// Returns the session ID exposed to the chart as `_sessionid`.
+    pub fn sessionid(&self) -> &str {
+        &self.sessionid
+    }
+
+    /// Returns true until the session has ended.
+    pub fn is_running(&self) -> bool {
+        !self.inbox.is_closed()
+    }
+}
+
+// The task body: processes events and timers until the session ends or its handle is dropped.
+async fn drive(
+    mut interpreter: Interpreter,
+    mut events: mpsc::Receiver<Event>,
+    updates: broadcast::Sender<SessionUpdate>,
+    configuration: watch::Sender<Vec<String>>,
+) {
+    loop {
+        publish(&mut interpreter, &updates, &configuration);
+        if !interpreter.is_running() {
+            let _ = updates.send(SessionUpdate::Done(interpreter.done_data().cloned()));
+            return;
+        }
+        let timeout = interpreter.next_timeout();
+        let waker = interpreter.mailbox.waker.clone();
+        let received = tokio::select! {
+            received = events.recv() => received,
+            _ = waker.notified() => {
+                let _ = interpreter.tick();
+                continue;
+            }
+            _ = sleep(timeout) => {
+                let _ = interpreter.tick();
+                continue;
+            }
+        };
+        match received {
+            Some(event) => {
+                let _ = interpreter.send(event);
+            }
+            None => {
+                interpreter.halt();
+                publish(&mut interpreter, &updates, &configuration);
+                let _ = updates.send(SessionUpdate::Done(None));
+                return;
+            }
+        }
+    }
+}
+
+// Sleeps for the given time, or forever if there is nothing to wait for.
+async fn sleep(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Publishes the events sent to `#_parent`, then the configuration if it changed.
+fn publish(interpreter: &mut Interpreter, updates: &broadcast::Sender<SessionUpdate>, configuration: &watch::Sender<Vec<String>>) {
+    for event in interpreter.parent_outbox.as_mut().map(std::mem::take).unwrap_or_default() {
+        let _ = updates.send(SessionUpdate::Emitted(event));
+    }
+    let current = active_states(interpreter);
+    if *configuration.borrow() != current {
+        configuration.send_replace(current.clone());
+        let _ = updates.send(SessionUpdate::Configuration(current));
+    }
+}
+
+fn active_states(interpreter: &Interpreter) -> Vec<String> {
+    interpreter.configuration().into_iter().map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_scxml;
+
+    fn spawn(xml: &str) -> Session {
+        Session::spawn(&parse_scxml(xml).unwrap()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_events_and_delayed_sends() {
+        let session = spawn(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+            <state id="idle">
+                <transition event="brew" target="brewing"/>
+            </state>
+            <state id="brewing">
+                <onentry><send event="ready" delay="30s"/></onentry>
+                <transition event="ready" target="done">
+                    <send event="coffee" target="#_parent"><content expr="'espresso'"/></send>
+                </transition>
+            </state>
+            <final id="done"><donedata><content expr="1"/></donedata></final>
+        </scxml>"##);
+        let mut updates = session.subscribe();
+        let started = tokio::time::Instant::now();
+        session.send(Event::new("brew")).await.unwrap();
+        assert_eq!(updates.recv().await.unwrap(), SessionUpdate::Configuration(vec!["brewing".to_string()]));
+
+        // The paused clock jumps straight to the delayed event.
+        match updates.recv().await.unwrap() {
+            SessionUpdate::Emitted(event) => assert_eq!((event.name.as_str(), event.data), ("coffee", Value::from("espresso"))),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(started.elapsed(), Duration::from_secs(30));
+        // Reaching the top-level <final> ends the session, leaving no active state.
+        assert_eq!(updates.recv().await.unwrap(), SessionUpdate::Configuration(Vec::new()));
+        assert_eq!(updates.recv().await.unwrap(), SessionUpdate::Done(Some(Value::Number(1.0))));
+        assert!(!session.is_running());
+        assert!(matches!(session.send(Event::new("brew")).await, Err(RuntimeError::NotRunning)));
+    }
+
+    #[tokio::test]
+    async fn test_drop_cancels_session() {
+        let session = spawn(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="working">
+                <onentry><send event="tick" delay="1h"/></onentry>
+                <onexit><send event="stopped" target="#_parent"/></onexit>
+            </state>
+        </scxml>"##);
+        let mut updates = session.subscribe();
+        let mut interpreter = Interpreter::new(&parse_scxml(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0"/>"#).unwrap()).unwrap();
+        interpreter.start().unwrap();
+        assert!(matches!(Session::from_interpreter(interpreter), Err(RuntimeError::AlreadyStarted)));
+
+        drop(session);
+        assert!(matches!(updates.recv().await.unwrap(), SessionUpdate::Emitted(event) if event.name == "stopped"));
+        assert_eq!(updates.recv().await.unwrap(), SessionUpdate::Configuration(Vec::new()));
+        assert_eq!(updates.recv().await.unwrap(), SessionUpdate::Done(None));
+        assert!(matches!(updates.recv().await, Err(broadcast::error::RecvError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn test_mail_wakes_session() {
+        let session = spawn(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="waiting"><transition event="ping" target="pinged"/></state>
+            <state id="pinged"/>
+        </scxml>"#);
+        let mut updates = session.subscribe();
+        let mut sender = Interpreter::new(&parse_scxml(&format!(r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="s"><onentry><send event="ping" target="#_scxml_{}"/></onentry></state>
+        </scxml>"##, session.sessionid())).unwrap()).unwrap();
+        sender.start().unwrap();
+
+        let update = tokio::time::timeout(Duration::from_secs(5), updates.recv()).await.unwrap().unwrap();
+        assert_eq!(update, SessionUpdate::Configuration(vec!["pinged".to_string()]));
+    }
+
+    #[cfg(feature = "http-ioprocessor")]
+    #[tokio::test]
+    async fn test_basic_http_events() {
+        use super::super::http::HttpListener;
+
+        let listener = HttpListener::bind("127.0.0.1:0").unwrap();
+        let unreachable = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut interpreter = Interpreter::new(&parse_scxml(&format!(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+            <state id="waiting">
+                <transition event="order.placed" target="sending"/>
+            </state>
+            <state id="sending">
+                <onentry><send id="out" event="ping" target="http://{unreachable}/x" type="basichttp"/></onentry>
+                <transition event="error.communication" target="failed"/>
+            </state>
+            <state id="failed"/>
+        </scxml>"#)).unwrap()).unwrap();
+        listener.attach(&mut interpreter);
+        let session = Session::from_interpreter(interpreter).unwrap();
+        let mut updates = session.subscribe();
+
+        // The POST arrives from outside the runtime, through the listener alone.
+        let location = listener.location(session.sessionid());
+        tokio::task::spawn_blocking(move || {
+            let mut sender = Interpreter::new(&parse_scxml(&format!(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+                <state id="s"><onentry><send event="order.placed" target="{location}" type="basichttp"/></onentry></state>
+            </scxml>"#)).unwrap()).unwrap();
+            sender.start().unwrap();
+        }).await.unwrap();
+
+        let mut next = async || tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap().unwrap();
+        assert_eq!(next().await, SessionUpdate::Configuration(vec!["sending".to_string()]));
+        assert_eq!(next().await, SessionUpdate::Configuration(vec!["failed".to_string()]));
+    }
+}
@@ -0,0 +1,223 @@
+//! Clocks and the queue of events scheduled by `<send delay="...">`.
+//!
+//! The [`Interpreter`](crate::runtime::Interpreter) reads time from a [`Clock`].
+//! [`SystemClock`] follows real time; [`ManualClock`] only moves when it is told
+//! to, so tests can advance time deterministically:
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use harel::parse_scxml;
+//! use harel::runtime::{Interpreter, ManualClock};
+//!
+//! let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+//!     <state id="waiting">
+//!         <onentry><send event="timeout" delay="2s"/></onentry>
+//!         <transition event="timeout" target="expired"/>
+//!     </state>
+//!     <state id="expired"/>
+//! </scxml>"#;
+//!
+//! let clock = ManualClock::new();
+//! let scxml = parse_scxml(xml).unwrap();
+//! let mut interpreter = Interpreter::new(&scxml).unwrap();
+//! interpreter.set_clock(clock.clone());
+//! interpreter.start().unwrap();
+//! assert_eq!(interpreter.next_timeout(), Some(Duration::from_secs(2)));
+//!
+//! clock.advance(Duration::from_secs(2));
+//! interpreter.tick().unwrap();
+//! assert!(interpreter.is_in_state("expired"));
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::runtime::Event;
+
+/// A source of time for scheduling delayed events.
+///
+/// An interpreter shares its clock with the child sessions it invokes.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    /// Returns the time elapsed since the clock's own, arbitrary, epoch.
+    fn now(&self) -> Duration;
+}
+
+/// A clock that follows real time, measured from its creation.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    /// Creates a clock whose epoch is the current instant.
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A virtual clock that only advances when told to.
+///
+/// Clones share the same time, so a test keeps one handle and gives a clone to the interpreter.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at time zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+
+    /// Sets the current time. Setting it backwards delays events that are not yet delivered.
+    pub fn set(&self, now: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Events waiting for their delay to expire, ordered by due time.
+///
+/// Events due at the same time are delivered in the order they were scheduled.
+/// The queue holds [`Event`]s by default; the interpreter also records where each one is sent.
+#[derive(Debug, Clone)]
+pub struct TimerQueue<T = Event> {
+    // Keyed by due time and then a sequence number that preserves scheduling order.
+    pending: BTreeMap<(Duration, u64), (String, T)>,
+    next_seq: u64,
+}
+
+impl<T> Default for TimerQueue<T> {
+    fn default() -> Self {
+        Self { pending: BTreeMap::new(), next_seq: 0 }
+    }
+}
+
+impl<T> TimerQueue<T> {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedules an event.
+    ///
+    /// # Arguments
+    ///
+    /// * `due` - The clock time at which the event is delivered.
+    /// * `sendid` - The send ID the event can be cancelled by.
+    /// * `event` - The event to deliver.
+    pub fn schedule(&mut self, due: Duration, sendid: impl Into<String>, event: T) {
+        self.pending.insert((due, self.next_seq), (sendid.into(), event));
+        self.next_seq += 1;
+    }
+
+    /// Removes every pending event with the given send ID, returning true if there was one.
+    pub fn cancel(&mut self, sendid: &str) -> bool {
+        let before = self.pending.len();
+        self.pending.retain(|_, (id, _)| id != sendid);
+        self.pending.len() != before
+    }
+
+    /// Removes and returns the earliest event due at or before `now`.
+    pub fn pop_due(&mut self, now: Duration) -> Option<T> {
+        let entry = self.pending.first_entry().filter(|entry| entry.key().0 <= now)?;
+        Some(entry.remove().1)
+    }
+
+    /// Returns the due time of the earliest pending event.
+    pub fn next_due(&self) -> Option<Duration> {
+        self.pending.keys().next().map(|&(due, _)| due)
+    }
+
+    /// Returns the number of pending events.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if no event is pending.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Drops every pending event.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Parses a CSS2 time value such as `"5s"`, `"1.5s"` or `"250ms"`.
+///
+/// # Returns
+///
+/// * `Some(Duration)` - The delay.
+/// * `None` - If the value is not a non-negative number followed by `s` or `ms`.
+pub fn parse_delay(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, scale) = match value.strip_suffix("ms") {
+        Some(number) => (number, 0.001),
+        None => (value.strip_suffix('s')?, 1.0),
+    };
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    let seconds = number.parse::<f64>().ok()? * scale;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_delay() {
+        assert_eq!(parse_delay("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_delay(" 250ms "), Some(Duration::from_millis(250)));
+        assert_eq!(parse_delay(".5s"), Some(Duration::from_millis(500)));
+        for invalid in ["", "5", "s", "-1s", "1.5.2s", "5 s", "1e3ms"] {
+            assert_eq!(parse_delay(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_queue_orders_and_cancels() {
+        let mut queue: TimerQueue = TimerQueue::new();
+        let at = Duration::from_secs;
+        queue.schedule(at(2), "late", Event::new("late"));
+        queue.schedule(at(1), "first", Event::new("first"));
+        queue.schedule(at(1), "second", Event::new("second"));
+        queue.schedule(at(1), "cancelled", Event::new("cancelled"));
+        assert!(queue.cancel("cancelled"));
+        assert!(!queue.cancel("cancelled"));
+        assert_eq!(queue.next_due(), Some(at(1)));
+
+        assert_eq!(queue.pop_due(at(0)), None);
+        let names: Vec<String> = std::iter::from_fn(|| queue.pop_due(at(1))).map(|e| e.name).collect();
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.pop_due(at(5)).map(|e| e.name), Some("late".to_string()));
+        assert!(queue.is_empty());
+    }
+}
@@ -1,7 +1,207 @@
+use harel::runtime::{Event, Interpreter};
 use harel::*;
+use std::time::Duration;
 
 #[test]
 fn test_basic_functionality() {
     // Basic smoke test to verify the crate works
     assert_eq!(2 + 2, 4);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_microwave_parallel_runs() {
+    let xml = std::fs::read_to_string("examples/microwave-parallell.scxml").unwrap();
+    let scxml = parse_scxml(&xml).unwrap();
+    let mut interpreter = Interpreter::new(&scxml).unwrap();
+    interpreter.start().unwrap();
+    assert_eq!(interpreter.configuration(), vec!["oven", "engine", "off", "door", "closed"]);
+
+    interpreter.send(Event::new("door.open")).unwrap();
+    assert!(interpreter.is_in_state("open"));
+    assert!(interpreter.is_in_state("off"));
+}
+
+#[test]
+fn test_microwave_cook_timer() {
+    // The microwave example, with the 'time' event sent by a repeating delayed <send>.
+    let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript" initial="off">
+        <datamodel>
+            <data id="cook_time" expr="5"/>
+            <data id="door_closed" expr="true"/>
+            <data id="timer" expr="0"/>
+            <data id="tick" expr="null"/>
+        </datamodel>
+        <state id="off">
+            <transition event="turn.on" target="on"/>
+        </state>
+        <state id="on">
+            <initial><transition target="idle"/></initial>
+            <transition event="turn.off" target="off"/>
+            <transition cond="timer &gt;= cook_time" target="off"/>
+            <state id="idle">
+                <transition cond="door_closed" target="cooking"/>
+                <transition event="door.close" target="cooking">
+                    <assign location="door_closed" expr="true"/>
+                </transition>
+            </state>
+            <state id="cooking">
+                <onentry><send event="time" idlocation="tick" delay="1s"/></onentry>
+                <onexit><cancel sendidexpr="tick"/></onexit>
+                <transition event="door.open" target="idle">
+                    <assign location="door_closed" expr="false"/>
+                </transition>
+                <transition event="time">
+                    <assign location="timer" expr="timer + 1"/>
+                    <send event="time" idlocation="tick" delay="1s"/>
+                </transition>
+            </state>
+        </state>
+    </scxml>"#;
+    let scxml = parse_scxml(xml).unwrap();
+    let clock = runtime::ManualClock::new();
+    let mut interpreter = Interpreter::new(&scxml).unwrap();
+    interpreter.set_clock(clock.clone());
+    interpreter.start().unwrap();
+    interpreter.send(Event::new("turn.on")).unwrap();
+    assert!(interpreter.is_in_state("cooking"));
+
+    // Opening the door cancels the pending tick; closing it starts a new one.
+    clock.advance(Duration::from_millis(1500));
+    interpreter.tick().unwrap();
+    interpreter.send(Event::new("door.open")).unwrap();
+    assert_eq!(interpreter.next_timeout(), None);
+    interpreter.send(Event::new("door.close")).unwrap();
+    assert_eq!(interpreter.next_timeout(), Some(Duration::from_secs(1)));
+
+    for _ in 0..3 {
+        clock.advance(Duration::from_secs(1));
+        interpreter.tick().unwrap();
+    }
+    assert_eq!(interpreter.evaluate("timer").unwrap(), 4.0.into());
+    assert!(interpreter.is_in_state("cooking"));
+    clock.advance(Duration::from_secs(1));
+    interpreter.tick().unwrap();
+    assert!(interpreter.is_in_state("off"));
+    assert_eq!(interpreter.next_timeout(), None);
+}
+
+#[test]
+fn test_invoked_session_from_src_shares_clock() {
+    let parent = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+        <state id="waiting">
+            <invoke id="alarm" src="file:alarm.scxml"/>
+            <transition event="ring" target="awake"/>
+        </state>
+        <state id="awake"/>
+    </scxml>"#;
+    let alarm = r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+        <state id="set">
+            <onentry><send event="ring" target="#_parent" delay="5s"/></onentry>
+        </state>
+    </scxml>"##;
+    let scxml = parse_scxml(parent).unwrap();
+    let clock = runtime::ManualClock::new();
+    let mut interpreter = Interpreter::new(&scxml).unwrap();
+    interpreter.set_clock(clock.clone());
+    interpreter.set_loader(move |src| match src {
+        "file:alarm.scxml" => Ok(alarm.to_string()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    });
+    interpreter.start().unwrap();
+    assert_eq!(interpreter.next_timeout(), Some(Duration::from_secs(5)));
+
+    clock.advance(Duration::from_secs(5));
+    interpreter.tick().unwrap();
+    assert!(interpreter.is_in_state("awake"));
+    assert_eq!(interpreter.next_timeout(), None);
+}
+
+#[test]
+fn test_done_state_event_completes_compound_state() {
+    let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" initial="Main" datamodel="ecmascript">
+        <datamodel><data id="result" expr="null"/></datamodel>
+        <state id="Main">
+            <state id="Test1">
+                <state id="Test1Sub1">
+                    <transition event="Event1" target="Test1Sub2"/>
+                </state>
+                <final id="Test1Sub2">
+                    <donedata><param name="passed" expr="true"/></donedata>
+                </final>
+                <transition event="done.state.Test1" target="Test2">
+                    <assign location="result" expr="_event.data.passed"/>
+                </transition>
+            </state>
+            <state id="Test2">
+                <state id="Test2Sub1"/>
+            </state>
+        </state>
+    </scxml>"#;
+    let scxml = parse_scxml(xml).unwrap();
+    let mut interpreter = Interpreter::new(&scxml).unwrap();
+    interpreter.start().unwrap();
+    assert_eq!(interpreter.configuration(), vec!["Main", "Test1", "Test1Sub1"]);
+
+    // Entering the final Test1Sub2 raises done.state.Test1, which moves on to Test2.
+    interpreter.send(Event::new("Event1")).unwrap();
+    assert_eq!(interpreter.configuration(), vec!["Main", "Test2", "Test2Sub1"]);
+    assert_eq!(interpreter.evaluate("result").unwrap(), true.into());
+}
+
+#[test]
+fn test_calc_example_runs() {
+    let xml = std::fs::read_to_string("examples/calc.scxml").unwrap();
+    let scxml = parse_scxml(&xml).unwrap();
+    let mut interpreter = Interpreter::new(&scxml).unwrap();
+    interpreter.start().unwrap();
+    assert_eq!(interpreter.evaluate("short_expr").unwrap(), 0.0.into());
+
+    interpreter.send(Event::new("DIGIT.1")).unwrap();
+    interpreter.send(Event::new("DIGIT.2")).unwrap();
+    assert_eq!(interpreter.configuration(), vec!["wrapper", "on", "operand1", "int1"]);
+    assert_eq!(interpreter.evaluate("short_expr").unwrap(), "12".into());
+
+    interpreter.send(Event::new("OPER.PLUS")).unwrap();
+    assert!(interpreter.is_in_state("opEntered"));
+    interpreter.send(Event::new("DIGIT.3")).unwrap();
+    assert_eq!(interpreter.configuration(), vec!["wrapper", "on", "operand2", "int2"]);
+
+    interpreter.send(Event::new("EQUALS")).unwrap();
+    assert!(interpreter.is_in_state("result"));
+    // The example cannot compute 12 + 3 as written: `long_expr` has no initial value, so it
+    // reads as undefined, and OP.INSERT is sent to `_internal` instead of `#_internal` and reads
+    // its operator as `_event.data[0]` although it is passed as a <param>. The expression given
+    // to eval() is therefore not arithmetic, `res` is never assigned and the display shows it.
+    assert_eq!(interpreter.evaluate("short_expr").unwrap(), "undefined".into());
+
+    // With those three mistakes corrected, the chart displays the sum.
+    let fixed = xml
+        .replace(r#"<data id="long_expr" />"#, r#"<data id="long_expr" expr="''" />"#)
+        .replace(r#"target="_internal""#, r##"target="#_internal""##)
+        .replace("_event.data[0]", "_event.data.operator");
+    let mut interpreter = Interpreter::new(&parse_scxml(&fixed).unwrap()).unwrap();
+    interpreter.start().unwrap();
+    for event in ["DIGIT.1", "DIGIT.2", "OPER.PLUS", "DIGIT.3", "EQUALS"] {
+        interpreter.send(Event::new(event)).unwrap();
+    }
+    assert!(interpreter.is_in_state("result"));
+    assert_eq!(interpreter.evaluate("short_expr").unwrap(), "15".into());
+}
+
+#[test]
+fn test_blackjack_example_starts() {
+    let xml = std::fs::read_to_string("examples/blackjack.scxml").unwrap();
+    let options = ParseOptions { relaxed_namespace: true, ..Default::default() };
+    let scxml = parse_scxml_with_options(&xml, options).unwrap();
+    let mut interpreter = Interpreter::new(&scxml).unwrap();
+    interpreter.start().unwrap();
+    assert_eq!(interpreter.configuration(), vec!["master", "_home"]);
+}
+
+#[test]
+fn test_unknown_datamodel_is_rejected() {
+    let xml = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="xpath"><state id="s"/></scxml>"#;
+    let scxml = parse_scxml(xml).unwrap();
+    let error = Interpreter::new(&scxml).unwrap_err();
+    assert!(matches!(error, runtime::RuntimeError::UnsupportedDatamodel(ref name) if name == "xpath"));
+}